@@ -0,0 +1,638 @@
+use crate::{
+    memory::MemFile,
+    traits::{IndexedAccess, RawAccess},
+};
+use arrow_array::{
+    builder::BinaryBuilder, Array, ArrayRef, BinaryArray, BooleanArray, Float32Array, Float64Array,
+    GenericBinaryArray, Int16Array, Int32Array, Int64Array, Int8Array, NullArray, OffsetSizeTrait,
+    RecordBatch, RecordBatchOptions, StringArray, UInt16Array, UInt32Array, UInt64Array,
+    UInt8Array,
+};
+use arrow_buffer::{Buffer, OffsetBuffer, ScalarBuffer};
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef};
+use serde::{
+    de::DeserializeOwned,
+    ser::{self, Impossible, Serialize, SerializeStruct, Serializer},
+};
+use std::{
+    fmt::Display,
+    ops::{Bound, Range, RangeBounds},
+    sync::Arc,
+};
+
+/// Converts all entries within `range` into a record batch with a single binary column
+/// named `data`. The data of files storing their entries contiguously, like [`MemFile`] and
+/// [`MappedFile`](crate::MappedFile), is copied in bulk and only the offsets get rebased.
+pub fn to_record_batch<F, R>(file: &F, range: R) -> Result<RecordBatch, ArrowError>
+where
+    F: IndexedAccess,
+    R: RangeBounds<usize>,
+{
+    let range = resolve_range(range, file.len());
+    let array = match file.as_raw() {
+        Some(raw) => raw_binary_array(raw, range)?,
+        None => {
+            let mut builder = BinaryBuilder::new();
+            let mut data_len = 0usize;
+            for entry in file.iter_range(range) {
+                data_len += entry.len();
+                check_binary_len(data_len)?;
+                builder.append_value(entry);
+            }
+            builder.finish()
+        }
+    };
+
+    let schema = Schema::new(vec![Field::new("data", DataType::Binary, false)]);
+    RecordBatch::try_new(Arc::new(schema), vec![Arc::new(array)])
+}
+
+/// Builds a binary array from the raw data and offsets of the entries within `range`
+fn raw_binary_array(file: &dyn RawAccess, range: Range<usize>) -> Result<BinaryArray, ArrowError> {
+    let data = file.raw_data();
+    let offset = |id: usize| file.raw_offset(id).map_or(data.len(), |i| i as usize);
+    let (first, last) = (offset(range.start), offset(range.end));
+    check_binary_len(last - first)?;
+
+    let offsets: ScalarBuffer<i32> = range
+        .map(|id| (offset(id) - first) as i32)
+        .chain(std::iter::once((last - first) as i32))
+        .collect();
+    let values = Buffer::from(&data[first..last]);
+    BinaryArray::try_new(OffsetBuffer::new(offsets), values, None)
+}
+
+/// Infers the schema of typed entries within `range` from their values. Each field of `T`
+/// becomes a nullable column, typed by its first non null value within the range. Columns
+/// without any value get the type `Null`.
+///
+/// Batches should be created using a single schema, eg. inferred once from a representative
+/// range or defined by hand, so all batches of a file have the same columns and types.
+pub fn infer_schema<T, F, R>(file: &F, range: R) -> Result<SchemaRef, ArrowError>
+where
+    T: DeserializeOwned + Serialize,
+    F: IndexedAccess,
+    R: RangeBounds<usize>,
+{
+    let (names, columns) = typed_columns::<T, F, R>(file, range)?;
+    let fields: Vec<_> = names
+        .into_iter()
+        .zip(columns)
+        .map(|(name, column)| {
+            let data_type = column
+                .iter()
+                .find(|i| **i != Scalar::Null)
+                .map_or(DataType::Null, Scalar::data_type);
+            Field::new(name, data_type, true)
+        })
+        .collect();
+    Ok(Arc::new(Schema::new(fields)))
+}
+
+/// Converts all typed entries within `range` into a record batch with the given schema. The
+/// schema needs one column per field of `T` in declaration order, see [`infer_schema`].
+/// Supported fields are booleans, integers, floats, strings, byte buffers, unit enum variants
+/// and options of those. A `T` that isn't a struct has a single field named `value`.
+pub fn to_typed_record_batch<T, F, R>(
+    file: &F,
+    range: R,
+    schema: &SchemaRef,
+) -> Result<RecordBatch, ArrowError>
+where
+    T: DeserializeOwned + Serialize,
+    F: IndexedAccess,
+    R: RangeBounds<usize>,
+{
+    let range = resolve_range(range, file.len());
+    let rows = range.len();
+    let (names, columns) = typed_columns::<T, F, _>(file, range)?;
+
+    let fields = schema.fields();
+    if rows > 0
+        && (names.len() != fields.len() || names.iter().zip(fields).any(|(a, b)| a != b.name()))
+    {
+        return Err(ArrowError::SchemaError(
+            "Fields of the entries don't match the schema".to_string(),
+        ));
+    }
+
+    let mut columns = columns.into_iter();
+    let arrays = fields
+        .iter()
+        .map(|field| {
+            let column = columns.next().unwrap_or_default();
+            build_array(field.name(), column, field.data_type())
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let options = RecordBatchOptions::new().with_row_count(Some(rows));
+    RecordBatch::try_new_with_options(schema.clone(), arrays, &options)
+}
+
+/// Decodes all entries within `range` and returns the field names and the values of each
+/// column
+#[allow(clippy::type_complexity)]
+fn typed_columns<T, F, R>(
+    file: &F,
+    range: R,
+) -> Result<(Vec<&'static str>, Vec<Vec<Scalar>>), ArrowError>
+where
+    T: DeserializeOwned + Serialize,
+    F: IndexedAccess,
+    R: RangeBounds<usize>,
+{
+    let mut names: Vec<&'static str> = vec![];
+    let mut columns: Vec<Vec<Scalar>> = vec![];
+
+    for (pos, entry) in file.iter_range(range).enumerate() {
+        let item: T =
+            bincode::deserialize(entry).map_err(|e| ArrowError::ExternalError(Box::new(e)))?;
+        let row = item
+            .serialize(RowSerializer { top: true })
+            .map_err(|e| ArrowError::InvalidArgumentError(e.0))?;
+
+        if pos == 0 {
+            names = row.iter().map(|i| i.0).collect();
+            columns = vec![vec![]; names.len()];
+        } else if row.len() != names.len() || row.iter().zip(&names).any(|(a, b)| a.0 != *b) {
+            return Err(ArrowError::SchemaError(format!(
+                "Entry {pos} has different fields than the first entry"
+            )));
+        }
+
+        for (column, (_, value)) in columns.iter_mut().zip(row) {
+            column.push(value);
+        }
+    }
+    Ok((names, columns))
+}
+
+impl MemFile {
+    /// Creates a new MemFile with one entry for each value of `array`. Fails if the array
+    /// contains null values, as entries can't be null.
+    pub fn from_arrow<O: OffsetSizeTrait>(
+        array: &GenericBinaryArray<O>,
+    ) -> Result<Self, ArrowError> {
+        if array.null_count() > 0 {
+            return Err(ArrowError::InvalidArgumentError(format!(
+                "Array contains {} null values",
+                array.null_count()
+            )));
+        }
+
+        let offsets = array.value_offsets();
+        let first = offsets[0].as_usize();
+        let last = offsets[array.len()].as_usize();
+        if last - first > u32::MAX as usize {
+            return Err(ArrowError::InvalidArgumentError(
+                "Array too large for a MemFile".to_string(),
+            ));
+        }
+
+        let data = array.value_data()[first..last].to_vec();
+        let index = offsets[..array.len()]
+            .iter()
+            .map(|i| (i.as_usize() - first) as u32)
+            .collect();
+        Ok(MemFile::new_raw_unchecked(data, index))
+    }
+
+    /// Converts the file into a binary array without copying its data
+    pub fn into_arrow(self) -> Result<BinaryArray, ArrowError> {
+        check_binary_len(self.raw_len())?;
+        let (data, offsets) = self
+            .into_arrow_offsets::<i32>()
+            .map_err(|e| ArrowError::InvalidArgumentError(e.to_string()))?;
+        let offsets = OffsetBuffer::new(ScalarBuffer::from(offsets));
+        BinaryArray::try_new(offsets, Buffer::from_vec(data), None)
+    }
+}
+
+#[inline]
+fn check_binary_len(len: usize) -> Result<(), ArrowError> {
+    if len > i32::MAX as usize {
+        return Err(ArrowError::InvalidArgumentError(
+            "Entries exceed the size of a binary column".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Converts `range` into a range of IDs, clamped to the `len` entries of a file
+fn resolve_range<R: RangeBounds<usize>>(range: R, len: usize) -> Range<usize> {
+    let start = match range.start_bound() {
+        Bound::Included(start) => *start,
+        Bound::Excluded(start) => start.saturating_add(1),
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(end) => end.saturating_add(1),
+        Bound::Excluded(end) => *end,
+        Bound::Unbounded => len,
+    };
+    let end = end.min(len);
+    start.min(end)..end
+}
+
+/// A single field value of a typed entry
+#[derive(Clone, Debug, PartialEq)]
+enum Scalar {
+    Null,
+    Bool(bool),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+    Str(String),
+    Bytes(Vec<u8>),
+}
+
+impl Scalar {
+    /// Returns the arrow type of the value
+    fn data_type(&self) -> DataType {
+        match self {
+            Scalar::Null => DataType::Null,
+            Scalar::Bool(_) => DataType::Boolean,
+            Scalar::I8(_) => DataType::Int8,
+            Scalar::I16(_) => DataType::Int16,
+            Scalar::I32(_) => DataType::Int32,
+            Scalar::I64(_) => DataType::Int64,
+            Scalar::U8(_) => DataType::UInt8,
+            Scalar::U16(_) => DataType::UInt16,
+            Scalar::U32(_) => DataType::UInt32,
+            Scalar::U64(_) => DataType::UInt64,
+            Scalar::F32(_) => DataType::Float32,
+            Scalar::F64(_) => DataType::Float64,
+            Scalar::Str(_) => DataType::Utf8,
+            Scalar::Bytes(_) => DataType::Binary,
+        }
+    }
+}
+
+/// Builds the arrow array of a column with the given type
+fn build_array(
+    name: &str,
+    column: Vec<Scalar>,
+    data_type: &DataType,
+) -> Result<ArrayRef, ArrowError> {
+    macro_rules! build {
+        ($variant:ident, $array:ty) => {{
+            let values = column
+                .into_iter()
+                .map(|i| match i {
+                    Scalar::Null => Ok(None),
+                    Scalar::$variant(v) => Ok(Some(v)),
+                    _ => Err(mismatched_type(name, data_type)),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Arc::new(<$array>::from(values)) as ArrayRef)
+        }};
+    }
+
+    match data_type {
+        DataType::Null => {
+            if column.iter().any(|i| *i != Scalar::Null) {
+                return Err(mismatched_type(name, data_type));
+            }
+            Ok(Arc::new(NullArray::new(column.len())))
+        }
+        DataType::Boolean => build!(Bool, BooleanArray),
+        DataType::Int8 => build!(I8, Int8Array),
+        DataType::Int16 => build!(I16, Int16Array),
+        DataType::Int32 => build!(I32, Int32Array),
+        DataType::Int64 => build!(I64, Int64Array),
+        DataType::UInt8 => build!(U8, UInt8Array),
+        DataType::UInt16 => build!(U16, UInt16Array),
+        DataType::UInt32 => build!(U32, UInt32Array),
+        DataType::UInt64 => build!(U64, UInt64Array),
+        DataType::Float32 => build!(F32, Float32Array),
+        DataType::Float64 => build!(F64, Float64Array),
+        DataType::Utf8 => build!(Str, StringArray),
+        DataType::Binary => {
+            let values = column
+                .into_iter()
+                .map(|i| match i {
+                    Scalar::Null => Ok(None),
+                    Scalar::Bytes(v) => Ok(Some(v)),
+                    _ => Err(mismatched_type(name, data_type)),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Arc::new(BinaryArray::from_iter(values)))
+        }
+        _ => Err(ArrowError::SchemaError(format!(
+            "Unsupported type {data_type} of column '{name}'"
+        ))),
+    }
+}
+
+#[inline]
+fn mismatched_type(name: &str, data_type: &DataType) -> ArrowError {
+    ArrowError::SchemaError(format!(
+        "Values of column '{name}' are not of type {data_type}"
+    ))
+}
+
+#[derive(Debug)]
+struct SerError(String);
+
+impl Display for SerError {
+    #[inline]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for SerError {}
+
+impl ser::Error for SerError {
+    #[inline]
+    fn custom<T: Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+type Row = Vec<(&'static str, Scalar)>;
+
+/// Serializes a typed entry into its field values. Structs are only allowed at the top
+/// level, all other values end up in a single `value` field.
+struct RowSerializer {
+    top: bool,
+}
+
+impl RowSerializer {
+    #[inline]
+    fn value(scalar: Scalar) -> Result<Row, SerError> {
+        Ok(vec![("value", scalar)])
+    }
+
+    #[inline]
+    fn unsupported(kind: &str) -> SerError {
+        SerError(format!("Unsupported field type: {kind}"))
+    }
+}
+
+/// Collects the fields of a top level struct
+struct FieldSerializer {
+    row: Row,
+}
+
+impl SerializeStruct for FieldSerializer {
+    type Ok = Row;
+    type Error = SerError;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerError> {
+        let mut value = value.serialize(RowSerializer { top: false })?;
+        self.row.push((key, value.pop().unwrap().1));
+        Ok(())
+    }
+
+    #[inline]
+    fn end(self) -> Result<Row, SerError> {
+        Ok(self.row)
+    }
+}
+
+macro_rules! serialize_scalar {
+    ($($method:ident($ty:ty) => $variant:ident),* $(,)?) => {
+        $(
+            #[inline]
+            fn $method(self, v: $ty) -> Result<Row, SerError> {
+                Self::value(Scalar::$variant(v.into()))
+            }
+        )*
+    };
+}
+
+impl Serializer for RowSerializer {
+    type Ok = Row;
+    type Error = SerError;
+    type SerializeSeq = Impossible<Row, SerError>;
+    type SerializeTuple = Impossible<Row, SerError>;
+    type SerializeTupleStruct = Impossible<Row, SerError>;
+    type SerializeTupleVariant = Impossible<Row, SerError>;
+    type SerializeMap = Impossible<Row, SerError>;
+    type SerializeStruct = FieldSerializer;
+    type SerializeStructVariant = Impossible<Row, SerError>;
+
+    serialize_scalar! {
+        serialize_bool(bool) => Bool,
+        serialize_i8(i8) => I8,
+        serialize_i16(i16) => I16,
+        serialize_i32(i32) => I32,
+        serialize_i64(i64) => I64,
+        serialize_u8(u8) => U8,
+        serialize_u16(u16) => U16,
+        serialize_u32(u32) => U32,
+        serialize_u64(u64) => U64,
+        serialize_f32(f32) => F32,
+        serialize_f64(f64) => F64,
+        serialize_str(&str) => Str,
+        serialize_bytes(&[u8]) => Bytes,
+    }
+
+    #[inline]
+    fn serialize_char(self, v: char) -> Result<Row, SerError> {
+        Self::value(Scalar::Str(v.to_string()))
+    }
+
+    #[inline]
+    fn serialize_none(self) -> Result<Row, SerError> {
+        Self::value(Scalar::Null)
+    }
+
+    #[inline]
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Row, SerError> {
+        value.serialize(self)
+    }
+
+    #[inline]
+    fn serialize_unit(self) -> Result<Row, SerError> {
+        Self::value(Scalar::Null)
+    }
+
+    #[inline]
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Row, SerError> {
+        Self::value(Scalar::Null)
+    }
+
+    #[inline]
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Row, SerError> {
+        Self::value(Scalar::Str(variant.to_string()))
+    }
+
+    #[inline]
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Row, SerError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Row, SerError> {
+        Err(Self::unsupported("enum variant with data"))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, SerError> {
+        Err(Self::unsupported("sequence"))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, SerError> {
+        Err(Self::unsupported("tuple"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, SerError> {
+        Err(Self::unsupported("tuple struct"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, SerError> {
+        Err(Self::unsupported("enum variant with data"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, SerError> {
+        Err(Self::unsupported("map"))
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, SerError> {
+        if !self.top {
+            return Err(Self::unsupported("nested struct"));
+        }
+        Ok(FieldSerializer {
+            row: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, SerError> {
+        Err(Self::unsupported("enum variant with data"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traits::TypedIndexedAccessMut;
+    use arrow_array::{cast::AsArray, types::UInt32Type, LargeBinaryArray};
+
+    #[derive(serde::Serialize, serde::Deserialize)]
+    enum Kind {
+        Small,
+        Large,
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct Record {
+        id: u32,
+        name: String,
+        score: Option<f64>,
+        kind: Kind,
+    }
+
+    #[test]
+    fn test_binary_roundtrip() {
+        let file = MemFile::from(["a", "", "ccc", "dd"].iter());
+        let batch = to_record_batch(&file, 1..).unwrap();
+        assert_eq!(batch.num_rows(), 3);
+        assert_eq!(batch.schema().field(0).name(), "data");
+
+        let column = batch.column(0).as_binary::<i32>();
+        let back = MemFile::from_arrow(column).unwrap();
+        assert!(back.iter().eq(file.iter_range(1..)));
+        assert_eq!(to_record_batch(&file, 5..).unwrap().num_rows(), 0);
+
+        let array = file.clone().into_arrow().unwrap();
+        assert_eq!(array.len(), 4);
+        assert!(array.iter().map(Option::unwrap).eq(file.iter()));
+
+        // Sliced arrays, which may only contain null values outside of the slice
+        let array = LargeBinaryArray::from(vec![Some(&b"x"[..]), None, Some(b"yz"), Some(b"w")]);
+        assert!(MemFile::from_arrow(&array.slice(1, 3)).is_err());
+        let back = MemFile::from_arrow(&array.slice(2, 2)).unwrap();
+        assert_eq!(back.raw_len(), 3);
+        assert!(back.iter().eq([&b"yz"[..], b"w"]));
+    }
+
+    #[test]
+    fn test_typed() {
+        let mut file = MemFile::new();
+        for (id, name) in ["a", "b", "c"].iter().enumerate() {
+            let record = Record {
+                id: id as u32,
+                name: name.to_string(),
+                score: (id != 1).then_some(id as f64 / 2.0),
+                kind: if id == 2 { Kind::Large } else { Kind::Small },
+            };
+            file.insert_typed(&record).unwrap();
+        }
+
+        let schema = infer_schema::<Record, _, _>(&file, ..).unwrap();
+        let names: Vec<_> = schema.fields().iter().map(|i| i.name().as_str()).collect();
+        assert_eq!(names, ["id", "name", "score", "kind"]);
+        assert_eq!(schema.field(2).data_type(), &DataType::Float64);
+
+        let batch = to_typed_record_batch::<Record, _, _>(&file, .., &schema).unwrap();
+        assert_eq!(batch.schema(), schema);
+        assert_eq!(
+            batch.column(0).as_primitive::<UInt32Type>().values(),
+            &[0, 1, 2]
+        );
+        assert_eq!(batch.column(1).as_string::<i32>().value(1), "b");
+        assert!(batch.column(2).is_null(1));
+        assert_eq!(batch.column(3).as_string::<i32>().value(2), "Large");
+
+        // Batches of all null values or without entries keep the types of the schema
+        let batch = to_typed_record_batch::<Record, _, _>(&file, 1..2, &schema).unwrap();
+        assert_eq!(batch.column(2).data_type(), &DataType::Float64);
+        assert!(batch.column(2).is_null(0));
+        let batch = to_typed_record_batch::<Record, _, _>(&file, 0..0, &schema).unwrap();
+        assert_eq!(batch.num_columns(), 4);
+        assert_eq!(batch.num_rows(), 0);
+
+        let wrong = Schema::new(vec![Field::new("id", DataType::Utf8, true)]);
+        assert!(to_typed_record_batch::<Record, _, _>(&file, .., &Arc::new(wrong)).is_err());
+
+        let mut file = MemFile::new();
+        file.insert_typed(&vec![1u32]).unwrap();
+        assert!(infer_schema::<Vec<u32>, _, _>(&file, ..).is_err());
+    }
+}
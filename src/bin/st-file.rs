@@ -0,0 +1,315 @@
+use st_file::{
+    import,
+    info::{self, FileInfo, Format},
+    traits::IndexedAccess,
+    FileWriter, IndexEncoding, MappedFile,
+};
+use std::{
+    fs::File,
+    io::{stdout, BufWriter, Error, ErrorKind, Write},
+    process::ExitCode,
+};
+
+const USAGE: &str = "Usage: st-file <command> [options]
+
+Commands:
+  build <input> <output> [--input lines|nul|u32|varint] [--encoding <encoding>]
+        Builds a file from newline separated, NUL separated or u32/varint length-prefixed
+        input
+  info <file>
+        Prints the format, entry count and sizes of a file
+  get <file> <id> [--mode raw|hex|utf8|json]
+        Prints a single entry
+  dump <file> [--mode hex|utf8|json] [--start <id>] [--end <id>]
+        Prints all entries, one per line
+  verify <file>
+        Checks the structure of a file
+  convert <input> <output> [--encoding <encoding>|--legacy]
+        Converts a file into another layout
+
+Encodings: plain (default), block-packed, elias-fano";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<(), Error> {
+    let Some((command, args)) = args.split_first() else {
+        println!("{USAGE}");
+        return Ok(());
+    };
+
+    let options: &[&str] = match command.as_str() {
+        "build" => &["input", "encoding"],
+        "info" | "verify" => &[],
+        "get" => &["mode"],
+        "dump" => &["mode", "start", "end"],
+        "convert" => &["encoding", "legacy"],
+        "help" | "--help" | "-h" => {
+            println!("{USAGE}");
+            return Ok(());
+        }
+        cmd => return Err(usage(format!("Unknown command '{cmd}'"))),
+    };
+    let args = Args::parse(args, options)?;
+
+    match command.as_str() {
+        "build" => build(&args),
+        "info" => print_info(&args),
+        "get" => get(&args),
+        "dump" => dump(&args),
+        "verify" => verify(&args),
+        "convert" => convert(&args),
+        _ => unreachable!(),
+    }
+}
+
+/// Positional arguments and `--name value` options of a command
+struct Args {
+    positional: Vec<String>,
+    options: Vec<(String, Option<String>)>,
+}
+
+impl Args {
+    /// Parses the arguments of a command accepting the options `allowed`
+    fn parse(args: &[String], allowed: &[&str]) -> Result<Self, Error> {
+        let mut positional = vec![];
+        let mut options = vec![];
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.strip_prefix("--") {
+                Some(name) if !allowed.contains(&name) => {
+                    return Err(usage(format!("Unknown option --{name}")));
+                }
+                Some("legacy") => options.push(("legacy".to_string(), None)),
+                Some(name) => {
+                    let value = iter
+                        .next()
+                        .ok_or_else(|| usage(format!("Missing value for --{name}")))?;
+                    options.push((name.to_string(), Some(value.clone())));
+                }
+                None => positional.push(arg.clone()),
+            }
+        }
+        Ok(Self {
+            positional,
+            options,
+        })
+    }
+
+    fn positional(&self, pos: usize, name: &str) -> Result<&str, Error> {
+        self.positional
+            .get(pos)
+            .map(|i| i.as_str())
+            .ok_or_else(|| usage(format!("Missing argument <{name}>")))
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .find(|i| i.0 == name)
+            .and_then(|i| i.1.as_deref())
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.options.iter().any(|i| i.0 == name)
+    }
+
+    fn id_option(&self, name: &str) -> Result<Option<usize>, Error> {
+        self.option(name).map(parse_id).transpose()
+    }
+
+    fn encoding(&self) -> Result<IndexEncoding, Error> {
+        match self.option("encoding").unwrap_or("plain") {
+            "plain" => Ok(IndexEncoding::Plain),
+            "block-packed" => Ok(IndexEncoding::BlockPacked),
+            "elias-fano" => Ok(IndexEncoding::EliasFano),
+            enc => Err(usage(format!("Unknown encoding '{enc}'"))),
+        }
+    }
+}
+
+fn build(args: &Args) -> Result<(), Error> {
+    let input = args.positional(0, "input")?;
+    let output = args.positional(1, "output")?;
+    let encoding = args.encoding()?;
+
+    let input = File::open(input)?;
+    let mut file = FileWriter::create(output, encoding)?;
+    match args.option("input").unwrap_or("lines") {
+        "lines" => import::import_lines(input, &mut file)?,
+        "nul" => import::import_nul(input, &mut file)?,
+        "u32" => import::import_u32_prefixed(input, &mut file)?,
+        "varint" => import::import_varint_prefixed(input, &mut file)?,
+        kind => return Err(usage(format!("Unknown input format '{kind}'"))),
+    };
+
+    let count = file.len();
+    file.finish()?;
+    println!("Wrote {count} entries to {output}");
+    Ok(())
+}
+
+fn print_info(args: &Args) -> Result<(), Error> {
+    let info = FileInfo::load(args.positional(0, "file")?)?;
+    print_file_info(&info)
+}
+
+fn get(args: &Args) -> Result<(), Error> {
+    let file = MappedFile::open(args.positional(0, "file")?)?;
+    let id = parse_id(args.positional(1, "id")?)?;
+    let entry = file
+        .get(id)
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("No entry with ID {id}")))?;
+
+    let mut out = BufWriter::new(stdout().lock());
+    match args.option("mode").unwrap_or("raw") {
+        "raw" => out.write_all(entry)?,
+        mode => {
+            let mode = DumpMode::parse(mode)?;
+            mode.write(&mut out, id, entry)?;
+        }
+    }
+    out.flush()
+}
+
+fn dump(args: &Args) -> Result<(), Error> {
+    let file = MappedFile::open(args.positional(0, "file")?)?;
+    let mode = DumpMode::parse(args.option("mode").unwrap_or("utf8"))?;
+    let start = args.id_option("start")?.unwrap_or(0);
+    let end = args.id_option("end")?.unwrap_or(file.len()).min(file.len());
+
+    let mut out = BufWriter::new(stdout().lock());
+    for id in start..end {
+        let entry = file.get(id).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid entry with ID {id}"),
+            )
+        })?;
+        mode.write(&mut out, id, entry)?;
+    }
+    out.flush()
+}
+
+fn verify(args: &Args) -> Result<(), Error> {
+    let info = info::verify_file(args.positional(0, "file")?)?;
+    print_file_info(&info)?;
+    println!("OK");
+    Ok(())
+}
+
+fn convert(args: &Args) -> Result<(), Error> {
+    let input = args.positional(0, "input")?;
+    let output = args.positional(1, "output")?;
+    let format = if args.flag("legacy") {
+        Format::Legacy
+    } else {
+        Format::Container(args.encoding()?)
+    };
+
+    let info = FileInfo::load(input)?;
+    if format == Format::Legacy && info.sections.len() > 2 {
+        eprintln!("Warning: Additional sections are dropped in the legacy format");
+    }
+
+    let out = BufWriter::new(File::create(output)?);
+    info::convert_file(input, out, format)?;
+    println!(
+        "Converted {input} ({}) to {output}",
+        format_name(info.format)
+    );
+    Ok(())
+}
+
+/// Output format of `get` and `dump`
+#[derive(Clone, Copy)]
+enum DumpMode {
+    Hex,
+    Utf8,
+    Json,
+}
+
+impl DumpMode {
+    fn parse(mode: &str) -> Result<Self, Error> {
+        match mode {
+            "hex" => Ok(Self::Hex),
+            "utf8" => Ok(Self::Utf8),
+            "json" => Ok(Self::Json),
+            mode => Err(usage(format!("Unknown mode '{mode}'"))),
+        }
+    }
+
+    fn write<W: Write>(&self, mut w: W, id: usize, entry: &[u8]) -> Result<(), Error> {
+        match self {
+            Self::Hex => writeln!(w, "{id}\t{}", hex(entry)),
+            Self::Utf8 => {
+                let s = String::from_utf8_lossy(entry);
+                writeln!(w, "{id}\t{}", s.escape_debug())
+            }
+            Self::Json => match std::str::from_utf8(entry) {
+                Ok(s) => writeln!(w, "{{\"id\":{id},\"utf8\":\"{}\"}}", json_escape(s)),
+                Err(_) => writeln!(w, "{{\"id\":{id},\"hex\":\"{}\"}}", hex(entry)),
+            },
+        }
+    }
+}
+
+fn print_file_info(info: &FileInfo) -> Result<(), Error> {
+    let mut out = stdout().lock();
+    writeln!(out, "Format:   {}", format_name(info.format))?;
+    writeln!(out, "Entries:  {}", info.entries)?;
+    writeln!(out, "Data:     {} bytes", info.data_len)?;
+    writeln!(out, "Index:    {} bytes", info.index_len)?;
+    writeln!(out, "Sections:")?;
+    for section in info.sections.iter() {
+        writeln!(
+            out,
+            "  {:<16} offset {:>10}  len {:>10}",
+            section.name, section.offset, section.len
+        )?;
+    }
+    Ok(())
+}
+
+fn format_name(format: Format) -> String {
+    match format {
+        Format::Legacy => "legacy".to_string(),
+        Format::Container(enc) => format!("container, {enc:?} index"),
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|i| format!("{i:02x}")).collect()
+}
+
+fn json_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn parse_id(s: &str) -> Result<usize, Error> {
+    s.parse().map_err(|_| usage(format!("Invalid ID '{s}'")))
+}
+
+fn usage(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, format!("{msg}\n\n{USAGE}"))
+}
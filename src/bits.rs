@@ -0,0 +1,154 @@
+/// Growable vector of bits that allows storing and reading integers of arbitrary width
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+pub(crate) struct BitVec {
+    pub(crate) words: Vec<u64>,
+    pub(crate) len: usize,
+}
+
+impl BitVec {
+    /// Creates a new BitVec with `len` zeroed bits
+    #[inline]
+    pub fn zeroed(len: usize) -> Self {
+        Self {
+            words: vec![0; words_for(len)],
+            len,
+        }
+    }
+
+    /// Creates a BitVec from its raw words
+    #[inline]
+    pub fn from_raw(words: Vec<u64>, len: usize) -> Self {
+        debug_assert!(words.len() >= words_for(len));
+        Self { words, len }
+    }
+
+    /// Appends the lowest `width` bits of `value`
+    #[inline]
+    pub fn push_bits(&mut self, value: u64, width: u32) {
+        if width == 0 {
+            return;
+        }
+
+        let pos = self.len;
+        self.len += width as usize;
+        self.words.resize(words_for(self.len), 0);
+        self.set_bits(pos, value, width);
+    }
+
+    /// Writes the lowest `width` bits of `value` at bit position `pos`
+    #[inline]
+    pub fn set_bits(&mut self, pos: usize, value: u64, width: u32) {
+        if width == 0 {
+            return;
+        }
+
+        let value = value & mask(width);
+        let word = pos / 64;
+        let shift = (pos % 64) as u32;
+
+        self.words[word] &= !(mask(width) << shift);
+        self.words[word] |= value << shift;
+
+        if shift + width > 64 {
+            let written = 64 - shift;
+            self.words[word + 1] &= !(mask(width - written));
+            self.words[word + 1] |= value >> written;
+        }
+    }
+
+    /// Sets a single bit
+    #[inline]
+    pub fn set(&mut self, pos: usize) {
+        self.words[pos / 64] |= 1 << (pos % 64);
+    }
+
+    /// Returns `width` bits starting at bit position `pos`
+    #[inline]
+    pub fn get_bits(&self, pos: usize, width: u32) -> u64 {
+        get_bits(&self.words, pos, width)
+    }
+
+    /// Returns the amount of bits in the vector
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+}
+
+/// Reads `width` bits at bit position `pos` out of `words`
+#[inline]
+pub(crate) fn get_bits(words: &[u64], pos: usize, width: u32) -> u64 {
+    if width == 0 {
+        return 0;
+    }
+
+    let word = pos / 64;
+    let shift = (pos % 64) as u32;
+
+    let mut value = words[word] >> shift;
+    if shift + width > 64 {
+        value |= words[word + 1] << (64 - shift);
+    }
+
+    value & mask(width)
+}
+
+/// Returns the amount of bits required to represent `value`
+#[inline]
+pub(crate) fn bit_width(value: u64) -> u32 {
+    64 - value.leading_zeros()
+}
+
+/// Returns the position of the `n`th (zero based) set bit in `word`
+#[inline]
+pub(crate) fn select_in_word(mut word: u64, n: u32) -> u32 {
+    for _ in 0..n {
+        word &= word - 1;
+    }
+    word.trailing_zeros()
+}
+
+#[inline]
+fn mask(width: u32) -> u64 {
+    if width >= 64 {
+        u64::MAX
+    } else {
+        (1 << width) - 1
+    }
+}
+
+#[inline]
+fn words_for(bits: usize) -> usize {
+    bits.div_ceil(64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_get() {
+        let mut bv = BitVec::default();
+        let widths = [1, 3, 7, 13, 32, 31, 5, 64, 17];
+
+        let mut exp = vec![];
+        for (i, w) in widths.iter().cycle().take(200).enumerate() {
+            let val = (i as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) & mask(*w);
+            exp.push((bv.len(), val, *w));
+            bv.push_bits(val, *w);
+        }
+
+        for (pos, val, w) in exp {
+            assert_eq!(bv.get_bits(pos, w), val);
+        }
+    }
+
+    #[test]
+    fn test_select() {
+        let word = 0b1011_0100u64;
+        assert_eq!(select_in_word(word, 0), 2);
+        assert_eq!(select_in_word(word, 1), 4);
+        assert_eq!(select_in_word(word, 2), 5);
+        assert_eq!(select_in_word(word, 3), 7);
+    }
+}
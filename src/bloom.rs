@@ -0,0 +1,242 @@
+use crate::{
+    encoded_index::IndexEncoding,
+    format::{invalid_data, section, Layout},
+    hash::{hash64, hash64_seeded},
+    traits::{ContainerFile, IndexedAccess},
+};
+use std::{
+    fs::File,
+    io::{BufWriter, Error, Write},
+    path::Path,
+};
+
+/// hash count (4) + reserved (4) + bit count (8)
+const HEADER_LEN: usize = 16;
+
+/// Seed of the second hash used for double hashing
+const SEED: u64 = 0x5bd1_e995;
+
+/// Bloom filter over entries of a file, allowing to cheaply rule out that a file contains
+/// some bytes. Returns no false negatives but false positives with the probability the
+/// filter has been created with.
+///
+/// The filter is stored as a 16 byte header holding the amount of hash functions and bits,
+/// followed by the bits. It can be saved together with any [`ContainerFile`], like a
+/// [`MemFile`](crate::MemFile) or [`KvFile`](crate::KvFile), and used directly from the
+/// mapping using `MappedFile::bloom_filter` or `MappedKvFile::bloom_filter`.
+#[derive(Clone, Debug)]
+pub struct BloomFilter<D = Vec<u8>> {
+    bytes: D,
+    hashes: u32,
+    bits: u64,
+}
+
+impl BloomFilter<Vec<u8>> {
+    /// Creates an empty filter for `items` items with the given false positive rate
+    pub fn new(items: usize, fp_rate: f64) -> Self {
+        let fp_rate = fp_rate.clamp(f64::MIN_POSITIVE, 0.5);
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-(items.max(1) as f64) * fp_rate.ln() / (ln2 * ln2)).ceil() as u64;
+        let bits = bits.max(64);
+        let hashes = ((bits as f64 / items.max(1) as f64) * ln2).round() as u32;
+        Self::with_params(bits, hashes.clamp(1, 30))
+    }
+
+    /// Creates an empty filter with `bits` bits and `hashes` hash functions
+    pub fn with_params(bits: u64, hashes: u32) -> Self {
+        let bits = bits.max(1);
+        let hashes = hashes.max(1);
+
+        let mut bytes = vec![0u8; HEADER_LEN + bits.div_ceil(8) as usize];
+        bytes[..4].copy_from_slice(&hashes.to_le_bytes());
+        bytes[8..16].copy_from_slice(&bits.to_le_bytes());
+        Self {
+            bytes,
+            hashes,
+            bits,
+        }
+    }
+
+    /// Builds a filter over the contents of all entries of `file`
+    pub fn build<F: IndexedAccess>(file: &F, fp_rate: f64) -> Self {
+        let mut filter = Self::new(file.len(), fp_rate);
+        for entry in file.iter() {
+            filter.insert(entry);
+        }
+        filter
+    }
+
+    /// Builds a filter over keys extracted from each entry of `file`. Entries for which
+    /// `extract` returns `None` are not added.
+    pub fn build_with<F, E, K>(file: &F, fp_rate: f64, mut extract: E) -> Self
+    where
+        F: IndexedAccess,
+        E: FnMut(&[u8]) -> Option<K>,
+        K: AsRef<[u8]>,
+    {
+        let mut filter = Self::new(file.len(), fp_rate);
+        for entry in file.iter() {
+            if let Some(key) = extract(entry) {
+                filter.insert(key.as_ref());
+            }
+        }
+        filter
+    }
+
+    /// Adds `data` to the filter
+    pub fn insert(&mut self, data: &[u8]) {
+        for bit in bit_positions(data, self.hashes, self.bits) {
+            self.bytes[HEADER_LEN + (bit / 8) as usize] |= 1 << (bit % 8);
+        }
+    }
+
+    /// Saves `file` together with the filter
+    pub fn save_with<F: ContainerFile, P: AsRef<Path>>(
+        &self,
+        file: &F,
+        path: P,
+        encoding: IndexEncoding,
+    ) -> Result<(), Error> {
+        let out = BufWriter::new(File::create(path)?);
+        self.write_with(file, out, encoding)
+    }
+
+    /// Writes `file` together with the filter
+    #[inline]
+    pub fn write_with<F: ContainerFile, W: Write>(
+        &self,
+        file: &F,
+        w: W,
+        encoding: IndexEncoding,
+    ) -> Result<(), Error> {
+        file.write_with_sections(w, encoding, &[(section::BLOOM, &self.bytes)])
+    }
+
+    /// Loads the filter of a file saved with `BloomFilter::save_with`. Returns `None` if the
+    /// file doesn't contain a filter.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Option<Self>, Error> {
+        let bytes = std::fs::read(path)?;
+        let layout = Layout::parse(&bytes)?;
+        let Some(filter) = layout.section(section::BLOOM) else {
+            return Ok(None);
+        };
+        Ok(Some(Self::from_bytes(bytes[filter.range()].to_vec())?))
+    }
+}
+
+impl<D: AsRef<[u8]>> BloomFilter<D> {
+    /// Creates a filter from its encoding
+    pub fn from_bytes(bytes: D) -> Result<Self, Error> {
+        let buf = bytes.as_ref();
+        if buf.len() < HEADER_LEN {
+            return Err(invalid_data("Invalid bloom filter length"));
+        }
+
+        let hashes = u32::from_le_bytes(buf[..4].try_into().unwrap());
+        let bits = u64::from_le_bytes(buf[8..16].try_into().unwrap());
+        if hashes == 0 || bits == 0 || (buf.len() - HEADER_LEN) as u64 != bits.div_ceil(8) {
+            return Err(invalid_data("Invalid bloom filter"));
+        }
+
+        Ok(Self {
+            bytes,
+            hashes,
+            bits,
+        })
+    }
+
+    /// Returns `false` if `data` has definitely not been added to the filter
+    #[inline]
+    pub fn may_contain(&self, data: &[u8]) -> bool {
+        let bytes = &self.bytes.as_ref()[HEADER_LEN..];
+        bit_positions(data, self.hashes, self.bits)
+            .all(|bit| bytes[(bit / 8) as usize] & (1 << (bit % 8)) != 0)
+    }
+
+    /// Returns the encoded filter
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        self.bytes.as_ref()
+    }
+
+    /// Returns the amount of bits in the filter
+    #[inline]
+    pub fn bits(&self) -> u64 {
+        self.bits
+    }
+
+    /// Returns the amount of hash functions used
+    #[inline]
+    pub fn hashes(&self) -> u32 {
+        self.hashes
+    }
+}
+
+/// Returns the bits to set for `data` using double hashing
+#[inline]
+fn bit_positions(data: &[u8], hashes: u32, bits: u64) -> impl Iterator<Item = u64> {
+    let h1 = hash64(data);
+    let h2 = hash64_seeded(data, SEED) | 1;
+    (0..hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MemFile;
+    use std::fs::read_to_string;
+
+    fn lines() -> MemFile {
+        let content = read_to_string("./testfiles/LICENSE").unwrap();
+        MemFile::from(content.lines())
+    }
+
+    #[test]
+    fn test_may_contain() {
+        let file = lines();
+        let filter = BloomFilter::build(&file, 0.01);
+        assert!(file.iter().all(|i| filter.may_contain(i)));
+
+        let false_positives = (0..10_000)
+            .filter(|i| filter.may_contain(format!("not in the file {i}").as_bytes()))
+            .count();
+        assert!(false_positives < 300, "{false_positives}");
+
+        let filter = BloomFilter::build_with(&file, 0.01, |i| i.get(..4).map(|i| i.to_vec()));
+        assert!(filter.may_contain(b"GNU "));
+        assert!(!filter.may_contain(b"GNU GENERAL PUBLIC LICENSE"));
+    }
+
+    #[test]
+    fn test_save_load() {
+        let file = lines();
+        let filter = BloomFilter::build(&file, 0.001);
+        filter
+            .save_with(&file, "test_bloom_save_load", IndexEncoding::Plain)
+            .unwrap();
+
+        let loaded = BloomFilter::load("test_bloom_save_load").unwrap().unwrap();
+        assert_eq!(loaded.as_bytes(), filter.as_bytes());
+        assert!(file.iter().all(|i| loaded.may_contain(i)));
+        assert!(MemFile::load("test_bloom_save_load")
+            .unwrap()
+            .iter()
+            .eq(file.iter()));
+
+        #[cfg(feature = "mapped")]
+        {
+            let mapped = crate::MappedFile::open("test_bloom_save_load").unwrap();
+            let mapped_filter = mapped.bloom_filter().unwrap().unwrap();
+            assert_eq!(mapped_filter.hashes(), filter.hashes());
+            assert!(mapped.iter().all(|i| mapped_filter.may_contain(i)));
+        }
+
+        file.save("test_bloom_save_load", IndexEncoding::Plain)
+            .unwrap();
+        assert!(BloomFilter::load("test_bloom_save_load").unwrap().is_none());
+
+        std::fs::remove_file("test_bloom_save_load").unwrap();
+
+        assert!(BloomFilter::from_bytes(&filter.as_bytes()[..20]).is_err());
+    }
+}
@@ -0,0 +1,348 @@
+use crate::{
+    format::invalid_data,
+    hash::hash64,
+    memory::MemFile,
+    traits::{IndexedAccess, IndexedAccessMut},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, io::Error, ops::Index};
+
+/// An in-memory file that stores byte-identical entries only once. Every inserted entry gets
+/// its own ID, but entries with the same content share the same data.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(try_from = "RawDedupFile")]
+pub struct DedupFile {
+    /// Unique contents
+    store: MemFile,
+    /// Maps the ID of an entry to the ID of its content in `store`
+    refs: Vec<u32>,
+
+    /// Maps content hashes to the ID of the content in `store`
+    #[serde(skip)]
+    table: HashMap<u64, u32>,
+    /// Amount of entries referencing each content
+    #[serde(skip)]
+    ref_counts: Vec<u32>,
+    /// Sum of the lengths of all entries
+    #[serde(skip)]
+    logical_bytes: usize,
+}
+
+/// Deduplication statistics of a [`DedupFile`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DedupStats {
+    /// Amount of entries in the file
+    pub entries: usize,
+    /// Amount of distinct contents that are referenced by at least one entry
+    pub unique_entries: usize,
+    /// Sum of the lengths of all entries
+    pub logical_bytes: usize,
+    /// Amount of bytes actually stored, including unreferenced contents
+    pub stored_bytes: usize,
+    /// Amount of stored bytes that are not referenced anymore and can be freed with `compact`
+    pub unreferenced_bytes: usize,
+}
+
+impl DedupStats {
+    /// Returns the amount of bytes saved by deduplication
+    #[inline]
+    pub fn saved_bytes(&self) -> usize {
+        self.logical_bytes.saturating_sub(self.stored_bytes)
+    }
+}
+
+impl DedupFile {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns deduplication statistics of the file
+    pub fn stats(&self) -> DedupStats {
+        let (unique_entries, unreferenced_bytes) = self.ref_counts.iter().enumerate().fold(
+            (0, 0),
+            |(unique, unreferenced), (id, count)| {
+                if *count > 0 {
+                    (unique + 1, unreferenced)
+                } else {
+                    (unique, unreferenced + self.store.get_unchecked(id).len())
+                }
+            },
+        );
+
+        DedupStats {
+            entries: self.len(),
+            unique_entries,
+            logical_bytes: self.logical_bytes,
+            stored_bytes: self.store.raw_len(),
+            unreferenced_bytes,
+        }
+    }
+
+    /// Removes all contents that aren't referenced by any entry anymore. IDs of entries
+    /// stay the same.
+    pub fn compact(&mut self) {
+        let mut new_store = MemFile::with_capacity(self.store.raw_len());
+        let mut new_ids = vec![u32::MAX; self.store.len()];
+
+        for (id, count) in self.ref_counts.iter().enumerate() {
+            if *count > 0 {
+                new_ids[id] = new_store.insert(self.store.get_unchecked(id)) as u32;
+            }
+        }
+
+        for content in self.refs.iter_mut() {
+            *content = new_ids[*content as usize];
+        }
+
+        self.store = new_store;
+        self.rebuild();
+    }
+
+    /// Returns the ID of the content that equals `data`, inserting it if not stored yet
+    fn content_id(&mut self, data: &[u8]) -> u32 {
+        let hash = hash64(data);
+
+        if let Some(id) = self.table.get(&hash) {
+            if self.store.get_unchecked(*id as usize) == data {
+                return *id;
+            }
+
+            // Hash collision with different content. Don't deduplicate this one.
+            return self.insert_content(data);
+        }
+
+        let id = self.insert_content(data);
+        self.table.insert(hash, id);
+        id
+    }
+
+    #[inline]
+    fn insert_content(&mut self, data: &[u8]) -> u32 {
+        self.ref_counts.push(0);
+        let id = self.store.insert(data);
+        u32::try_from(id).expect("Too many distinct entries")
+    }
+
+    /// Rebuilds all state that is derived from `store` and `refs`
+    fn rebuild(&mut self) {
+        self.ref_counts = vec![0; self.store.len()];
+        self.logical_bytes = 0;
+        for content in self.refs.iter() {
+            self.ref_counts[*content as usize] += 1;
+            self.logical_bytes += self.store.get_unchecked(*content as usize).len();
+        }
+
+        self.table.clear();
+        for (id, count) in self.ref_counts.iter().enumerate() {
+            if *count > 0 {
+                let hash = hash64(self.store.get_unchecked(id));
+                self.table.entry(hash).or_insert(id as u32);
+            }
+        }
+    }
+}
+
+impl IndexedAccessMut for DedupFile {
+    #[inline]
+    fn insert(&mut self, data: &[u8]) -> usize {
+        let content = self.content_id(data);
+        self.ref_counts[content as usize] += 1;
+        self.logical_bytes += data.len();
+
+        let id = self.refs.len();
+        self.refs.push(content);
+        id
+    }
+
+    fn replace(&mut self, pos: usize, data: &[u8]) -> Option<()> {
+        let old = *self.refs.get(pos)?;
+        let old_data = self.store.get_unchecked(old as usize);
+        if old_data == data {
+            return Some(());
+        }
+
+        self.logical_bytes = self.logical_bytes - old_data.len() + data.len();
+        let old_hash = hash64(old_data);
+        self.ref_counts[old as usize] -= 1;
+
+        let new_hash = hash64(data);
+        let is_stored = self.table.contains_key(&new_hash);
+
+        // The old content isn't shared with other entries so it can be overwritten in place
+        if self.ref_counts[old as usize] == 0 && !is_stored {
+            if self.table.get(&old_hash) == Some(&old) {
+                self.table.remove(&old_hash);
+            }
+            self.store.replace(old as usize, data)?;
+            self.table.insert(new_hash, old);
+            self.ref_counts[old as usize] += 1;
+            return Some(());
+        }
+
+        let content = self.content_id(data);
+        self.ref_counts[content as usize] += 1;
+        self.refs[pos] = content;
+        Some(())
+    }
+}
+
+impl IndexedAccess for DedupFile {
+    #[inline]
+    fn get(&self, pos: usize) -> Option<&[u8]> {
+        self.store.get(*self.refs.get(pos)? as usize)
+    }
+
+    #[inline]
+    fn get_unchecked(&self, pos: usize) -> &[u8] {
+        self.store.get_unchecked(self.refs[pos] as usize)
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.refs.len()
+    }
+}
+
+impl Index<usize> for DedupFile {
+    type Output = [u8];
+
+    #[inline]
+    fn index(&self, index: usize) -> &Self::Output {
+        self.get_unchecked(index)
+    }
+}
+
+impl<I: AsRef<[u8]>> Extend<I> for DedupFile {
+    #[inline]
+    fn extend<T: IntoIterator<Item = I>>(&mut self, iter: T) {
+        for line in iter {
+            self.insert(line.as_ref());
+        }
+    }
+}
+
+/// Serialized representation of a [`DedupFile`]
+#[derive(Deserialize)]
+struct RawDedupFile {
+    store: MemFile,
+    refs: Vec<u32>,
+}
+
+impl TryFrom<RawDedupFile> for DedupFile {
+    type Error = Error;
+
+    fn try_from(raw: RawDedupFile) -> Result<Self, Self::Error> {
+        let contents = raw.store.len();
+        let invalid = raw
+            .refs
+            .iter()
+            .position(|i| usize::try_from(*i).map_or(true, |i| i >= contents));
+        if let Some(id) = invalid {
+            return Err(invalid_data(format!(
+                "Entry {id} references missing content {}",
+                raw.refs[id]
+            )));
+        }
+
+        let mut file = DedupFile {
+            store: raw.store,
+            refs: raw.refs,
+            ..Default::default()
+        };
+        file.rebuild();
+        Ok(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::read_to_string;
+
+    #[test]
+    fn test_dedup() {
+        let content = read_to_string("./testfiles/LICENSE").unwrap();
+        let split: Vec<_> = content.split(' ').collect();
+
+        let mut file = DedupFile::new();
+        file.extend(split.iter());
+
+        assert_eq!(file.len(), split.len());
+        for (id, exp) in split.iter().enumerate() {
+            assert_eq!(file.get(id).unwrap(), exp.as_bytes());
+        }
+
+        let stats = file.stats();
+        assert_eq!(stats.entries, split.len());
+        assert_eq!(
+            stats.logical_bytes,
+            split.iter().map(|i| i.len()).sum::<usize>()
+        );
+        assert!(stats.unique_entries < split.len() / 2);
+        assert!(stats.saved_bytes() > stats.stored_bytes);
+        assert_eq!(stats.unreferenced_bytes, 0);
+    }
+
+    #[test]
+    fn test_replace_shared() {
+        let mut file = DedupFile::new();
+        let a = file.insert(b"shared");
+        let b = file.insert(b"shared");
+        let c = file.insert(b"other");
+        assert_eq!(file.stats().unique_entries, 2);
+
+        // Replacing a shared entry must not change the others
+        file.replace(a, b"new").unwrap();
+        assert_eq!(file.get(a), Some(&b"new"[..]));
+        assert_eq!(file.get(b), Some(&b"shared"[..]));
+        assert_eq!(file.get(c), Some(&b"other"[..]));
+
+        // Exclusive content gets replaced in place
+        let stored = file.stats().stored_bytes;
+        file.replace(c, b"otter").unwrap();
+        assert_eq!(file.get(c), Some(&b"otter"[..]));
+        assert_eq!(file.stats().stored_bytes, stored);
+
+        // Replace with content that already exists
+        file.replace(b, b"otter").unwrap();
+        assert_eq!(file.get(b), Some(&b"otter"[..]));
+        let stats = file.stats();
+        assert_eq!(stats.unique_entries, 2);
+        assert_eq!(stats.unreferenced_bytes, "shared".len());
+
+        file.compact();
+        let stats = file.stats();
+        assert_eq!(stats.unreferenced_bytes, 0);
+        assert_eq!(stats.stored_bytes, "new".len() + "otter".len());
+        assert_eq!(file.get(a), Some(&b"new"[..]));
+        assert_eq!(file.get(b), Some(&b"otter"[..]));
+        assert_eq!(file.get(c), Some(&b"otter"[..]));
+
+        // Dedup still works after compacting
+        let d = file.insert(b"new");
+        assert_eq!(file.get(d), Some(&b"new"[..]));
+        assert_eq!(file.stats().stored_bytes, stats.stored_bytes);
+    }
+
+    #[cfg(feature = "typed")]
+    #[test]
+    fn test_serde() {
+        let mut file = DedupFile::new();
+        file.extend(["a", "b", "a", "c", "a"]);
+
+        let enc = bincode::serialize(&file).unwrap();
+        let mut decoded: DedupFile = bincode::deserialize(&enc).unwrap();
+        assert_eq!(decoded.stats(), file.stats());
+        assert!(decoded.iter().eq(file.iter()));
+
+        decoded.insert(b"b");
+        assert_eq!(decoded.stats().stored_bytes, file.stats().stored_bytes);
+
+        // References to contents that don't exist
+        let mut invalid = file.clone();
+        invalid.refs[3] = invalid.store.len() as u32;
+        let enc = bincode::serialize(&invalid).unwrap();
+        assert!(bincode::deserialize::<DedupFile>(&enc).is_err());
+    }
+}
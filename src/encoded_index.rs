@@ -0,0 +1,586 @@
+use crate::{
+    bits::{bit_width, select_in_word, BitVec},
+    format::{invalid_data, section, write_u32s, write_u64s, ByteReader, ContainerWriter},
+    mem_index::MemIndex,
+    traits::OffsetIndex,
+};
+use std::io::{Error, ErrorKind, Write};
+
+/// Amount of offsets stored in a single block of a [`BlockPackedIndex`]
+const BLOCK_SIZE: usize = 128;
+
+/// Every n-th set bit in the upper bits of an [`EliasFanoIndex`] gets its position sampled
+const SAMPLE_RATE: usize = 64;
+
+/// Encoding of the offset index within a saved file
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum IndexEncoding {
+    /// Uncompressed little endian u32 values
+    #[default]
+    Plain,
+    /// Offsets split into blocks, each stored bit-packed relative to the blocks smallest value
+    BlockPacked,
+    /// Elias-Fano encoded offsets
+    EliasFano,
+}
+
+impl IndexEncoding {
+    /// Returns the encoding for a given section parameter
+    #[inline]
+    pub(crate) fn from_param(param: u32) -> Option<Self> {
+        Some(match param {
+            0 => Self::Plain,
+            1 => Self::BlockPacked,
+            2 => Self::EliasFano,
+            _ => return None,
+        })
+    }
+
+    /// Returns the section parameter of the encoding
+    #[inline]
+    pub(crate) fn param(&self) -> u32 {
+        match self {
+            Self::Plain => 0,
+            Self::BlockPacked => 1,
+            Self::EliasFano => 2,
+        }
+    }
+}
+
+#[inline]
+fn is_monotonic(offsets: &[u32]) -> bool {
+    offsets.windows(2).all(|w| w[0] <= w[1])
+}
+
+fn not_monotonic() -> Error {
+    Error::new(ErrorKind::InvalidInput, "Offsets are not monotonic")
+}
+
+#[inline]
+fn check_monotonic(offsets: &[u32]) -> Result<(), Error> {
+    if is_monotonic(offsets) {
+        Ok(())
+    } else {
+        Err(not_monotonic())
+    }
+}
+
+/// An offset index in any of the available encodings
+#[derive(Clone, Debug)]
+pub enum EncodedIndex {
+    Plain(MemIndex),
+    BlockPacked(BlockPackedIndex),
+    EliasFano(EliasFanoIndex),
+}
+
+impl EncodedIndex {
+    /// Encodes the given offsets. Fails if the offsets are not monotonically increasing.
+    pub fn encode(offsets: &[u32], encoding: IndexEncoding) -> Result<Self, Error> {
+        check_monotonic(offsets)?;
+        Ok(match encoding {
+            IndexEncoding::Plain => Self::Plain(MemIndex::from(offsets.to_vec())),
+            IndexEncoding::BlockPacked => {
+                Self::BlockPacked(BlockPackedIndex::new(offsets).ok_or_else(not_monotonic)?)
+            }
+            IndexEncoding::EliasFano => {
+                Self::EliasFano(EliasFanoIndex::new(offsets).ok_or_else(not_monotonic)?)
+            }
+        })
+    }
+
+    /// Encodes `offsets` and writes them as index section. Plain offsets are written directly
+    /// without encoding them into a copy first.
+    pub(crate) fn write_section<W: Write>(
+        writer: &mut ContainerWriter<W>,
+        offsets: &[u32],
+        encoding: IndexEncoding,
+    ) -> Result<(), Error> {
+        if encoding == IndexEncoding::Plain {
+            check_monotonic(offsets)?;
+            return writer.write_u32_section(section::INDEX, encoding.param(), offsets);
+        }
+
+        let mut buf = vec![];
+        Self::encode(offsets, encoding)?.write_to(&mut buf)?;
+        writer.write_section(section::INDEX, encoding.param(), &buf)
+    }
+
+    /// Returns the encoding of the index
+    #[inline]
+    pub fn encoding(&self) -> IndexEncoding {
+        match self {
+            EncodedIndex::Plain(_) => IndexEncoding::Plain,
+            EncodedIndex::BlockPacked(_) => IndexEncoding::BlockPacked,
+            EncodedIndex::EliasFano(_) => IndexEncoding::EliasFano,
+        }
+    }
+
+    /// Writes the encoded index
+    pub(crate) fn write_to<W: Write>(&self, w: W) -> Result<(), Error> {
+        match self {
+            EncodedIndex::Plain(index) => write_u32s(w, &index.inner),
+            EncodedIndex::BlockPacked(index) => index.write_to(w),
+            EncodedIndex::EliasFano(index) => index.write_to(w),
+        }
+    }
+
+    /// Decodes an index that has been written using `write_to`
+    pub(crate) fn decode(encoding: IndexEncoding, bytes: &[u8]) -> Result<Self, Error> {
+        Ok(match encoding {
+            IndexEncoding::Plain => {
+                if !bytes.len().is_multiple_of(4) {
+                    return Err(invalid_data("Invalid plain index length"));
+                }
+                let mut reader = ByteReader::new(bytes);
+                Self::Plain(MemIndex::from(reader.u32_vec(bytes.len() / 4)?))
+            }
+            IndexEncoding::BlockPacked => Self::BlockPacked(BlockPackedIndex::decode(bytes)?),
+            IndexEncoding::EliasFano => Self::EliasFano(EliasFanoIndex::decode(bytes)?),
+        })
+    }
+}
+
+impl OffsetIndex for EncodedIndex {
+    #[inline]
+    fn offset(&self, id: usize) -> Option<u32> {
+        match self {
+            EncodedIndex::Plain(index) => index.get(id),
+            EncodedIndex::BlockPacked(index) => index.offset(id),
+            EncodedIndex::EliasFano(index) => index.offset(id),
+        }
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        match self {
+            EncodedIndex::Plain(index) => index.len(),
+            EncodedIndex::BlockPacked(index) => index.len(),
+            EncodedIndex::EliasFano(index) => index.len(),
+        }
+    }
+
+    #[inline]
+    fn heap_size(&self) -> usize {
+        match self {
+            EncodedIndex::Plain(index) => index.heap_size(),
+            EncodedIndex::BlockPacked(index) => index.heap_size(),
+            EncodedIndex::EliasFano(index) => index.heap_size(),
+        }
+    }
+}
+
+/// Offset index that splits offsets into fixed size blocks and stores each offset bit-packed
+/// as delta to the smallest offset of its block. Allows O(1) random access.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlockPackedIndex {
+    len: usize,
+    bases: Vec<u32>,
+    widths: Vec<u8>,
+    bit_starts: Vec<u64>,
+    bits: BitVec,
+}
+
+impl BlockPackedIndex {
+    /// Creates a new BlockPackedIndex. Returns `None` if `offsets` are not monotonically increasing
+    pub fn new(offsets: &[u32]) -> Option<Self> {
+        if !is_monotonic(offsets) {
+            return None;
+        }
+
+        let mut index = Self {
+            len: offsets.len(),
+            ..Self::default()
+        };
+
+        for block in offsets.chunks(BLOCK_SIZE) {
+            let base = *block.iter().min().unwrap();
+            let max = *block.iter().max().unwrap();
+            let width = bit_width((max - base) as u64);
+
+            index.bases.push(base);
+            index.widths.push(width as u8);
+            index.bit_starts.push(index.bits.len() as u64);
+
+            for offset in block {
+                index.bits.push_bits((offset - base) as u64, width);
+            }
+        }
+
+        index.bits.words.shrink_to_fit();
+        Some(index)
+    }
+
+    #[inline]
+    fn write_to<W: Write>(&self, mut w: W) -> Result<(), Error> {
+        let header = [
+            self.len as u64,
+            self.bases.len() as u64,
+            self.bits.len() as u64,
+            self.bits.words.len() as u64,
+        ];
+        write_u64s(&mut w, &header)?;
+        write_u64s(&mut w, &self.bits.words)?;
+        write_u64s(&mut w, &self.bit_starts)?;
+        write_u32s(&mut w, &self.bases)?;
+        w.write_all(&self.widths)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = ByteReader::new(bytes);
+        let len = reader.u64()? as usize;
+        let blocks = reader.u64()? as usize;
+        let bit_len = reader.u64()? as usize;
+        let word_count = reader.u64()? as usize;
+
+        let words = reader.u64_vec(word_count)?;
+        let bit_starts = reader.u64_vec(blocks)?;
+        let bases = reader.u32_vec(blocks)?;
+        let widths = reader.bytes(blocks)?.to_vec();
+
+        if blocks != len.div_ceil(BLOCK_SIZE) || words.len() != bit_len.div_ceil(64) {
+            return Err(invalid_data("Invalid block packed index"));
+        }
+
+        for (block, (start, width)) in bit_starts.iter().zip(widths.iter()).enumerate() {
+            let items = (len - block * BLOCK_SIZE).min(BLOCK_SIZE) as u64;
+            let end = items
+                .checked_mul(*width as u64)
+                .and_then(|i| i.checked_add(*start));
+            if *width > 32 || end.is_none_or(|end| end > bit_len as u64) {
+                return Err(invalid_data("Invalid block in block packed index"));
+            }
+        }
+
+        let index = Self {
+            len,
+            bases,
+            widths,
+            bit_starts,
+            bits: BitVec::from_raw(words, bit_len),
+        };
+
+        let mut last = 0;
+        for id in 0..len {
+            let offset = index
+                .offset(id)
+                .ok_or_else(|| invalid_data("Invalid offset in block packed index"))?;
+            if offset < last {
+                return Err(invalid_data("Block packed offsets are not monotonic"));
+            }
+            last = offset;
+        }
+
+        Ok(index)
+    }
+}
+
+impl OffsetIndex for BlockPackedIndex {
+    #[inline]
+    fn offset(&self, id: usize) -> Option<u32> {
+        if id >= self.len {
+            return None;
+        }
+
+        let block = id / BLOCK_SIZE;
+        let width = self.widths[block] as u32;
+        let pos = self.bit_starts[block] as usize + (id % BLOCK_SIZE) * width as usize;
+        let delta = self.bits.get_bits(pos, width) as u32;
+
+        self.bases[block].checked_add(delta)
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    fn heap_size(&self) -> usize {
+        self.bases.capacity() * 4
+            + self.widths.capacity()
+            + self.bit_starts.capacity() * 8
+            + self.bits.words.capacity() * 8
+    }
+}
+
+/// Elias-Fano encoded offset index. Needs about 2 + log(data_len / entries) bits per entry
+/// and allows near O(1) random access by sampling the positions of the upper bits.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EliasFanoIndex {
+    len: usize,
+    low_bits: u32,
+    lower: BitVec,
+    upper: BitVec,
+    samples: Vec<u64>,
+}
+
+impl EliasFanoIndex {
+    /// Creates a new EliasFanoIndex. Returns `None` if `offsets` are not monotonically increasing
+    pub fn new(offsets: &[u32]) -> Option<Self> {
+        if !is_monotonic(offsets) {
+            return None;
+        }
+
+        let len = offsets.len();
+        let universe = offsets.last().map(|i| *i as u64 + 1).unwrap_or(0);
+
+        let low_bits = if len > 0 && universe > len as u64 {
+            bit_width(universe / len as u64) - 1
+        } else {
+            0
+        };
+
+        let upper_len = offsets.last().map(|i| (*i as usize >> low_bits) + len + 1);
+
+        let mut index = Self {
+            len,
+            low_bits,
+            upper: BitVec::zeroed(upper_len.unwrap_or(0)),
+            ..Self::default()
+        };
+
+        for (i, offset) in offsets.iter().enumerate() {
+            index.lower.push_bits(*offset as u64, low_bits);
+
+            let pos = (*offset as usize >> low_bits) + i;
+            index.upper.set(pos);
+
+            if i % SAMPLE_RATE == 0 {
+                index.samples.push(pos as u64);
+            }
+        }
+
+        index.lower.words.shrink_to_fit();
+        index.samples.shrink_to_fit();
+        Some(index)
+    }
+
+    /// Returns the position of the `n`th set bit in the upper bits
+    #[inline]
+    fn select(&self, n: usize) -> usize {
+        let start = self.samples[n / SAMPLE_RATE] as usize;
+        let mut remaining = (n % SAMPLE_RATE) as u32;
+
+        let mut word_pos = start / 64;
+        let mut word = self.upper.words[word_pos] & (u64::MAX << (start % 64));
+
+        loop {
+            let ones = word.count_ones();
+            if remaining < ones {
+                return word_pos * 64 + select_in_word(word, remaining) as usize;
+            }
+            remaining -= ones;
+            word_pos += 1;
+            word = self.upper.words[word_pos];
+        }
+    }
+
+    #[inline]
+    fn write_to<W: Write>(&self, mut w: W) -> Result<(), Error> {
+        let header = [
+            self.len as u64,
+            self.low_bits as u64,
+            self.lower.len() as u64,
+            self.lower.words.len() as u64,
+            self.upper.len() as u64,
+            self.upper.words.len() as u64,
+            self.samples.len() as u64,
+        ];
+        write_u64s(&mut w, &header)?;
+        write_u64s(&mut w, &self.lower.words)?;
+        write_u64s(&mut w, &self.upper.words)?;
+        write_u64s(&mut w, &self.samples)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = ByteReader::new(bytes);
+        let len = reader.u64()? as usize;
+        let low_bits = reader.u64()? as u32;
+        let lower_len = reader.u64()? as usize;
+        let lower_words = reader.u64()? as usize;
+        let upper_len = reader.u64()? as usize;
+        let upper_words = reader.u64()? as usize;
+        let sample_count = reader.u64()? as usize;
+
+        let lower = reader.u64_vec(lower_words)?;
+        let upper = reader.u64_vec(upper_words)?;
+        let samples = reader.u64_vec(sample_count)?;
+
+        let valid = low_bits <= 32
+            && Some(lower_len) == len.checked_mul(low_bits as usize)
+            && lower.len() == lower_len.div_ceil(64)
+            && upper.len() == upper_len.div_ceil(64)
+            && samples.len() == len.div_ceil(SAMPLE_RATE)
+            && upper.iter().map(|i| i.count_ones() as usize).sum::<usize>() == len
+            && (upper_len.is_multiple_of(64)
+                || upper.last().is_none_or(|i| i >> (upper_len % 64) == 0));
+
+        // Samples are used for unchecked lookups in the upper bits and must point at the
+        // sampled set bits
+        if !valid || samples != sample_positions(&upper, len) {
+            return Err(invalid_data("Invalid Elias-Fano index"));
+        }
+
+        Ok(Self {
+            len,
+            low_bits,
+            lower: BitVec::from_raw(lower, lower_len),
+            upper: BitVec::from_raw(upper, upper_len),
+            samples,
+        })
+    }
+}
+
+impl OffsetIndex for EliasFanoIndex {
+    #[inline]
+    fn offset(&self, id: usize) -> Option<u32> {
+        if id >= self.len {
+            return None;
+        }
+
+        let high = self.select(id).checked_sub(id)? as u64;
+        let low = self
+            .lower
+            .get_bits(id * self.low_bits as usize, self.low_bits);
+
+        u32::try_from((high << self.low_bits) | low).ok()
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    fn heap_size(&self) -> usize {
+        self.lower.words.capacity() * 8
+            + self.upper.words.capacity() * 8
+            + self.samples.capacity() * 8
+    }
+}
+
+/// Returns the position of every `SAMPLE_RATE`-th set bit within the first `len` set bits
+fn sample_positions(words: &[u64], len: usize) -> Vec<u64> {
+    let mut samples = Vec::with_capacity(len.div_ceil(SAMPLE_RATE));
+    let mut seen = 0;
+    for (pos, word) in words.iter().enumerate() {
+        let mut word = *word;
+        while word != 0 && seen < len {
+            if seen % SAMPLE_RATE == 0 {
+                samples.push((pos * 64) as u64 + word.trailing_zeros() as u64);
+            }
+            seen += 1;
+            word &= word - 1;
+        }
+    }
+    samples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_offsets() -> Vec<Vec<u32>> {
+        let mut sets = vec![vec![], vec![0], vec![0, 0, 0], vec![5, 17, 17, 300]];
+
+        // Variable sized entries including some large gaps
+        let mut offset = 0u32;
+        let mut offsets = vec![];
+        for i in 0..10_000u32 {
+            offsets.push(offset);
+            offset += i.wrapping_mul(2_654_435_761) % 97;
+            if i % 1000 == 999 {
+                offset += 1 << 20;
+            }
+        }
+        sets.push(offsets);
+
+        sets.push((0..1000).map(|i| i * 4).collect());
+        sets.push(vec![0, u32::MAX - 1, u32::MAX]);
+        sets.push(vec![0xFFFF_FFF1, 0xFFFF_FFF9]);
+        sets
+    }
+
+    #[test]
+    fn test_encodings() {
+        for offsets in test_offsets() {
+            for encoding in [
+                IndexEncoding::Plain,
+                IndexEncoding::BlockPacked,
+                IndexEncoding::EliasFano,
+            ] {
+                let index = EncodedIndex::encode(&offsets, encoding).unwrap();
+                check(&index, &offsets);
+
+                let mut buf = vec![];
+                index.write_to(&mut buf).unwrap();
+                let decoded = EncodedIndex::decode(encoding, &buf).unwrap();
+                assert_eq!(decoded.encoding(), encoding);
+                check(&decoded, &offsets);
+            }
+        }
+    }
+
+    #[test]
+    fn test_compression() {
+        let offsets: Vec<u32> = (0..100_000).map(|i| i * 10).collect();
+        let plain = EncodedIndex::encode(&offsets, IndexEncoding::Plain).unwrap();
+        for encoding in [IndexEncoding::BlockPacked, IndexEncoding::EliasFano] {
+            let index = EncodedIndex::encode(&offsets, encoding).unwrap();
+            assert!(index.heap_size() * 2 < plain.heap_size());
+        }
+    }
+
+    #[test]
+    fn test_non_monotonic() {
+        let offsets = [0, 10, 5];
+        for encoding in [
+            IndexEncoding::Plain,
+            IndexEncoding::BlockPacked,
+            IndexEncoding::EliasFano,
+        ] {
+            let err = EncodedIndex::encode(&offsets, encoding).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        let offsets: Vec<u32> = (0..1000).map(|i| i * 3).collect();
+        for encoding in [IndexEncoding::BlockPacked, IndexEncoding::EliasFano] {
+            let mut buf = vec![];
+            EncodedIndex::encode(&offsets, encoding)
+                .unwrap()
+                .write_to(&mut buf)
+                .unwrap();
+
+            assert!(EncodedIndex::decode(encoding, &buf[..buf.len() - 1]).is_err());
+
+            // Increase length
+            buf[0] += 1;
+            assert!(EncodedIndex::decode(encoding, &buf).is_err());
+            buf[0] -= 1;
+
+            // Corrupt the last sample or the base of the last block
+            let mut corrupt = buf.clone();
+            match encoding {
+                IndexEncoding::EliasFano => *corrupt.last_mut().unwrap() ^= 0x10,
+                _ => {
+                    let blocks = offsets.len().div_ceil(BLOCK_SIZE);
+                    let base = buf.len() - blocks - 4;
+                    corrupt[base..base + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+                }
+            }
+            assert!(EncodedIndex::decode(encoding, &corrupt).is_err());
+            assert!(EncodedIndex::decode(encoding, &buf).is_ok());
+        }
+    }
+
+    fn check(index: &EncodedIndex, offsets: &[u32]) {
+        assert_eq!(index.len(), offsets.len());
+        for (id, offset) in offsets.iter().enumerate() {
+            assert_eq!(index.offset(id), Some(*offset));
+        }
+        assert_eq!(index.offset(offsets.len()), None);
+    }
+}
@@ -0,0 +1,397 @@
+use crate::{
+    encoded_index::IndexEncoding,
+    format::{invalid_data, section, Layout},
+    memory::MemFile,
+    traits::{IndexedAccess, IndexedAccessMut},
+};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use std::{
+    fs::File,
+    io::{BufWriter, Error, Write},
+    path::Path,
+};
+
+#[cfg(feature = "mapped")]
+use crate::map::MappedFile;
+
+/// A 256 bit key used to encrypt entries
+pub type Key = [u8; 32];
+
+/// Identifies XChaCha20-Poly1305 in the encryption section
+const ALGORITHM: u32 = 1;
+
+const NONCE_LEN: usize = 24;
+const TAG_LEN: usize = 16;
+const FILE_ID_LEN: usize = 16;
+
+/// algorithm (4) + reserved (4) + file ID + nonce + tag of the key check
+const HEADER_LEN: usize = 8 + FILE_ID_LEN + NONCE_LEN + TAG_LEN;
+
+/// Associated data of the key check stored with the file
+const KEY_CHECK: &[u8] = b"st-file key check";
+
+/// A file that encrypts each entry with XChaCha20-Poly1305 using a caller provided key.
+///
+/// Entries are stored as a random nonce followed by the ciphertext and the authentication tag
+/// in a [`MemFile`], so the offset index still allows random access. Each file gets a random
+/// ID, which is authenticated together with the ID of each entry, so entries can't be swapped,
+/// moved to another ID or copied from another file using the same key without being rejected
+/// on `get`. Only the data is encrypted; the amount and sizes of entries are visible.
+///
+/// Saved files contain an encryption section holding the file ID and a key check, which
+/// allows to detect a wrong key when loading the file instead of failing on each entry. The
+/// key check authenticates the amount of entries too, so entries can't be cut off the end.
+#[derive(Clone)]
+pub struct EncryptedFile {
+    file: MemFile,
+    cipher: Cipher,
+}
+
+impl EncryptedFile {
+    /// Creates a new empty file encrypting entries with `key`
+    #[inline]
+    pub fn new(key: &Key) -> Self {
+        Self {
+            file: MemFile::new(),
+            cipher: Cipher::new(key),
+        }
+    }
+
+    /// Encrypts and inserts a new entry and returns its ID
+    #[inline]
+    pub fn insert(&mut self, data: &[u8]) -> usize {
+        let id = self.file.len();
+        self.file.insert(&self.cipher.encrypt(id, data))
+    }
+
+    /// Encrypts `data` and replaces the entry `id` with it
+    #[inline]
+    pub fn replace(&mut self, id: usize, data: &[u8]) -> Option<()> {
+        if id >= self.file.len() {
+            return None;
+        }
+        self.file.replace(id, &self.cipher.encrypt(id, data))
+    }
+
+    /// Returns the decrypted entry `id`. Fails if the entry has been tampered with.
+    #[inline]
+    pub fn get(&self, id: usize) -> Option<Result<Vec<u8>, Error>> {
+        if id >= self.len() {
+            return None;
+        }
+        Some(self.cipher.decrypt(id, self.file.get(id)))
+    }
+
+    /// Returns an iterator over all decrypted entries
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = Result<Vec<u8>, Error>> + '_ {
+        (0..self.len()).map(|id| self.get(id).unwrap())
+    }
+
+    /// Returns the amount of entries
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.file.len()
+    }
+
+    /// Returns `true` if the file has no entries
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.file.is_empty()
+    }
+
+    /// Returns the underlying file holding the encrypted entries
+    #[inline]
+    pub fn file(&self) -> &MemFile {
+        &self.file
+    }
+
+    /// Saves the encrypted file
+    pub fn save<P: AsRef<Path>>(&self, path: P, encoding: IndexEncoding) -> Result<(), Error> {
+        let out = BufWriter::new(File::create(path)?);
+        self.write_to(out, encoding)
+    }
+
+    /// Writes the encrypted file
+    pub fn write_to<W: Write>(&self, w: W, encoding: IndexEncoding) -> Result<(), Error> {
+        let header = self.cipher.header(self.len());
+        self.file
+            .write_with_sections(w, encoding, &[(section::ENCRYPTED, &header)])
+    }
+
+    /// Loads a file saved with `EncryptedFile::save`. Fails if `key` is not the key the file
+    /// has been encrypted with.
+    pub fn load<P: AsRef<Path>>(path: P, key: &Key) -> Result<Self, Error> {
+        let bytes = std::fs::read(path)?;
+        let layout = Layout::parse(&bytes)?;
+        let header = layout.required_section(section::ENCRYPTED)?;
+        let cipher = Cipher::open(key, &bytes[header.range()], layout.entries)?;
+
+        let file = MemFile::from_layout(&bytes, &layout)?;
+        Ok(Self { file, cipher })
+    }
+}
+
+/// Read only [`EncryptedFile`] backed by a [`MappedFile`]. Entries are decrypted on each
+/// access.
+#[cfg(feature = "mapped")]
+pub struct MappedEncryptedFile {
+    file: MappedFile,
+    cipher: Cipher,
+}
+
+#[cfg(feature = "mapped")]
+impl MappedEncryptedFile {
+    /// Opens a file saved with `EncryptedFile::save`. Fails if `key` is not the key the file
+    /// has been encrypted with.
+    pub fn open<P: AsRef<Path>>(path: P, key: &Key) -> Result<Self, Error> {
+        let file = MappedFile::open(path)?;
+        let header = file
+            .section(section::ENCRYPTED)
+            .ok_or_else(|| invalid_data("Not an encrypted file"))?;
+        let cipher = Cipher::open(key, header, file.len())?;
+        Ok(Self { file, cipher })
+    }
+
+    /// Returns the decrypted entry `id`. Fails if the entry has been tampered with.
+    #[inline]
+    pub fn get(&self, id: usize) -> Option<Result<Vec<u8>, Error>> {
+        if id >= self.len() {
+            return None;
+        }
+        Some(self.cipher.decrypt(id, self.file.get(id)))
+    }
+
+    /// Returns an iterator over all decrypted entries
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = Result<Vec<u8>, Error>> + '_ {
+        (0..self.len()).map(|id| self.get(id).unwrap())
+    }
+
+    /// Returns the amount of entries
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.file.len()
+    }
+
+    /// Returns `true` if the file has no entries
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.file.is_empty()
+    }
+}
+
+/// Encrypts and decrypts single entries of a file
+#[derive(Clone)]
+struct Cipher {
+    aead: XChaCha20Poly1305,
+    /// Random ID of the file, authenticated with every entry
+    file_id: [u8; FILE_ID_LEN],
+}
+
+impl Cipher {
+    /// Creates a cipher for a new file with a random file ID
+    fn new(key: &Key) -> Self {
+        let mut file_id = [0u8; FILE_ID_LEN];
+        OsRng.fill_bytes(&mut file_id);
+        Self {
+            aead: XChaCha20Poly1305::new(key.into()),
+            file_id,
+        }
+    }
+
+    /// Creates the cipher of a saved file with `entries` entries from its encryption section.
+    /// Fails if the file hasn't been encrypted with `key` or the amount of entries changed.
+    fn open(key: &Key, header: &[u8], entries: usize) -> Result<Self, Error> {
+        check_header(header)?;
+        let cipher = Self {
+            aead: XChaCha20Poly1305::new(key.into()),
+            file_id: header[8..8 + FILE_ID_LEN].try_into().unwrap(),
+        };
+
+        let nonce = XNonce::from_slice(&header[8 + FILE_ID_LEN..8 + FILE_ID_LEN + NONCE_LEN]);
+        let payload = Payload {
+            msg: &header[8 + FILE_ID_LEN + NONCE_LEN..],
+            aad: &cipher.key_check_aad(entries),
+        };
+        cipher
+            .aead
+            .decrypt(nonce, payload)
+            .map_err(|_| invalid_data("Wrong key or corrupted file"))?;
+        Ok(cipher)
+    }
+
+    /// Returns nonce, ciphertext and tag of an entry, using the file ID and the entry ID as
+    /// associated data
+    fn encrypt(&self, id: usize, data: &[u8]) -> Vec<u8> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: data,
+            aad: &self.entry_aad(id),
+        };
+        let ciphertext = self
+            .aead
+            .encrypt(&nonce, payload)
+            .expect("Entry too large to encrypt");
+
+        let mut out = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&ciphertext);
+        out
+    }
+
+    fn decrypt(&self, id: usize, entry: Option<&[u8]>) -> Result<Vec<u8>, Error> {
+        let entry = entry.ok_or_else(|| invalid_data(format!("Encrypted entry {id} missing")))?;
+        if entry.len() < NONCE_LEN + TAG_LEN {
+            return Err(invalid_data(format!("Encrypted entry {id} too short")));
+        }
+        let (nonce, ciphertext) = entry.split_at(NONCE_LEN);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: &self.entry_aad(id),
+        };
+        self.aead
+            .decrypt(XNonce::from_slice(nonce), payload)
+            .map_err(|_| invalid_data(format!("Entry {id} failed authentication")))
+    }
+
+    /// Returns the encryption section of a file with `entries` entries: algorithm, reserved,
+    /// file ID, nonce and the tag of an empty message authenticating the key check
+    fn header(&self, entries: usize) -> Vec<u8> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: &[],
+            aad: &self.key_check_aad(entries),
+        };
+        let tag = self.aead.encrypt(&nonce, payload).unwrap();
+
+        let mut out = Vec::with_capacity(HEADER_LEN);
+        out.extend_from_slice(&ALGORITHM.to_le_bytes());
+        out.extend_from_slice(&0u32.to_le_bytes());
+        out.extend_from_slice(&self.file_id);
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&tag);
+        out
+    }
+
+    /// Associated data of an entry: file ID and entry ID
+    #[inline]
+    fn entry_aad(&self, id: usize) -> [u8; FILE_ID_LEN + 8] {
+        let mut aad = [0u8; FILE_ID_LEN + 8];
+        aad[..FILE_ID_LEN].copy_from_slice(&self.file_id);
+        aad[FILE_ID_LEN..].copy_from_slice(&(id as u64).to_le_bytes());
+        aad
+    }
+
+    /// Associated data of the key check: a constant, the file ID and the amount of entries
+    fn key_check_aad(&self, entries: usize) -> Vec<u8> {
+        let mut aad = KEY_CHECK.to_vec();
+        aad.extend_from_slice(&self.file_id);
+        aad.extend_from_slice(&(entries as u64).to_le_bytes());
+        aad
+    }
+}
+
+/// Validates the encryption section of a file without checking the key
+pub(crate) fn check_header(header: &[u8]) -> Result<(), Error> {
+    if header.len() != HEADER_LEN {
+        return Err(invalid_data("Invalid encryption section length"));
+    }
+    let algorithm = u32::from_le_bytes(header[..4].try_into().unwrap());
+    if algorithm != ALGORITHM {
+        return Err(invalid_data(format!(
+            "Unsupported encryption algorithm {algorithm}"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::read_to_string;
+
+    #[test]
+    fn test_encrypted_file() {
+        let key = [7u8; 32];
+        let content = read_to_string("./testfiles/LICENSE").unwrap();
+        let mut file = EncryptedFile::new(&key);
+        for line in content.lines() {
+            file.insert(line.as_bytes());
+        }
+        file.replace(2, b"replaced").unwrap();
+        assert_eq!(file.replace(file.len(), b"x"), None);
+
+        let mut exp: Vec<_> = content.lines().map(|i| i.as_bytes()).collect();
+        exp[2] = b"replaced";
+        assert!(file.iter().map(|i| i.unwrap()).eq(exp.iter().copied()));
+        assert!(!file.file().iter().any(|i| i.ends_with(b"Permission")));
+
+        file.save("test_encrypted_file", IndexEncoding::EliasFano)
+            .unwrap();
+        assert!(EncryptedFile::load("test_encrypted_file", &[8u8; 32]).is_err());
+        let loaded = EncryptedFile::load("test_encrypted_file", &key).unwrap();
+        assert!(loaded.iter().map(|i| i.unwrap()).eq(exp.iter().copied()));
+
+        #[cfg(feature = "mapped")]
+        {
+            assert!(MappedEncryptedFile::open("test_encrypted_file", &[8u8; 32]).is_err());
+            let mapped = MappedEncryptedFile::open("test_encrypted_file", &key).unwrap();
+            assert_eq!(mapped.len(), exp.len());
+            assert!(mapped.iter().map(|i| i.unwrap()).eq(exp.iter().copied()));
+
+            // Entries with offsets outside of the data fail instead of panicking
+            file.save("test_encrypted_offsets", IndexEncoding::Plain)
+                .unwrap();
+            let mut bytes = std::fs::read("test_encrypted_offsets").unwrap();
+            let index = Layout::parse(&bytes)
+                .unwrap()
+                .required_section(section::INDEX)
+                .unwrap()
+                .range();
+            bytes[index.end - 4..index.end].copy_from_slice(&u32::MAX.to_le_bytes());
+            std::fs::write("test_encrypted_offsets", bytes).unwrap();
+            let mapped = MappedEncryptedFile::open("test_encrypted_offsets", &key).unwrap();
+            assert!(mapped.get(mapped.len() - 1).unwrap().is_err());
+            assert!(mapped.iter().last().unwrap().is_err());
+            std::fs::remove_file("test_encrypted_offsets").unwrap();
+        }
+        std::fs::remove_file("test_encrypted_file").unwrap();
+
+        // Tampered and swapped entries are rejected
+        let mut bytes = file.file().get(0).unwrap().to_vec();
+        *bytes.last_mut().unwrap() ^= 1;
+        file.file.replace(0, &bytes).unwrap();
+        assert!(file.get(0).unwrap().is_err());
+        let second = file.file().get(1).unwrap().to_vec();
+        file.file.replace(3, &second).unwrap();
+        assert!(file.get(3).unwrap().is_err());
+        assert!(file.get(1).unwrap().is_ok());
+        assert!(file.get(file.len()).is_none());
+
+        // Entries of another file encrypted with the same key are rejected
+        let mut other = EncryptedFile::new(&key);
+        other.insert(b"other");
+        let entry = other.file().get(0).unwrap().to_vec();
+        file.file.replace(1, &entry).unwrap();
+        assert!(file.get(1).unwrap().is_err());
+
+        // Removing entries from the end of a saved file is detected
+        let header = file.cipher.header(file.len());
+        let truncated = MemFile::from(file.file().iter().take(file.len() - 1));
+        let mut buf = vec![];
+        truncated
+            .write_with_sections(
+                &mut buf,
+                IndexEncoding::Plain,
+                &[(section::ENCRYPTED, &header)],
+            )
+            .unwrap();
+        std::fs::write("test_encrypted_truncated", buf).unwrap();
+        assert!(EncryptedFile::load("test_encrypted_truncated", &key).is_err());
+        std::fs::remove_file("test_encrypted_truncated").unwrap();
+    }
+}
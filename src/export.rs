@@ -0,0 +1,170 @@
+use crate::traits::IndexedAccess;
+use std::{
+    io::{Error, Write},
+    ops::RangeBounds,
+};
+
+#[cfg(any(feature = "json", feature = "csv"))]
+use {serde::de::DeserializeOwned, serde::Serialize, std::io::ErrorKind};
+
+/// Writes all entries within `range` followed by a newline each. Entries containing
+/// newlines can't be read back as separate lines. Returns the amount of written entries.
+pub fn export_lines<F, W, R>(file: &F, mut w: W, range: R) -> Result<usize, Error>
+where
+    F: IndexedAccess,
+    W: Write,
+    R: RangeBounds<usize>,
+{
+    let mut count = 0;
+    for entry in file.iter_range(range) {
+        w.write_all(entry)?;
+        w.write_all(b"\n")?;
+        count += 1;
+    }
+    w.flush()?;
+    Ok(count)
+}
+
+/// Writes all entries within `range`, each prefixed with its length as little endian u32.
+/// Returns the amount of written entries.
+pub fn export_length_prefixed<F, W, R>(file: &F, mut w: W, range: R) -> Result<usize, Error>
+where
+    F: IndexedAccess,
+    W: Write,
+    R: RangeBounds<usize>,
+{
+    let mut count = 0;
+    for entry in file.iter_range(range) {
+        w.write_all(&(entry.len() as u32).to_le_bytes())?;
+        w.write_all(entry)?;
+        count += 1;
+    }
+    w.flush()?;
+    Ok(count)
+}
+
+/// Writes all typed entries within `range` as JSON, one entry per line. Returns the amount of
+/// written entries.
+#[cfg(feature = "json")]
+pub fn export_json_lines<T, F, W, R>(file: &F, mut w: W, range: R) -> Result<usize, Error>
+where
+    T: DeserializeOwned + Serialize,
+    F: IndexedAccess,
+    W: Write,
+    R: RangeBounds<usize>,
+{
+    let mut count = 0;
+    for entry in file.iter_range(range) {
+        let item: T = decode(entry)?;
+        serde_json::to_writer(&mut w, &item)?;
+        w.write_all(b"\n")?;
+        count += 1;
+    }
+    w.flush()?;
+    Ok(count)
+}
+
+/// Writes all typed entries within `range` as CSV records. A header row gets written from
+/// the field names of the first record. Returns the amount of written entries.
+#[cfg(feature = "csv")]
+pub fn export_csv<T, F, W, R>(file: &F, w: W, range: R) -> Result<usize, Error>
+where
+    T: DeserializeOwned + Serialize,
+    F: IndexedAccess,
+    W: Write,
+    R: RangeBounds<usize>,
+{
+    let mut writer = csv::Writer::from_writer(w);
+    let mut count = 0;
+    for entry in file.iter_range(range) {
+        let item: T = decode(entry)?;
+        writer.serialize(item).map_err(Error::other)?;
+        count += 1;
+    }
+    writer.flush()?;
+    Ok(count)
+}
+
+#[cfg(any(feature = "json", feature = "csv"))]
+#[inline]
+fn decode<T: DeserializeOwned>(entry: &[u8]) -> Result<T, Error> {
+    bincode::deserialize(entry).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{import, MemFile};
+    use std::fs::read_to_string;
+
+    #[test]
+    fn test_roundtrip() {
+        let content = read_to_string("./testfiles/LICENSE").unwrap();
+        let file = MemFile::from(content.lines());
+
+        let mut out = vec![];
+        assert_eq!(export_lines(&file, &mut out, ..).unwrap(), file.len());
+        assert_eq!(String::from_utf8(out).unwrap(), content);
+
+        let mut out = vec![];
+        assert_eq!(export_length_prefixed(&file, &mut out, 10..20).unwrap(), 10);
+        let mut imported = MemFile::new();
+        import::import_u32_prefixed(&out[..], &mut imported).unwrap();
+        assert!(imported.iter().eq(file.iter_range(10..20)));
+
+        let mut out = vec![];
+        assert_eq!(export_lines(&file, &mut out, file.len()..).unwrap(), 0);
+        assert!(out.is_empty());
+    }
+
+    #[cfg(any(feature = "json", feature = "csv"))]
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct Record {
+        id: u32,
+        name: String,
+    }
+
+    #[cfg(any(feature = "json", feature = "csv"))]
+    fn records() -> MemFile {
+        use crate::traits::TypedIndexedAccessMut;
+        let mut file = MemFile::new();
+        for (id, name) in ["a", "b \"quoted\", with comma", "c"].iter().enumerate() {
+            let record = Record {
+                id: id as u32,
+                name: name.to_string(),
+            };
+            file.insert_typed(&record).unwrap();
+        }
+        file
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json_lines() {
+        let file = records();
+        let mut out = vec![];
+        export_json_lines::<Record, _, _, _>(&file, &mut out, 1..).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"id\":1,\"name\":\"b \\\"quoted\\\", with comma\"}\n{\"id\":2,\"name\":\"c\"}\n"
+        );
+
+        let mut out = vec![];
+        assert!(export_json_lines::<(u32, String, String), _, _, _>(&file, &mut out, ..).is_err());
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn test_csv() {
+        let file = records();
+        let mut out = vec![];
+        assert_eq!(
+            export_csv::<Record, _, _, _>(&file, &mut out, ..).unwrap(),
+            3
+        );
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "id,name\n0,a\n1,\"b \"\"quoted\"\", with comma\"\n2,c\n"
+        );
+    }
+}
@@ -0,0 +1,321 @@
+use crate::{
+    encoded_index::IndexEncoding,
+    map::MappedFile,
+    memory::MemFile,
+    sorted::{Bytewise, Comparator},
+    traits::{IndexedAccess, IndexedAccessMut},
+    writer::FileWriter,
+};
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    io::{Error, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering as AtomicOrdering},
+};
+
+/// Default amount of entry data sorted in memory at once
+const DEFAULT_RUN_SIZE: usize = 64 * 1024 * 1024;
+
+/// Used to give temporary runs of concurrent sorts distinct names
+static SORT_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Sorts files that don't fit into memory. Entries are read in chunks of about `run_size`
+/// bytes, each chunk is sorted in memory and spilled to a temporary file. The sorted runs
+/// are then merged into the output. The sort is stable.
+pub struct ExternalSorter<C = Bytewise> {
+    cmp: C,
+    run_size: usize,
+    temp_dir: PathBuf,
+}
+
+impl ExternalSorter<Bytewise> {
+    /// Creates a new sorter that orders entries by their raw bytes
+    #[inline]
+    pub fn new() -> Self {
+        Self::with_comparator(Bytewise)
+    }
+}
+
+impl Default for ExternalSorter<Bytewise> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Comparator> ExternalSorter<C> {
+    /// Creates a new sorter that orders entries using `cmp`
+    #[inline]
+    pub fn with_comparator(cmp: C) -> Self {
+        Self {
+            cmp,
+            run_size: DEFAULT_RUN_SIZE,
+            temp_dir: std::env::temp_dir(),
+        }
+    }
+
+    /// Sets the amount of entry data sorted in memory at once
+    #[inline]
+    pub fn run_size(mut self, run_size: usize) -> Self {
+        self.run_size = run_size.max(1);
+        self
+    }
+
+    /// Sets the directory temporary runs are written to. Defaults to the systems temp dir.
+    #[inline]
+    pub fn temp_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.temp_dir = dir.as_ref().to_path_buf();
+        self
+    }
+
+    /// Returns the comparator of the sorter
+    #[inline]
+    pub fn into_comparator(self) -> C {
+        self.cmp
+    }
+
+    /// Writes all entries of `file` in sorted order into `out`
+    #[inline]
+    pub fn sort<F, W>(&self, file: &F, out: &mut FileWriter<W>) -> Result<(), Error>
+    where
+        F: IndexedAccess,
+        W: Write,
+    {
+        self.sort_impl(file, out, false)?;
+        Ok(())
+    }
+
+    /// Writes all entries of `file` in sorted order into `out` and returns the permutation of
+    /// IDs: the entry with ID `id` in `file` has the ID `permutation[id]` in the output.
+    /// IDs are relative to the first entry written by this sort.
+    #[inline]
+    pub fn sort_with_permutation<F, W>(
+        &self,
+        file: &F,
+        out: &mut FileWriter<W>,
+    ) -> Result<Vec<usize>, Error>
+    where
+        F: IndexedAccess,
+        W: Write,
+    {
+        self.sort_impl(file, out, true)
+    }
+
+    fn sort_impl<F, W>(
+        &self,
+        file: &F,
+        out: &mut FileWriter<W>,
+        permutation: bool,
+    ) -> Result<Vec<usize>, Error>
+    where
+        F: IndexedAccess,
+        W: Write,
+    {
+        let runs = self.write_runs(file, permutation)?;
+        let mapped = runs
+            .paths
+            .iter()
+            .map(MappedFile::open)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut heap = BinaryHeap::with_capacity(mapped.len());
+        for (run, file) in mapped.iter().enumerate() {
+            if let Some(entry) = file.get(0) {
+                heap.push(Head::new(&self.cmp, entry, run, 0));
+            }
+        }
+
+        let mut ids = vec![0; if permutation { file.len() } else { 0 }];
+        let start = out.len();
+        while let Some(head) = heap.pop() {
+            let new_id = out.insert(head.entry)? - start;
+            if permutation {
+                let old_id = runs.starts[head.run] + runs.ids[head.run][head.pos] as usize;
+                ids[old_id] = new_id;
+            }
+
+            let next = head.pos + 1;
+            if let Some(entry) = mapped[head.run].get(next) {
+                heap.push(Head::new(&self.cmp, entry, head.run, next));
+            }
+        }
+
+        Ok(ids)
+    }
+
+    /// Sorts `file` in chunks and writes each sorted chunk to a temporary file
+    fn write_runs<F: IndexedAccess>(&self, file: &F, permutation: bool) -> Result<Runs, Error> {
+        let sort_id = SORT_COUNTER.fetch_add(1, AtomicOrdering::Relaxed);
+        let mut runs = Runs::default();
+
+        let mut chunk = MemFile::new();
+        let mut chunk_start = 0;
+        for id in 0..=file.len() {
+            let entry = file.get(id);
+            if let Some(entry) = entry {
+                chunk.insert(entry);
+                if chunk.raw_len() < self.run_size {
+                    continue;
+                }
+            }
+            if chunk.is_empty() {
+                break;
+            }
+
+            let mut order: Vec<u32> = (0..chunk.len() as u32).collect();
+            order.sort_by(|a, b| {
+                self.cmp.compare(
+                    chunk.get_unchecked(*a as usize),
+                    chunk.get_unchecked(*b as usize),
+                )
+            });
+
+            let path = self.temp_dir.join(format!(
+                "st-file-sort-{}-{sort_id}-{}",
+                std::process::id(),
+                runs.paths.len()
+            ));
+            runs.paths.push(path.clone());
+            let mut writer = FileWriter::create(&path, IndexEncoding::Plain)?;
+            for i in order.iter() {
+                writer.insert(chunk.get_unchecked(*i as usize))?;
+            }
+            writer.finish()?.flush()?;
+
+            runs.starts.push(chunk_start);
+            if permutation {
+                runs.ids.push(order);
+            }
+            chunk_start += chunk.len();
+            chunk = MemFile::new();
+        }
+
+        Ok(runs)
+    }
+}
+
+/// Sorted runs written to temporary files, which get removed on drop
+#[derive(Default)]
+struct Runs {
+    paths: Vec<PathBuf>,
+    /// ID of the first entry of each run in the input
+    starts: Vec<usize>,
+    /// Position of each entry within the input chunk of its run
+    ids: Vec<Vec<u32>>,
+}
+
+impl Drop for Runs {
+    fn drop(&mut self) {
+        for path in self.paths.iter() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// The current entry of a run during merging. Ordered reversed, so the smallest entry is
+/// on top of the heap.
+struct Head<'a, C> {
+    cmp: &'a C,
+    entry: &'a [u8],
+    run: usize,
+    pos: usize,
+}
+
+impl<'a, C: Comparator> Head<'a, C> {
+    #[inline]
+    fn new(cmp: &'a C, entry: &'a [u8], run: usize, pos: usize) -> Self {
+        Self {
+            cmp,
+            entry,
+            run,
+            pos,
+        }
+    }
+}
+
+impl<C: Comparator> Ord for Head<'_, C> {
+    #[inline]
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Equal entries of earlier runs come first to keep the sort stable
+        self.cmp
+            .compare(self.entry, other.entry)
+            .then(self.run.cmp(&other.run))
+            .reverse()
+    }
+}
+
+impl<C: Comparator> PartialOrd for Head<'_, C> {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<C: Comparator> PartialEq for Head<'_, C> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<C: Comparator> Eq for Head<'_, C> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::read_to_string;
+
+    #[test]
+    fn test_external_sort() {
+        let content = read_to_string("./testfiles/LICENSE").unwrap();
+        let file = MemFile::from(content.split_whitespace());
+        let mut exp: Vec<_> = content.split_whitespace().collect();
+        exp.sort();
+
+        let sorter = ExternalSorter::new().run_size(500);
+        let mut out = FileWriter::new(vec![], IndexEncoding::Plain).unwrap();
+        let permutation = sorter.sort_with_permutation(&file, &mut out).unwrap();
+        let sorted = MemFile::from_bytes(&out.finish().unwrap()).unwrap();
+        assert!(sorted.iter().eq(exp.iter().map(|i| i.as_bytes())));
+        for (old, new) in permutation.iter().enumerate() {
+            assert_eq!(file.get(old), sorted.get(*new));
+        }
+
+        // Stable for equal entries
+        let mut seen = permutation.clone();
+        seen.sort_unstable();
+        assert!(seen.iter().copied().eq(0..file.len()));
+        for old in 1..file.len() {
+            if file.get(old) == file.get(old - 1) {
+                assert!(permutation[old] > permutation[old - 1]);
+            }
+        }
+
+        let by_len = |a: &[u8], b: &[u8]| b.len().cmp(&a.len());
+        let sorter = ExternalSorter::with_comparator(by_len).run_size(100);
+        let mut out = FileWriter::new(vec![], IndexEncoding::Plain).unwrap();
+        sorter.sort(&file, &mut out).unwrap();
+        let sorted = MemFile::from_bytes(&out.finish().unwrap()).unwrap();
+        let mut exp: Vec<_> = content.split_whitespace().collect();
+        exp.sort_by_key(|i| std::cmp::Reverse(i.len()));
+        assert!(sorted.iter().eq(exp.iter().map(|i| i.as_bytes())));
+    }
+
+    #[cfg(feature = "typed")]
+    #[test]
+    fn test_sort_by_key() {
+        use crate::{sorted::ByKey, traits::TypedIndexedAccess, traits::TypedIndexedAccessMut};
+
+        let mut file = MemFile::new();
+        for i in (0..1000u32).rev() {
+            file.insert_typed(&(i % 7, i)).unwrap();
+        }
+
+        let sorter = ExternalSorter::with_comparator(ByKey::new(|i: &(u32, u32)| i.1)).run_size(64);
+        let mut out = FileWriter::new(vec![], IndexEncoding::Plain).unwrap();
+        sorter.sort(&file, &mut out).unwrap();
+        let sorted = MemFile::from_bytes(&out.finish().unwrap()).unwrap();
+        assert!(sorted.iter_typed::<(u32, u32)>().map(|i| i.1).eq(0..1000));
+    }
+}
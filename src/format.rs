@@ -0,0 +1,531 @@
+use std::{
+    io::{Error, ErrorKind, Read, Seek, SeekFrom, Write},
+    ops::Range,
+};
+
+/// Magic bytes at the beginning and the end of a file in the container format
+pub(crate) const MAGIC: [u8; 8] = *b"STFILE\0\0";
+
+/// Version of the container format
+pub(crate) const VERSION: u32 = 1;
+
+/// table offset (8) + section count (4) + version (4) + entries (8) + magic (8)
+const TRAILER_LEN: usize = 32;
+
+/// kind (4) + param (4) + offset (8) + len (8)
+const SECTION_ENTRY_LEN: usize = 24;
+
+/// Kinds of sections stored in a container file
+pub(crate) mod section {
+    /// The raw data of all entries
+    pub const DATA: u32 = 0;
+
+    /// The offset index. The sections parameter holds the used `IndexEncoding`
+    pub const INDEX: u32 = 1;
+
+    /// A `HashTable` mapping hashes of keys to entry IDs
+    pub const HASH_TABLE: u32 = 2;
+
+    /// Marks the entries as sorted. Has no content
+    pub const SORTED: u32 = 3;
+
+    /// Marks the entries as key-value pairs of a `KvFile`. Has no content
+    pub const KEY_VALUE: u32 = 4;
+
+    /// Secondary indexes of an `IndexedStore`
+    pub const SECONDARY_INDEX: u32 = 5;
+
+    /// A `TrigramIndex` over all entries
+    pub const TRIGRAM: u32 = 6;
+
+    /// A `BloomFilter` over entries or keys extracted from them
+    pub const BLOOM: u32 = 7;
+
+    /// Version histories of a `VersionedFile`
+    pub const VERSIONS: u32 = 8;
+
+    /// A `MerkleTree` over all entries
+    pub const MERKLE: u32 = 9;
+
+    /// Marks a file with entries encrypted by an `EncryptedFile` and holds the key check
+    pub const ENCRYPTED: u32 = 10;
+
+    /// Returns a human readable name of a section kind
+    pub fn name(kind: u32) -> &'static str {
+        match kind {
+            DATA => "data",
+            INDEX => "index",
+            HASH_TABLE => "hash table",
+            SORTED => "sorted",
+            KEY_VALUE => "key-value",
+            SECONDARY_INDEX => "secondary index",
+            TRIGRAM => "trigram index",
+            BLOOM => "bloom filter",
+            VERSIONS => "versions",
+            MERKLE => "merkle tree",
+            ENCRYPTED => "encryption",
+            _ => "unknown",
+        }
+    }
+}
+
+/// A single section in a container file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct SectionEntry {
+    pub kind: u32,
+    pub param: u32,
+    pub offset: u64,
+    pub len: u64,
+}
+
+impl SectionEntry {
+    /// Returns the byte range of the section within the file
+    #[inline]
+    pub fn range(&self) -> Range<usize> {
+        self.offset as usize..(self.offset + self.len) as usize
+    }
+}
+
+/// Layout of a file, either in the container or the legacy (bincode encoded `MemFile`) format.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Layout {
+    pub entries: usize,
+    pub sections: Vec<SectionEntry>,
+    /// `false` for files in the legacy format
+    pub container: bool,
+}
+
+impl Layout {
+    /// Parses the layout of a file. `bytes` has to contain the whole file.
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.starts_with(&MAGIC) {
+            Self::parse_container(bytes)
+        } else {
+            Self::parse_legacy(bytes)
+        }
+    }
+
+    /// Returns the first section of the given kind
+    #[inline]
+    pub fn section(&self, kind: u32) -> Option<&SectionEntry> {
+        self.sections.iter().find(|i| i.kind == kind)
+    }
+
+    /// Returns the first section of the given kind or an error if there is none
+    #[inline]
+    pub fn required_section(&self, kind: u32) -> Result<&SectionEntry, Error> {
+        self.section(kind)
+            .ok_or_else(|| invalid_data(format!("Missing section {kind}")))
+    }
+
+    /// Reads the layout of a file from `r`. Only the header, the trailer and the section
+    /// table are read, so the size of the sections doesn't matter.
+    pub fn read<R: Read + Seek>(mut r: R) -> Result<Self, Error> {
+        let len = usize::try_from(r.seek(SeekFrom::End(0))?)
+            .map_err(|_| invalid_data("File too large"))?;
+        if len < MAGIC.len() {
+            return Err(invalid_data("File too small"));
+        }
+
+        let mut head = [0u8; 8];
+        r.seek(SeekFrom::Start(0))?;
+        r.read_exact(&mut head)?;
+
+        if head != MAGIC {
+            let entries = u64::from_le_bytes(head) as usize;
+            let index_len = entries
+                .checked_mul(4)
+                .ok_or_else(|| invalid_data("Invalid index length"))?;
+            let data_len_pos = index_len
+                .checked_add(8)
+                .filter(|pos| pos.saturating_add(8) <= len)
+                .ok_or_else(|| invalid_data("Unexpected end of data"))?;
+
+            let mut data_len = [0u8; 8];
+            r.seek(SeekFrom::Start(data_len_pos as u64))?;
+            r.read_exact(&mut data_len)?;
+            let data_len = u64::from_le_bytes(data_len);
+            if data_len > (len - data_len_pos - 8) as u64 {
+                return Err(invalid_data("Unexpected end of data"));
+            }
+            return Ok(Self::legacy(entries, index_len, data_len));
+        }
+
+        if len < MAGIC.len() + TRAILER_LEN {
+            return Err(invalid_data("File too small"));
+        }
+        let mut trailer = [0u8; TRAILER_LEN];
+        r.seek(SeekFrom::Start((len - TRAILER_LEN) as u64))?;
+        r.read_exact(&mut trailer)?;
+        let (table_offset, section_count, entries) = Self::parse_trailer(&trailer, len)?;
+
+        let mut table = vec![0u8; len - TRAILER_LEN - table_offset];
+        r.seek(SeekFrom::Start(table_offset as u64))?;
+        r.read_exact(&mut table)?;
+        let sections = Self::parse_table(&table, section_count, table_offset)?;
+
+        Ok(Self {
+            entries,
+            sections,
+            container: true,
+        })
+    }
+
+    fn parse_container(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < MAGIC.len() + TRAILER_LEN {
+            return Err(invalid_data("File too small"));
+        }
+
+        let table_end = bytes.len() - TRAILER_LEN;
+        let (table_offset, section_count, entries) =
+            Self::parse_trailer(&bytes[table_end..], bytes.len())?;
+        let sections =
+            Self::parse_table(&bytes[table_offset..table_end], section_count, table_offset)?;
+
+        Ok(Self {
+            entries,
+            sections,
+            container: true,
+        })
+    }
+
+    /// Parses the trailer of a container file with `file_len` bytes and returns the offset of
+    /// the section table, the amount of sections and entries
+    fn parse_trailer(trailer: &[u8], file_len: usize) -> Result<(usize, usize, usize), Error> {
+        let mut trailer = ByteReader::new(trailer);
+        let table_offset = trailer.u64()? as usize;
+        let section_count = trailer.u32()? as usize;
+        let version = trailer.u32()?;
+        let entries = trailer.u64()? as usize;
+        if trailer.bytes(MAGIC.len())? != MAGIC {
+            return Err(invalid_data("Missing trailing magic bytes"));
+        }
+
+        if version != VERSION {
+            return Err(invalid_data(format!("Unsupported version {version}")));
+        }
+
+        let table_end = file_len - TRAILER_LEN;
+        let table_len = section_count
+            .checked_mul(SECTION_ENTRY_LEN)
+            .ok_or_else(|| invalid_data("Invalid section count"))?;
+        if table_offset > table_end || table_end - table_offset != table_len {
+            return Err(invalid_data("Invalid section table"));
+        }
+
+        Ok((table_offset, section_count, entries))
+    }
+
+    /// Parses the section table, which starts at `table_offset`
+    fn parse_table(
+        table: &[u8],
+        section_count: usize,
+        table_offset: usize,
+    ) -> Result<Vec<SectionEntry>, Error> {
+        let mut table = ByteReader::new(table);
+        let mut sections = Vec::with_capacity(section_count);
+        for _ in 0..section_count {
+            let section = SectionEntry {
+                kind: table.u32()?,
+                param: table.u32()?,
+                offset: table.u64()?,
+                len: table.u64()?,
+            };
+
+            let end = section.offset.checked_add(section.len);
+            if end.is_none_or(|end| end > table_offset as u64) {
+                return Err(invalid_data("Section out of bounds"));
+            }
+
+            sections.push(section);
+        }
+        Ok(sections)
+    }
+
+    fn parse_legacy(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = ByteReader::new(bytes);
+
+        let entries = reader.u64()? as usize;
+        let index_len = entries
+            .checked_mul(4)
+            .ok_or_else(|| invalid_data("Invalid index length"))?;
+        reader.bytes(index_len)?;
+
+        let data_len = reader.u64()?;
+        reader.bytes(data_len as usize)?;
+
+        Ok(Self::legacy(entries, index_len, data_len))
+    }
+
+    /// Layout of a legacy file, which stores the entry count, the index, the data length and
+    /// the data one after another
+    fn legacy(entries: usize, index_len: usize, data_len: u64) -> Self {
+        let sections = vec![
+            SectionEntry {
+                kind: section::INDEX,
+                param: 0,
+                offset: 8,
+                len: index_len as u64,
+            },
+            SectionEntry {
+                kind: section::DATA,
+                param: 0,
+                offset: index_len as u64 + 16,
+                len: data_len,
+            },
+        ];
+
+        Self {
+            entries,
+            sections,
+            container: false,
+        }
+    }
+}
+
+/// Writes a file in the container format. Sections are written one after another and
+/// the section table gets appended in `finish`, so the output doesn't have to be seekable.
+pub(crate) struct ContainerWriter<W: Write> {
+    inner: W,
+    pos: u64,
+    sections: Vec<SectionEntry>,
+    current: Option<SectionEntry>,
+}
+
+impl<W: Write> ContainerWriter<W> {
+    /// Creates a new ContainerWriter and writes the file header
+    pub fn new(mut inner: W) -> Result<Self, Error> {
+        inner.write_all(&MAGIC)?;
+        Ok(Self {
+            inner,
+            pos: MAGIC.len() as u64,
+            sections: vec![],
+            current: None,
+        })
+    }
+
+    /// Starts a new section. Sections are always aligned to 8 bytes
+    pub fn begin_section(&mut self, kind: u32, param: u32) -> Result<(), Error> {
+        debug_assert!(self.current.is_none());
+        self.pad()?;
+        self.current = Some(SectionEntry {
+            kind,
+            param,
+            offset: self.pos,
+            len: 0,
+        });
+        Ok(())
+    }
+
+    /// Writes data into the current section
+    #[inline]
+    pub fn write_all(&mut self, buf: &[u8]) -> Result<(), Error> {
+        debug_assert!(self.current.is_some());
+        self.inner.write_all(buf)?;
+        self.pos += buf.len() as u64;
+        Ok(())
+    }
+
+    /// Ends the current section
+    pub fn end_section(&mut self) {
+        let mut section = self.current.take().expect("No section started");
+        section.len = self.pos - section.offset;
+        self.sections.push(section);
+    }
+
+    /// Writes a whole section at once
+    pub fn write_section(&mut self, kind: u32, param: u32, buf: &[u8]) -> Result<(), Error> {
+        self.begin_section(kind, param)?;
+        self.write_all(buf)?;
+        self.end_section();
+        Ok(())
+    }
+
+    /// Writes a section of little endian u32 values in chunks, without copying all values
+    /// into a single buffer first
+    pub fn write_u32_section(
+        &mut self,
+        kind: u32,
+        param: u32,
+        values: &[u32],
+    ) -> Result<(), Error> {
+        self.begin_section(kind, param)?;
+        let mut buf = Vec::with_capacity(4096);
+        for chunk in values.chunks(1024) {
+            buf.clear();
+            write_u32s(&mut buf, chunk)?;
+            self.write_all(&buf)?;
+        }
+        self.end_section();
+        Ok(())
+    }
+
+    /// Writes the section table and the trailer and returns the inner writer
+    pub fn finish(mut self, entries: usize) -> Result<W, Error> {
+        debug_assert!(self.current.is_none());
+        self.pad()?;
+
+        let table_offset = self.pos;
+        for section in &self.sections {
+            self.inner.write_all(&section.kind.to_le_bytes())?;
+            self.inner.write_all(&section.param.to_le_bytes())?;
+            self.inner.write_all(&section.offset.to_le_bytes())?;
+            self.inner.write_all(&section.len.to_le_bytes())?;
+        }
+
+        self.inner.write_all(&table_offset.to_le_bytes())?;
+        self.inner
+            .write_all(&(self.sections.len() as u32).to_le_bytes())?;
+        self.inner.write_all(&VERSION.to_le_bytes())?;
+        self.inner.write_all(&(entries as u64).to_le_bytes())?;
+        self.inner.write_all(&MAGIC)?;
+        self.inner.flush()?;
+
+        Ok(self.inner)
+    }
+
+    fn pad(&mut self) -> Result<(), Error> {
+        let padding = (8 - (self.pos % 8) as usize) % 8;
+        self.inner.write_all(&[0u8; 8][..padding])?;
+        self.pos += padding as u64;
+        Ok(())
+    }
+}
+
+/// Bounds checked little endian reader over a byte slice
+pub(crate) struct ByteReader<'a> {
+    buf: &'a [u8],
+    pub pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    #[inline]
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    /// Returns the next `len` bytes
+    #[inline]
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.buf.len())
+            .ok_or_else(|| invalid_data("Unexpected end of data"))?;
+        let out = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(out)
+    }
+
+    #[inline]
+    pub fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    #[inline]
+    pub fn u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    /// Reads `len` little endian u32 values
+    pub fn u32_vec(&mut self, len: usize) -> Result<Vec<u32>, Error> {
+        let bytes = self.bytes(len.checked_mul(4).ok_or_else(|| invalid_data("Overflow"))?)?;
+        Ok(bytes
+            .chunks_exact(4)
+            .map(|i| u32::from_le_bytes(i.try_into().unwrap()))
+            .collect())
+    }
+
+    /// Reads `len` little endian u64 values
+    pub fn u64_vec(&mut self, len: usize) -> Result<Vec<u64>, Error> {
+        let bytes = self.bytes(len.checked_mul(8).ok_or_else(|| invalid_data("Overflow"))?)?;
+        Ok(bytes
+            .chunks_exact(8)
+            .map(|i| u64::from_le_bytes(i.try_into().unwrap()))
+            .collect())
+    }
+}
+
+/// Writes a slice of u32 values in little endian
+pub(crate) fn write_u32s<W: Write>(mut w: W, values: &[u32]) -> Result<(), Error> {
+    for i in values {
+        w.write_all(&i.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Writes a slice of u64 values in little endian
+pub(crate) fn write_u64s<W: Write>(mut w: W, values: &[u64]) -> Result<(), Error> {
+    for i in values {
+        w.write_all(&i.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+#[inline]
+pub(crate) fn invalid_data<E>(err: E) -> Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    Error::new(ErrorKind::InvalidData, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_container_roundtrip() {
+        let mut writer = ContainerWriter::new(vec![]).unwrap();
+        writer.write_section(section::DATA, 0, b"hello").unwrap();
+        writer.write_section(section::INDEX, 2, &[1, 2, 3]).unwrap();
+        let out = writer.finish(3).unwrap();
+
+        let layout = Layout::parse(&out).unwrap();
+        assert_eq!(layout.entries, 3);
+
+        let data = layout.section(section::DATA).unwrap();
+        assert_eq!(&out[data.range()], b"hello");
+
+        let index = layout.section(section::INDEX).unwrap();
+        assert_eq!(index.param, 2);
+        assert_eq!(index.offset % 8, 0);
+        assert_eq!(&out[index.range()], &[1, 2, 3]);
+
+        assert_eq!(Layout::read(std::io::Cursor::new(&out)).unwrap(), layout);
+    }
+
+    #[test]
+    fn test_read_legacy() {
+        let mut out = vec![];
+        out.extend_from_slice(&2u64.to_le_bytes());
+        write_u32s(&mut out, &[0, 3]).unwrap();
+        out.extend_from_slice(&5u64.to_le_bytes());
+        out.extend_from_slice(b"hello");
+
+        let layout = Layout::parse(&out).unwrap();
+        assert!(!layout.container);
+        assert_eq!(
+            &out[layout.required_section(section::DATA).unwrap().range()],
+            b"hello"
+        );
+        assert_eq!(Layout::read(std::io::Cursor::new(&out)).unwrap(), layout);
+
+        for len in 0..out.len() {
+            assert!(Layout::parse(&out[..len]).is_err());
+            assert!(Layout::read(std::io::Cursor::new(&out[..len])).is_err());
+        }
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut writer = ContainerWriter::new(vec![]).unwrap();
+        writer.write_section(section::DATA, 0, b"hello").unwrap();
+        let out = writer.finish(1).unwrap();
+
+        for len in 0..out.len() {
+            assert!(Layout::parse(&out[..len]).is_err());
+            assert!(Layout::read(std::io::Cursor::new(&out[..len])).is_err());
+        }
+    }
+}
@@ -0,0 +1,324 @@
+use crate::traits::IndexedAccess;
+use fst::{
+    automaton::{Levenshtein, Str},
+    Automaton, IntoStreamer, Map, MapBuilder, Streamer,
+};
+use regex_automata::dense;
+use std::{
+    io::{Error, ErrorKind},
+    ops::{Bound, Range, RangeBounds},
+    path::Path,
+};
+
+/// FST based index mapping keys to IDs of entries in a file. Allows searching keys by
+/// prefix, range, regex or Levenshtein distance. Returned IDs can be resolved using
+/// `IndexedAccess::get` of the file the index has been built from.
+///
+/// Multiple entries can have the same key. The FST maps each key to a range within a list
+/// of postings holding the ascending IDs of all entries with that key. The index is stored
+/// as the FST, followed by the little endian u32 postings and the u64 length of the FST.
+pub struct FstIndex<D = Vec<u8>> {
+    map: Map<Prefix<D>>,
+    /// Byte range of the postings
+    postings: Range<usize>,
+}
+
+/// The first `len` bytes of `D`, which hold the FST
+struct Prefix<D> {
+    bytes: D,
+    len: usize,
+}
+
+impl<D: AsRef<[u8]>> AsRef<[u8]> for Prefix<D> {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes.as_ref()[..self.len]
+    }
+}
+
+impl FstIndex<Vec<u8>> {
+    /// Builds an index using the raw bytes of each entry as key
+    #[inline]
+    pub fn build<F: IndexedAccess>(file: &F) -> Result<Self, Error> {
+        Self::build_with(file, |_, entry| Some(entry.to_vec()))
+    }
+
+    /// Builds an index using keys extracted from each entry. Entries for which `extract`
+    /// returns `None` are not indexed.
+    pub fn build_with<F, E, K>(file: &F, mut extract: E) -> Result<Self, Error>
+    where
+        F: IndexedAccess,
+        E: FnMut(usize, &[u8]) -> Option<K>,
+        K: AsRef<[u8]>,
+    {
+        let mut keys: Vec<(K, u64)> = file
+            .iter()
+            .enumerate()
+            .filter_map(|(id, entry)| Some((extract(id, entry)?, id as u64)))
+            .collect();
+        keys.sort_by(|a, b| a.0.as_ref().cmp(b.0.as_ref()).then(a.1.cmp(&b.1)));
+
+        let mut builder = MapBuilder::memory();
+        let mut postings = vec![];
+        let mut keys = keys.iter().peekable();
+        while let Some((key, id)) = keys.next() {
+            let start = postings.len() as u64;
+            postings.push(*id as u32);
+            while let Some((_, id)) = keys.next_if(|i| i.0.as_ref() == key.as_ref()) {
+                postings.push(*id as u32);
+            }
+            let count = postings.len() as u64 - start;
+            builder
+                .insert(key, start << 32 | count)
+                .map_err(fst_error)?;
+        }
+
+        let mut bytes = builder.into_inner().map_err(fst_error)?;
+        let fst_len = bytes.len() as u64;
+        postings
+            .iter()
+            .for_each(|i| bytes.extend_from_slice(&i.to_le_bytes()));
+        bytes.extend_from_slice(&fst_len.to_le_bytes());
+        Self::from_bytes(bytes)
+    }
+
+    /// Loads an index saved with `FstIndex::save`
+    #[inline]
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::from_bytes(std::fs::read(path)?)
+    }
+}
+
+impl<D: AsRef<[u8]>> FstIndex<D> {
+    /// Creates an index from its raw bytes, eg. from a memory mapped file
+    pub fn from_bytes(bytes: D) -> Result<Self, Error> {
+        let buf = bytes.as_ref();
+        let invalid = || Error::new(ErrorKind::InvalidData, "Invalid FST index");
+        let trailer = buf.len().checked_sub(8).ok_or_else(invalid)?;
+        let len = u64::from_le_bytes(buf[trailer..].try_into().unwrap());
+        let len = usize::try_from(len).map_err(|_| invalid())?;
+        if len > trailer || !(trailer - len).is_multiple_of(4) {
+            return Err(invalid());
+        }
+
+        let postings = len..trailer;
+        let count = (postings.len() / 4) as u64;
+        let map = Map::new(Prefix { bytes, len }).map_err(fst_error)?;
+
+        // Lookups don't check the postings ranges, so all of them get validated once
+        let mut stream = map.stream();
+        while let Some((_, value)) = stream.next() {
+            if (value >> 32) + (value & u32::MAX as u64) > count {
+                return Err(invalid());
+            }
+        }
+        drop(stream);
+
+        Ok(Self { map, postings })
+    }
+
+    /// Saves the index
+    #[inline]
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        std::fs::write(path, self.as_bytes())
+    }
+
+    /// Returns the raw bytes of the index
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        self.map.as_fst().as_inner().bytes.as_ref()
+    }
+
+    /// Returns the IDs of all entries with the given key in ascending order
+    #[inline]
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Vec<usize> {
+        let mut out = vec![];
+        if let Some(value) = self.map.get(key) {
+            self.push_ids(value, &mut out);
+        }
+        out
+    }
+
+    /// Returns the IDs of all entries whose key starts with `prefix`, ordered by key
+    #[inline]
+    pub fn prefix(&self, prefix: &str) -> Vec<usize> {
+        self.search(Str::new(prefix).starts_with())
+    }
+
+    /// Returns the IDs of all entries whose key is within `range`, ordered by key
+    pub fn range<K, R>(&self, range: R) -> Vec<usize>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        let mut builder = self.map.range();
+        builder = match range.start_bound() {
+            Bound::Included(key) => builder.ge(key),
+            Bound::Excluded(key) => builder.gt(key),
+            Bound::Unbounded => builder,
+        };
+        builder = match range.end_bound() {
+            Bound::Included(key) => builder.le(key),
+            Bound::Excluded(key) => builder.lt(key),
+            Bound::Unbounded => builder,
+        };
+        self.collect_ids(builder.into_stream())
+    }
+
+    /// Returns the IDs of all entries whose whole key matches the given regex, ordered by key
+    pub fn regex(&self, pattern: &str) -> Result<Vec<usize>, Error> {
+        let dfa = dense::Builder::new()
+            .anchored(true)
+            .build(pattern)
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+        Ok(self.search(&dfa))
+    }
+
+    /// Returns the IDs of all entries whose key is within the given Levenshtein distance
+    /// of `query`, ordered by key
+    pub fn fuzzy(&self, query: &str, distance: u32) -> Result<Vec<usize>, Error> {
+        let aut = Levenshtein::new(query, distance)
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+        Ok(self.search(aut))
+    }
+
+    /// Same as `fuzzy` but only returns keys starting with a match, eg. for autocompletion
+    /// that tolerates typos
+    pub fn fuzzy_prefix(&self, query: &str, distance: u32) -> Result<Vec<usize>, Error> {
+        let aut = Levenshtein::new(query, distance)
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+        Ok(self.search(aut.starts_with()))
+    }
+
+    /// Returns the IDs of all entries whose key matches the given automaton, ordered by key
+    #[inline]
+    pub fn search<A: Automaton>(&self, aut: A) -> Vec<usize> {
+        self.collect_ids(self.map.search(aut).into_stream())
+    }
+
+    /// Returns the amount of distinct indexed keys
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if there are no indexed keys
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Returns the IDs of all keys of `stream`, ordered by key and ID
+    #[inline]
+    fn collect_ids<S>(&self, mut stream: S) -> Vec<usize>
+    where
+        S: for<'a> Streamer<'a, Item = (&'a [u8], u64)>,
+    {
+        let mut out = vec![];
+        while let Some((_, value)) = stream.next() {
+            self.push_ids(value, &mut out);
+        }
+        out
+    }
+
+    /// Appends the IDs of the postings range encoded in `value`
+    #[inline]
+    fn push_ids(&self, value: u64, out: &mut Vec<usize>) {
+        let start = self.postings.start + (value >> 32) as usize * 4;
+        let end = start + (value & u32::MAX as u64) as usize * 4;
+        let postings = &self.as_bytes()[start..end];
+        out.extend(
+            postings
+                .chunks_exact(4)
+                .map(|i| u32::from_le_bytes(i.try_into().unwrap()) as usize),
+        );
+    }
+}
+
+#[inline]
+fn fst_error(err: fst::Error) -> Error {
+    match err {
+        fst::Error::Io(err) => err,
+        err => Error::new(ErrorKind::InvalidData, err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemFile;
+    use std::fs::read_to_string;
+
+    fn words() -> MemFile {
+        let content = read_to_string("./testfiles/LICENSE").unwrap();
+        MemFile::from(content.split_whitespace())
+    }
+
+    fn keys<F: IndexedAccess>(file: &F, ids: &[usize]) -> Vec<String> {
+        ids.iter()
+            .map(|i| String::from_utf8(file.get(*i).unwrap().to_vec()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_queries() {
+        let file = words();
+        let index = FstIndex::build(&file).unwrap();
+        assert!(index.len() < file.len());
+
+        for (id, entry) in file.iter().enumerate() {
+            let found = index.get(entry);
+            assert!(found.contains(&id));
+            assert!(found.iter().all(|i| file.get(*i) == Some(entry)));
+        }
+        assert!(index.get("not in the license").is_empty());
+
+        // Every entry is found, including all entries with duplicate keys
+        let mut all = index.range::<&str, _>(..);
+        assert_eq!(all.len(), file.len());
+        all.sort_unstable();
+        assert!(all.into_iter().eq(0..file.len()));
+
+        let prefix = keys(&file, &index.prefix("licens"));
+        assert!(prefix.contains(&"license".to_string()));
+        assert!(prefix.iter().all(|i| i.starts_with("licens")));
+
+        let range = keys(&file, &index.range("a".."b"));
+        assert!(!range.is_empty());
+        assert!(range.iter().all(|i| i.as_str() >= "a" && i.as_str() < "b"));
+
+        let regex = keys(&file, &index.regex("lic[a-z]+").unwrap());
+        assert!(regex.contains(&"license".to_string()));
+        assert!(!regex.contains(&"license,".to_string()));
+        assert!(index.regex("(").is_err());
+
+        let fuzzy = keys(&file, &index.fuzzy("lisence", 2).unwrap());
+        assert!(fuzzy.contains(&"license".to_string()));
+
+        let fuzzy_prefix = keys(&file, &index.fuzzy_prefix("lisen", 1).unwrap());
+        assert!(fuzzy_prefix.contains(&"licenses".to_string()));
+    }
+
+    #[test]
+    fn test_build_with() {
+        let file = MemFile::from(["b=2", "a=1", "c=3", "invalid", "a=4"].iter());
+        let index = FstIndex::build_with(&file, |_, entry| {
+            let pos = entry.iter().position(|i| *i == b'=')?;
+            Some(entry[..pos].to_vec())
+        })
+        .unwrap();
+
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.get("a"), vec![1, 4]);
+        assert_eq!(index.range("a"..="b"), vec![1, 4, 0]);
+        assert_eq!(index.prefix("a"), vec![1, 4]);
+
+        index.save("test_fst_build_with").unwrap();
+        let loaded = FstIndex::load("test_fst_build_with").unwrap();
+        assert_eq!(loaded.get("c"), vec![2]);
+
+        let bytes = index.as_bytes();
+        assert!(FstIndex::from_bytes(&bytes[..bytes.len() - 4]).is_err());
+        std::fs::remove_file("test_fst_build_with").unwrap();
+    }
+}
@@ -0,0 +1,53 @@
+/// Multiplier used for mixing in words
+const K: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// Stable 64 bit hash function. In contrast to std's `DefaultHasher` its output is
+/// guaranteed to never change and can thus be used for persisted structures.
+#[inline]
+pub(crate) fn hash64(data: &[u8]) -> u64 {
+    hash64_seeded(data, 0)
+}
+
+/// Same as [`hash64`] but with a custom seed
+pub(crate) fn hash64_seeded(data: &[u8], seed: u64) -> u64 {
+    let mut hash = (seed ^ data.len() as u64).wrapping_add(K);
+
+    let mut chunks = data.chunks_exact(8);
+    for chunk in &mut chunks {
+        let word = u64::from_le_bytes(chunk.try_into().unwrap());
+        hash = (hash.rotate_left(5) ^ word).wrapping_mul(K);
+    }
+
+    let rem = chunks.remainder();
+    if !rem.is_empty() {
+        let mut buf = [0u8; 8];
+        buf[..rem.len()].copy_from_slice(rem);
+        hash = (hash.rotate_left(5) ^ u64::from_le_bytes(buf)).wrapping_mul(K);
+    }
+
+    fmix64(hash)
+}
+
+/// Finalizer of murmur3
+#[inline]
+fn fmix64(mut k: u64) -> u64 {
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    k ^= k >> 33;
+    k
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stable() {
+        // Persisted structures rely on these values to never change
+        assert_eq!(hash64(b"st-file"), 0x92d5_f83e_72bc_0561);
+        assert_ne!(hash64(b"a"), hash64(b"a\0"));
+        assert_ne!(hash64_seeded(b"a", 1), hash64(b"a"));
+    }
+}
@@ -0,0 +1,305 @@
+use crate::format::{invalid_data, write_u64s};
+use std::io::{Error, Write};
+
+/// Smallest amount of slots in a table
+const MIN_CAPACITY: usize = 16;
+
+/// Open addressing hash table with linear probing that maps hashes of keys to entry IDs.
+/// Keys themselves are not stored, so candidates have to be verified by the caller.
+///
+/// Each slot is a u64 holding the upper 32 bits of the keys hash in the high half and ID + 1
+/// in the low half. A slot of 0 is empty. Since slot positions only depend on the stored
+/// hash bits, the table can be grown without having access to the keys and be used directly
+/// from its little endian encoding, eg. in a mapped file.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct HashTable {
+    slots: Vec<u64>,
+    len: usize,
+}
+
+impl HashTable {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes a table written with `write_to`
+    pub fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        let table = HashTableRef::new(bytes)?;
+        let slots: Vec<_> = (0..table.capacity()).map(|i| table.slot(i)).collect();
+        let len = slots.iter().filter(|i| **i != 0).count();
+        Ok(Self { slots, len })
+    }
+
+    /// Inserts a new ID for the given hash. Doesn't check for duplicates.
+    pub fn insert(&mut self, hash: u64, id: u32) {
+        if (self.len + 1) * 2 > self.slots.len() {
+            self.grow();
+        }
+
+        let slot = make_slot(hash, id);
+        insert_slot(&mut self.slots, slot);
+        self.len += 1;
+    }
+
+    /// Returns the first ID with the given hash for which `is_key` returns true
+    #[inline]
+    pub fn find<F>(&self, hash: u64, is_key: F) -> Option<u32>
+    where
+        F: FnMut(u32) -> bool,
+    {
+        find(self.slots.len(), |i| self.slots[i], hash, is_key)
+    }
+
+    /// Removes the entry with the given hash and ID. Returns `false` if it doesn't exist
+    pub fn remove(&mut self, hash: u64, id: u32) -> bool {
+        let cap = self.slots.len();
+        if cap == 0 {
+            return false;
+        }
+
+        let target = make_slot(hash, id);
+        let mut pos = home(target, cap);
+        loop {
+            match self.slots[pos] {
+                0 => return false,
+                slot if slot == target => break,
+                _ => pos = (pos + 1) & (cap - 1),
+            }
+        }
+
+        // Backward shift deletion, so no tombstones are required
+        let mut hole = pos;
+        let mut next = (hole + 1) & (cap - 1);
+        while self.slots[next] != 0 {
+            // A slot can be moved into the hole if the hole lies between its home and itself
+            let home = home(self.slots[next], cap);
+            if next.wrapping_sub(home) & (cap - 1) >= next.wrapping_sub(hole) & (cap - 1) {
+                self.slots[hole] = self.slots[next];
+                hole = next;
+            }
+            next = (next + 1) & (cap - 1);
+        }
+        self.slots[hole] = 0;
+
+        self.len -= 1;
+        true
+    }
+
+    /// Checks that all IDs in the table are smaller than `len`
+    #[inline]
+    pub fn check_ids(&self, len: usize) -> Result<(), Error> {
+        check_ids(self.slots.iter().copied(), len)
+    }
+
+    /// Writes the table in little endian
+    #[inline]
+    pub fn write_to<W: Write>(&self, w: W) -> Result<(), Error> {
+        write_u64s(w, &self.slots)
+    }
+
+    fn grow(&mut self) {
+        let new_cap = (self.slots.len() * 2).max(MIN_CAPACITY);
+        let mut slots = vec![0u64; new_cap];
+        for slot in self.slots.iter().filter(|i| **i != 0) {
+            insert_slot(&mut slots, *slot);
+        }
+        self.slots = slots;
+    }
+}
+
+/// Read only view over an encoded [`HashTable`]
+#[derive(Clone, Copy)]
+pub(crate) struct HashTableRef<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> HashTableRef<'a> {
+    /// Creates a new view over an encoded table
+    pub fn new(bytes: &'a [u8]) -> Result<Self, Error> {
+        let cap = bytes.len() / 8;
+        if !bytes.len().is_multiple_of(8) || (cap != 0 && !cap.is_power_of_two()) {
+            return Err(invalid_data("Invalid hash table length"));
+        }
+        Ok(Self { bytes })
+    }
+
+    /// Creates a new view over a table that has already been validated using `new`
+    #[inline]
+    #[cfg_attr(not(feature = "mapped"), allow(dead_code))]
+    pub fn new_unchecked(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    /// Returns the first ID with the given hash for which `is_key` returns true
+    #[inline]
+    #[cfg_attr(not(feature = "mapped"), allow(dead_code))]
+    pub fn find<F>(&self, hash: u64, is_key: F) -> Option<u32>
+    where
+        F: FnMut(u32) -> bool,
+    {
+        find(self.capacity(), |i| self.slot(i), hash, is_key)
+    }
+
+    /// Checks that all IDs in the table are smaller than `len`
+    #[inline]
+    #[cfg_attr(not(feature = "mapped"), allow(dead_code))]
+    pub fn check_ids(&self, len: usize) -> Result<(), Error> {
+        check_ids((0..self.capacity()).map(|i| self.slot(i)), len)
+    }
+
+    #[inline]
+    fn capacity(&self) -> usize {
+        self.bytes.len() / 8
+    }
+
+    #[inline]
+    fn slot(&self, pos: usize) -> u64 {
+        u64::from_le_bytes(self.bytes[pos * 8..pos * 8 + 8].try_into().unwrap())
+    }
+}
+
+/// Checks that the IDs of all non empty `slots` are smaller than `len`
+fn check_ids<I: Iterator<Item = u64>>(slots: I, len: usize) -> Result<(), Error> {
+    let invalid = slots
+        .filter(|i| *i != 0)
+        .find_map(|slot| match (slot as u32).checked_sub(1) {
+            Some(id) if (id as usize) < len => None,
+            id => Some(id),
+        });
+
+    match invalid {
+        Some(Some(id)) => Err(invalid_data(format!(
+            "Hash table references missing entry {id}"
+        ))),
+        Some(None) => Err(invalid_data("Hash table slot without entry ID")),
+        None => Ok(()),
+    }
+}
+
+#[inline]
+fn find<S, F>(cap: usize, slot: S, hash: u64, mut is_key: F) -> Option<u32>
+where
+    S: Fn(usize) -> u64,
+    F: FnMut(u32) -> bool,
+{
+    if cap == 0 {
+        return None;
+    }
+
+    let tag = hash >> 32;
+    let mut pos = home(hash, cap);
+
+    for _ in 0..cap {
+        let slot = slot(pos);
+        if slot == 0 {
+            return None;
+        }
+
+        if slot >> 32 == tag {
+            if let Some(id) = (slot as u32).checked_sub(1) {
+                if is_key(id) {
+                    return Some(id);
+                }
+            }
+        }
+
+        pos = (pos + 1) & (cap - 1);
+    }
+
+    None
+}
+
+#[inline]
+fn insert_slot(slots: &mut [u64], slot: u64) {
+    let cap = slots.len();
+    let mut pos = home(slot, cap);
+    while slots[pos] != 0 {
+        pos = (pos + 1) & (cap - 1);
+    }
+    slots[pos] = slot;
+}
+
+#[inline]
+fn make_slot(hash: u64, id: u32) -> u64 {
+    (hash & 0xFFFF_FFFF_0000_0000) | (id as u64 + 1)
+}
+
+/// Returns the position a slot or hash would ideally be stored at
+#[inline]
+fn home(slot: u64, cap: usize) -> usize {
+    (slot >> 32) as usize & (cap - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::hash64;
+
+    #[test]
+    fn test_insert_find_remove() {
+        let keys: Vec<_> = (0..2000u32).map(|i| format!("key{i}")).collect();
+
+        let mut table = HashTable::new();
+        for (id, key) in keys.iter().enumerate() {
+            table.insert(hash64(key.as_bytes()), id as u32);
+        }
+        check(&table, &keys, |_| true);
+
+        let mut buf = vec![];
+        table.write_to(&mut buf).unwrap();
+        let table_ref = HashTableRef::new(&buf).unwrap();
+        for (id, key) in keys.iter().enumerate() {
+            let found = table_ref.find(hash64(key.as_bytes()), |i| keys[i as usize] == *key);
+            assert_eq!(found, Some(id as u32));
+        }
+        let decoded = HashTable::decode(&buf).unwrap();
+        assert_eq!(decoded.len, keys.len());
+        assert!(decoded.check_ids(keys.len()).is_ok());
+        assert!(decoded.check_ids(keys.len() - 1).is_err());
+        check(&decoded, &keys, |_| true);
+
+        for (id, key) in keys.iter().enumerate().step_by(3) {
+            assert!(table.remove(hash64(key.as_bytes()), id as u32));
+            assert!(!table.remove(hash64(key.as_bytes()), id as u32));
+        }
+        check(&table, &keys, |id| id % 3 != 0);
+    }
+
+    #[test]
+    fn test_slot_without_id() {
+        // Non empty slot with a tag but an ID of zero
+        let slots = [0, 7 << 32];
+        assert!(check_ids(slots.iter().copied(), 10).is_err());
+        assert_eq!(find(2, |i| slots[i], 7 << 32, |_| true), None);
+    }
+
+    #[test]
+    fn test_collisions() {
+        // All entries share the same hash
+        let mut table = HashTable::new();
+        for id in 0..100 {
+            table.insert(42 << 32, id);
+        }
+
+        for id in 0..100 {
+            assert_eq!(table.find(42 << 32, |i| i == id), Some(id));
+        }
+        assert_eq!(table.find(42 << 32, |i| i == 100), None);
+
+        for id in (0..100).step_by(2) {
+            assert!(table.remove(42 << 32, id));
+        }
+        for id in 0..100 {
+            let exp = (id % 2 == 1).then_some(id);
+            assert_eq!(table.find(42 << 32, |i| i == id), exp);
+        }
+    }
+
+    fn check<F: Fn(usize) -> bool>(table: &HashTable, keys: &[String], exists: F) {
+        for (id, key) in keys.iter().enumerate() {
+            let found = table.find(hash64(key.as_bytes()), |i| keys[i as usize] == *key);
+            assert_eq!(found, exists(id).then_some(id as u32));
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use crate::writer::Sink;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read};
+
+/// Imports newline separated entries from `r` into `sink`. A trailing `\r` of each line is
+/// removed. Returns the amount of imported entries.
+#[inline]
+pub fn import_lines<R: Read, S: Sink>(r: R, sink: &mut S) -> Result<usize, Error> {
+    import_split(r, sink, b'\n', |line| {
+        line.strip_suffix(b"\r").unwrap_or(line)
+    })
+}
+
+/// Imports NUL separated entries from `r` into `sink`. Returns the amount of imported entries.
+#[inline]
+pub fn import_nul<R: Read, S: Sink>(r: R, sink: &mut S) -> Result<usize, Error> {
+    import_delimited(r, sink, 0)
+}
+
+/// Imports entries separated by `delimiter` from `r` into `sink`. A delimiter at the end of
+/// the input doesn't start a new entry. Returns the amount of imported entries.
+#[inline]
+pub fn import_delimited<R: Read, S: Sink>(
+    r: R,
+    sink: &mut S,
+    delimiter: u8,
+) -> Result<usize, Error> {
+    import_split(r, sink, delimiter, |entry| entry)
+}
+
+/// Imports entries prefixed with their length as little endian u32 from `r` into `sink`.
+/// Returns the amount of imported entries.
+pub fn import_u32_prefixed<R: Read, S: Sink>(r: R, sink: &mut S) -> Result<usize, Error> {
+    let mut r = BufReader::new(r);
+    import_prefixed(sink, |buf| {
+        let mut len = [0u8; 4];
+        if !read_exact_or_eof(&mut r, &mut len)? {
+            return Ok(false);
+        }
+        read_entry(&mut r, buf, u32::from_le_bytes(len) as u64)?;
+        Ok(true)
+    })
+}
+
+/// Imports entries prefixed with their length as LEB128 varint from `r` into `sink`.
+/// Returns the amount of imported entries.
+pub fn import_varint_prefixed<R: Read, S: Sink>(r: R, sink: &mut S) -> Result<usize, Error> {
+    let mut r = BufReader::new(r);
+    import_prefixed(sink, |buf| {
+        let Some(len) = read_varint(&mut r)? else {
+            return Ok(false);
+        };
+        read_entry(&mut r, buf, len)?;
+        Ok(true)
+    })
+}
+
+fn import_split<R, S, F>(r: R, sink: &mut S, delimiter: u8, trim: F) -> Result<usize, Error>
+where
+    R: Read,
+    S: Sink,
+    F: Fn(&[u8]) -> &[u8],
+{
+    let mut r = BufReader::new(r);
+    let mut buf = vec![];
+    let mut count = 0;
+    loop {
+        buf.clear();
+        if r.read_until(delimiter, &mut buf)? == 0 {
+            return Ok(count);
+        }
+        let entry = buf.strip_suffix(&[delimiter]).unwrap_or(&buf);
+        sink.push(trim(entry))?;
+        count += 1;
+    }
+}
+
+/// Imports entries using `next`, which reads the next entry into the given buffer and
+/// returns `false` at the end of the input
+fn import_prefixed<S, F>(sink: &mut S, mut next: F) -> Result<usize, Error>
+where
+    S: Sink,
+    F: FnMut(&mut Vec<u8>) -> Result<bool, Error>,
+{
+    let mut buf = vec![];
+    let mut count = 0;
+    while next(&mut buf)? {
+        sink.push(&buf)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Reads an entry of `len` bytes into `buf`
+#[inline]
+fn read_entry<R: Read>(r: &mut R, buf: &mut Vec<u8>, len: u64) -> Result<(), Error> {
+    buf.clear();
+    if r.take(len).read_to_end(buf)? as u64 != len {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "Truncated entry"));
+    }
+    Ok(())
+}
+
+/// Fills `buf` completely. Returns `false` if the input ended before the first byte
+fn read_exact_or_eof<R: Read>(r: &mut R, buf: &mut [u8]) -> Result<bool, Error> {
+    let mut read = 0;
+    while read < buf.len() {
+        match r.read(&mut buf[read..]) {
+            Ok(0) if read == 0 => return Ok(false),
+            Ok(0) => return Err(Error::new(ErrorKind::UnexpectedEof, "Truncated length")),
+            Ok(n) => read += n,
+            Err(err) if err.kind() == ErrorKind::Interrupted => (),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(true)
+}
+
+/// Reads a LEB128 encoded u64. Returns `None` if the input ended before the first byte
+fn read_varint<R: Read>(r: &mut R) -> Result<Option<u64>, Error> {
+    let mut value = 0u64;
+    for i in 0..10 {
+        let mut byte = [0u8];
+        if !read_exact_or_eof(r, &mut byte)? {
+            if i == 0 {
+                return Ok(None);
+            }
+            return Err(Error::new(ErrorKind::UnexpectedEof, "Truncated length"));
+        }
+        value |= ((byte[0] & 0x7F) as u64) << (i * 7);
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+    Err(Error::new(ErrorKind::InvalidData, "Invalid varint"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{traits::IndexedAccess, writer::FileWriter, IndexEncoding, MemFile};
+    use std::fs::{read_to_string, File};
+
+    #[test]
+    fn test_delimited() {
+        let content = read_to_string("./testfiles/LICENSE").unwrap();
+
+        let mut file = MemFile::new();
+        let count = import_lines(File::open("./testfiles/LICENSE").unwrap(), &mut file).unwrap();
+        assert_eq!(count, content.lines().count());
+        assert!(file.iter().eq(content.lines().map(|i| i.as_bytes())));
+
+        let mut file = MemFile::new();
+        import_delimited(content.as_bytes(), &mut file, b' ').unwrap();
+        assert!(file.iter().eq(content.split(' ').map(|i| i.as_bytes())));
+
+        let mut file = MemFile::new();
+        assert_eq!(import_nul(&b"a\0\0bc\0"[..], &mut file).unwrap(), 3);
+        assert!(file.iter().eq([&b"a"[..], b"", b"bc"]));
+
+        assert_eq!(import_lines(&b"a\r\nb"[..], &mut file).unwrap(), 2);
+        assert_eq!(file.get(4), Some(&b"b"[..]));
+        assert_eq!(file.get(3), Some(&b"a"[..]));
+    }
+
+    #[test]
+    fn test_prefixed() {
+        let entries: [&[u8]; 4] = [b"a", b"", &[7u8; 300], b"last"];
+
+        let mut u32_input = vec![];
+        let mut varint_input = vec![];
+        for entry in entries {
+            u32_input.extend_from_slice(&(entry.len() as u32).to_le_bytes());
+            u32_input.extend_from_slice(entry);
+            let mut len = entry.len();
+            while len >= 0x80 {
+                varint_input.push((len as u8 & 0x7F) | 0x80);
+                len >>= 7;
+            }
+            varint_input.push(len as u8);
+            varint_input.extend_from_slice(entry);
+        }
+
+        let mut file = MemFile::new();
+        assert_eq!(import_u32_prefixed(&u32_input[..], &mut file).unwrap(), 4);
+        assert!(file.iter().eq(entries));
+
+        let mut writer = FileWriter::new(vec![], IndexEncoding::Plain).unwrap();
+        assert_eq!(
+            import_varint_prefixed(&varint_input[..], &mut writer).unwrap(),
+            4
+        );
+        let file = MemFile::from_bytes(&writer.finish().unwrap()).unwrap();
+        assert!(file.iter().eq(entries));
+
+        let mut file = MemFile::new();
+        let truncated = &u32_input[..u32_input.len() - 1];
+        assert!(import_u32_prefixed(truncated, &mut file).is_err());
+        assert!(import_u32_prefixed(&u32_input[..2], &mut file).is_err());
+        assert!(import_varint_prefixed(&[0x80u8][..], &mut file).is_err());
+    }
+}
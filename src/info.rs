@@ -0,0 +1,295 @@
+use crate::{
+    bloom::BloomFilter,
+    encoded_index::{EncodedIndex, IndexEncoding},
+    format::{invalid_data, section, Layout},
+    hash_table::HashTable,
+    memory::MemFile,
+    traits::{IndexedAccess, OffsetIndex},
+    trigram::TrigramIndex,
+    versioned,
+};
+use std::{
+    fs::File,
+    io::{BufReader, Error, Write},
+    path::Path,
+};
+
+/// Layout of a saved file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// A bincode encoded `MemFile`, as written by versions before the container format
+    Legacy,
+    /// The container format with the given offset index encoding
+    Container(IndexEncoding),
+}
+
+/// A single section of a saved file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SectionInfo {
+    pub kind: u32,
+    pub name: &'static str,
+    pub param: u32,
+    pub offset: u64,
+    pub len: u64,
+}
+
+/// Information about a saved file, read without decoding its entries
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileInfo {
+    pub format: Format,
+    pub entries: usize,
+    /// Size of the entries data in bytes
+    pub data_len: u64,
+    /// Size of the encoded offset index in bytes
+    pub index_len: u64,
+    /// All sections of the file, including data and index
+    pub sections: Vec<SectionInfo>,
+}
+
+impl FileInfo {
+    /// Reads the information of a saved file. Only the header, the trailer and the section
+    /// table are read, not the whole file.
+    #[inline]
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let layout = Layout::read(BufReader::new(File::open(path)?))?;
+        Self::from_layout(&layout)
+    }
+
+    /// Reads the information of a file from its saved representation
+    #[inline]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Self::from_layout(&Layout::parse(bytes)?)
+    }
+
+    fn from_layout(layout: &Layout) -> Result<Self, Error> {
+        let index = layout.required_section(section::INDEX)?;
+        let data = layout.required_section(section::DATA)?;
+
+        let format = if layout.container {
+            let encoding = IndexEncoding::from_param(index.param)
+                .ok_or_else(|| invalid_data("Unknown index encoding"))?;
+            Format::Container(encoding)
+        } else {
+            Format::Legacy
+        };
+
+        let sections = layout
+            .sections
+            .iter()
+            .map(|i| SectionInfo {
+                kind: i.kind,
+                name: section::name(i.kind),
+                param: i.param,
+                offset: i.offset,
+                len: i.len,
+            })
+            .collect();
+
+        Ok(Self {
+            format,
+            entries: layout.entries,
+            data_len: data.len,
+            index_len: index.len,
+            sections,
+        })
+    }
+}
+
+/// Checks the structure of a saved file: the layout, that the offset index is valid and
+/// points into the data and that all known sections can be decoded.
+pub fn verify(bytes: &[u8]) -> Result<FileInfo, Error> {
+    let info = FileInfo::from_bytes(bytes)?;
+    let layout = Layout::parse(bytes)?;
+
+    let mut ranges: Vec<_> = layout.sections.iter().map(|i| i.range()).collect();
+    ranges.sort_by_key(|i| i.start);
+    if ranges.windows(2).any(|i| i[0].end > i[1].start) {
+        return Err(invalid_data("Overlapping sections"));
+    }
+
+    let index = layout.required_section(section::INDEX)?;
+    let encoding = IndexEncoding::from_param(index.param)
+        .ok_or_else(|| invalid_data("Unknown index encoding"))?;
+    let index = EncodedIndex::decode(encoding, &bytes[index.range()])?;
+    if index.len() != layout.entries {
+        return Err(invalid_data("Index length mismatch"));
+    }
+
+    let mut last = 0;
+    for id in 0..index.len() {
+        let offset = index.offset(id).unwrap();
+        if offset < last || offset as u64 > info.data_len {
+            return Err(invalid_data(format!("Invalid offset of entry {id}")));
+        }
+        last = offset;
+    }
+
+    for section in layout.sections.iter() {
+        let content = &bytes[section.range()];
+        match section.kind {
+            section::HASH_TABLE => {
+                HashTable::decode(content)?.check_ids(layout.entries)?;
+            }
+            section::TRIGRAM => {
+                TrigramIndex::from_bytes(content, layout.entries)?;
+            }
+            section::BLOOM => {
+                BloomFilter::from_bytes(content)?;
+            }
+            section::VERSIONS => {
+                versioned::decode_versions(content, layout.entries)?;
+            }
+            #[cfg(feature = "merkle")]
+            section::MERKLE => {
+                let tree = crate::merkle::MerkleTree::from_bytes(content)?;
+                if tree.entries() != layout.entries {
+                    return Err(invalid_data("Merkle tree length mismatch"));
+                }
+            }
+            #[cfg(feature = "encryption")]
+            section::ENCRYPTED => {
+                crate::encrypted::check_header(content)?;
+            }
+            _ => (),
+        }
+    }
+
+    Ok(info)
+}
+
+/// Checks the structure of the file at `path`, see [`verify`]. The file gets mapped into
+/// memory instead of being read if the `mapped` feature is enabled.
+#[inline]
+pub fn verify_file<P: AsRef<Path>>(path: P) -> Result<FileInfo, Error> {
+    with_bytes(path, verify)
+}
+
+/// Converts the file at `path` into the given format, see [`convert`]. The file gets mapped
+/// into memory instead of being read if the `mapped` feature is enabled.
+#[inline]
+pub fn convert_file<P: AsRef<Path>, W: Write>(path: P, w: W, format: Format) -> Result<(), Error> {
+    with_bytes(path, |bytes| convert(bytes, w, format))
+}
+
+/// Calls `f` with the content of the file at `path`
+#[cfg(feature = "mapped")]
+fn with_bytes<P, T, F>(path: P, f: F) -> Result<T, Error>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&[u8]) -> Result<T, Error>,
+{
+    f(&crate::map::MappedFile::open_map(path)?)
+}
+
+/// Calls `f` with the content of the file at `path`
+#[cfg(not(feature = "mapped"))]
+fn with_bytes<P, T, F>(path: P, f: F) -> Result<T, Error>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&[u8]) -> Result<T, Error>,
+{
+    f(&std::fs::read(path)?)
+}
+
+/// Converts a saved file into the given format. Additional sections, eg. hash tables, are
+/// kept when converting to the container format and dropped for the legacy format.
+pub fn convert<W: Write>(bytes: &[u8], mut w: W, format: Format) -> Result<(), Error> {
+    let layout = Layout::parse(bytes)?;
+    let file = MemFile::from_layout(bytes, &layout)?;
+
+    match format {
+        Format::Container(encoding) => {
+            let sections: Vec<_> = layout
+                .sections
+                .iter()
+                .filter(|i| i.kind != section::DATA && i.kind != section::INDEX)
+                .map(|i| (i.kind, &bytes[i.range()]))
+                .collect();
+            file.write_with_sections(w, encoding, &sections)
+        }
+        Format::Legacy => {
+            w.write_all(&(file.len() as u64).to_le_bytes())?;
+            for offset in file.index.inner.iter() {
+                w.write_all(&offset.to_le_bytes())?;
+            }
+            w.write_all(&(file.data.len() as u64).to_le_bytes())?;
+            w.write_all(&file.data)?;
+            w.flush()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hash::hash64, interner::StringInterner};
+
+    #[test]
+    fn test_info_verify_convert() {
+        let mut interner = StringInterner::new();
+        interner.extend(["a", "bb", "ccc", "bb"]);
+        let mut bytes = vec![];
+        interner
+            .write_to(&mut bytes, IndexEncoding::EliasFano)
+            .unwrap();
+
+        let info = verify(&bytes).unwrap();
+        assert_eq!(info.format, Format::Container(IndexEncoding::EliasFano));
+        assert_eq!(info.entries, 3);
+        assert_eq!(info.data_len, 6);
+        let names: Vec<_> = info.sections.iter().map(|i| i.name).collect();
+        assert_eq!(names, ["data", "index", "hash table"]);
+
+        let mut plain = vec![];
+        convert(&bytes, &mut plain, Format::Container(IndexEncoding::Plain)).unwrap();
+        let info = verify(&plain).unwrap();
+        assert_eq!(info.format, Format::Container(IndexEncoding::Plain));
+        assert_eq!(info.sections.len(), 3);
+
+        let mut legacy = vec![];
+        convert(&plain, &mut legacy, Format::Legacy).unwrap();
+        let info = verify(&legacy).unwrap();
+        assert_eq!(info.format, Format::Legacy);
+        assert_eq!(info.index_len, 12);
+        assert!(MemFile::from_bytes(&legacy)
+            .unwrap()
+            .iter()
+            .eq(interner.file().iter()));
+
+        let path = "test_info_load";
+        std::fs::write(path, &bytes).unwrap();
+        assert_eq!(FileInfo::load(path).unwrap(), verify_file(path).unwrap());
+        let mut converted = vec![];
+        convert_file(
+            path,
+            &mut converted,
+            Format::Container(IndexEncoding::Plain),
+        )
+        .unwrap();
+        assert_eq!(converted, plain);
+        std::fs::write(path, &legacy).unwrap();
+        assert_eq!(FileInfo::load(path).unwrap(), info);
+        std::fs::remove_file(path).unwrap();
+
+        // Offsets not pointing into the data
+        legacy[12] = 0xFF;
+        assert!(verify(&legacy).is_err());
+        assert!(verify(&bytes[..bytes.len() - 1]).is_err());
+
+        // Hash table referencing a missing entry
+        let mut table = HashTable::new();
+        table.insert(hash64(b"x"), 3);
+        let mut buf = vec![];
+        table.write_to(&mut buf).unwrap();
+        let mut bytes = vec![];
+        interner
+            .file()
+            .write_with_sections(
+                &mut bytes,
+                IndexEncoding::Plain,
+                &[(section::HASH_TABLE, &buf)],
+            )
+            .unwrap();
+        assert!(verify(&bytes).is_err());
+    }
+}
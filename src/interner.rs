@@ -0,0 +1,288 @@
+use crate::{
+    encoded_index::IndexEncoding,
+    format::{invalid_data, section, Layout},
+    hash::hash64,
+    hash_table::HashTable,
+    memory::MemFile,
+    traits::{IndexedAccess, IndexedAccessMut},
+};
+use std::{
+    fs::File,
+    io::{BufWriter, Error, Write},
+    path::Path,
+};
+
+#[cfg(feature = "mapped")]
+use {
+    crate::{hash_table::HashTableRef, map::MappedFile},
+    std::ops::Range,
+};
+
+/// String pool built on a [`MemFile`] that stores every distinct string only once and allows
+/// finding the ID of an existing string. The lookup table gets saved together with the
+/// strings, so a [`MappedInterner`] can do reverse lookups without rebuilding it.
+#[derive(Clone, Default)]
+pub struct StringInterner {
+    file: MemFile,
+    table: HashTable,
+}
+
+impl StringInterner {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the ID of `s`, inserting it if it doesn't exist yet
+    pub fn intern(&mut self, s: &str) -> usize {
+        if let Some(id) = self.lookup(s) {
+            return id;
+        }
+
+        let id = self.file.insert(s.as_bytes());
+        self.table.insert(hash64(s.as_bytes()), id as u32);
+        id
+    }
+
+    /// Returns the ID of `s` if it has been interned
+    #[inline]
+    pub fn lookup(&self, s: &str) -> Option<usize> {
+        let id = self.table.find(hash64(s.as_bytes()), |id| {
+            self.file.get_unchecked(id as usize) == s.as_bytes()
+        })?;
+        Some(id as usize)
+    }
+
+    /// Returns the string with the given ID
+    #[inline]
+    pub fn resolve(&self, id: usize) -> Option<&str> {
+        std::str::from_utf8(self.file.get(id)?).ok()
+    }
+
+    /// Returns the amount of interned strings
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.file.len()
+    }
+
+    /// Returns `true` if there are no interned strings
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.file.is_empty()
+    }
+
+    /// Returns the underlying file
+    #[inline]
+    pub fn file(&self) -> &MemFile {
+        &self.file
+    }
+
+    /// Saves the interner to `path`. The saved file can be opened as a regular file too.
+    pub fn save<P: AsRef<Path>>(&self, path: P, encoding: IndexEncoding) -> Result<(), Error> {
+        let out = BufWriter::new(File::create(path)?);
+        self.write_to(out, encoding)
+    }
+
+    /// Writes the interner including its lookup table
+    pub fn write_to<W: Write>(&self, w: W, encoding: IndexEncoding) -> Result<(), Error> {
+        let mut table = vec![];
+        self.table.write_to(&mut table)?;
+        self.file
+            .write_with_sections(w, encoding, &[(section::HASH_TABLE, &table)])
+    }
+
+    /// Loads an interner from `path`. If the file doesn't contain a lookup table, eg. because
+    /// it has been saved as regular `MemFile`, the table gets rebuilt.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let bytes = std::fs::read(path)?;
+        let layout = Layout::parse(&bytes)?;
+        let file = MemFile::from_layout(&bytes, &layout)?;
+
+        if let Some(table) = layout.section(section::HASH_TABLE) {
+            let table = HashTable::decode(&bytes[table.range()])?;
+            table.check_ids(file.len())?;
+            return Ok(Self { file, table });
+        }
+
+        let mut interner = Self {
+            file,
+            table: HashTable::new(),
+        };
+        for id in 0..interner.file.len() {
+            let s = std::str::from_utf8(interner.file.get_unchecked(id)).map_err(invalid_data)?;
+            if interner.lookup(s).is_none() {
+                interner.table.insert(hash64(s.as_bytes()), id as u32);
+            }
+        }
+        Ok(interner)
+    }
+}
+
+impl<S: AsRef<str>> Extend<S> for StringInterner {
+    #[inline]
+    fn extend<T: IntoIterator<Item = S>>(&mut self, iter: T) {
+        for s in iter {
+            self.intern(s.as_ref());
+        }
+    }
+}
+
+/// Read only [`StringInterner`] backed by a [`MappedFile`]. Lookups are done using the
+/// persisted table directly from the mapping.
+#[cfg(feature = "mapped")]
+pub struct MappedInterner {
+    file: MappedFile,
+    /// Range of the validated lookup table within the mapping
+    table: Range<usize>,
+}
+
+#[cfg(feature = "mapped")]
+impl MappedInterner {
+    /// Opens an interner saved with `StringInterner::save`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = MappedFile::open(path)?;
+        let table = file
+            .section_range(section::HASH_TABLE)
+            .ok_or_else(|| invalid_data("Missing lookup table"))?;
+        HashTableRef::new(file.bytes(table.clone()))?;
+        Ok(Self { file, table })
+    }
+
+    /// Returns the ID of `s` if it has been interned
+    #[inline]
+    pub fn lookup(&self, s: &str) -> Option<usize> {
+        let table = HashTableRef::new_unchecked(self.file.bytes(self.table.clone()));
+        let id = table.find(hash64(s.as_bytes()), |id| {
+            self.file.get(id as usize) == Some(s.as_bytes())
+        })?;
+        Some(id as usize)
+    }
+
+    /// Returns the string with the given ID
+    #[inline]
+    pub fn resolve(&self, id: usize) -> Option<&str> {
+        std::str::from_utf8(self.file.get(id)?).ok()
+    }
+
+    /// Returns the amount of interned strings
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.file.len()
+    }
+
+    /// Returns `true` if there are no interned strings
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.file.is_empty()
+    }
+
+    /// Returns the underlying file
+    #[inline]
+    pub fn file(&self) -> &MappedFile {
+        &self.file
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::read_to_string;
+
+    fn words() -> Vec<String> {
+        let content = read_to_string("./testfiles/LICENSE").unwrap();
+        content.split_whitespace().map(|i| i.to_string()).collect()
+    }
+
+    #[test]
+    fn test_intern() {
+        let words = words();
+        let mut interner = StringInterner::new();
+
+        let ids: Vec<_> = words.iter().map(|i| interner.intern(i)).collect();
+        assert!(interner.len() < words.len());
+
+        for (word, id) in words.iter().zip(ids.iter()) {
+            assert_eq!(interner.lookup(word), Some(*id));
+            assert_eq!(interner.resolve(*id), Some(word.as_str()));
+            assert_eq!(interner.intern(word), *id);
+        }
+
+        assert_eq!(interner.lookup("not in the license"), None);
+    }
+
+    #[test]
+    fn test_save_load() {
+        let words = words();
+        let mut interner = StringInterner::new();
+        interner.extend(words.iter());
+
+        interner
+            .save("test_interner_save_load", IndexEncoding::EliasFano)
+            .unwrap();
+        let loaded = StringInterner::load("test_interner_save_load").unwrap();
+        check(&interner, |s| loaded.lookup(s), |id| loaded.resolve(id));
+
+        // Without lookup table
+        interner
+            .file()
+            .save("test_interner_save_load", IndexEncoding::Plain)
+            .unwrap();
+        let loaded = StringInterner::load("test_interner_save_load").unwrap();
+        check(&interner, |s| loaded.lookup(s), |id| loaded.resolve(id));
+
+        // Lookup table referencing strings that don't exist
+        let mut table = HashTable::new();
+        table.insert(hash64(b"x"), interner.len() as u32);
+        let mut buf = vec![];
+        table.write_to(&mut buf).unwrap();
+        let mut out = vec![];
+        interner
+            .file()
+            .write_with_sections(
+                &mut out,
+                IndexEncoding::Plain,
+                &[(section::HASH_TABLE, &buf)],
+            )
+            .unwrap();
+        std::fs::write("test_interner_save_load", out).unwrap();
+        assert!(StringInterner::load("test_interner_save_load").is_err());
+
+        std::fs::remove_file("test_interner_save_load").unwrap();
+    }
+
+    #[cfg(feature = "mapped")]
+    #[test]
+    fn test_mapped() {
+        let words = words();
+        let mut interner = StringInterner::new();
+        interner.extend(words.iter());
+
+        interner
+            .save("test_interner_mapped", IndexEncoding::Plain)
+            .unwrap();
+        let mapped = MappedInterner::open("test_interner_mapped").unwrap();
+        assert_eq!(mapped.len(), interner.len());
+        check(&interner, |s| mapped.lookup(s), |id| mapped.resolve(id));
+        assert_eq!(mapped.lookup("not in the license"), None);
+
+        interner
+            .file()
+            .save("test_interner_mapped", IndexEncoding::Plain)
+            .unwrap();
+        assert!(MappedInterner::open("test_interner_mapped").is_err());
+
+        std::fs::remove_file("test_interner_mapped").unwrap();
+    }
+
+    fn check<'a, L, R>(exp: &StringInterner, lookup: L, resolve: R)
+    where
+        L: Fn(&str) -> Option<usize>,
+        R: Fn(usize) -> Option<&'a str>,
+    {
+        for id in 0..exp.len() {
+            let s = exp.resolve(id).unwrap();
+            assert_eq!(resolve(id), Some(s));
+            assert_eq!(lookup(s), Some(id));
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use crate::traits::IndexedAccess;
+use std::ops::Range;
 
 /// Iterator over all entries of an indexed file
 pub struct IndexedAccessIter<'a, I> {
@@ -21,6 +22,17 @@ where
             end,
         }
     }
+
+    /// Creates an iterator over the entries within `range`
+    #[inline]
+    pub(crate) fn with_range(file: &'a I, range: Range<usize>) -> Self {
+        let end = range.end.min(file.len());
+        Self {
+            file,
+            start: range.start.min(end),
+            end,
+        }
+    }
 }
 
 impl<'a, I> ExactSizeIterator for IndexedAccessIter<'a, I>
@@ -29,7 +41,7 @@ where
 {
     #[inline]
     fn len(&self) -> usize {
-        I::len(self.file)
+        self.end - self.start
     }
 }
 
@@ -41,10 +53,19 @@ where
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
+        if self.start >= self.end {
+            return None;
+        }
+
         let content = self.file.get(self.start)?;
         self.start += 1;
         Some(content)
     }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len(), Some(self.len()))
+    }
 }
 
 impl<'a, I> DoubleEndedIterator for IndexedAccessIter<'a, I>
@@ -53,11 +74,10 @@ where
 {
     #[inline]
     fn next_back(&mut self) -> Option<Self::Item> {
-        if self.end == 0 {
+        if self.end <= self.start {
             return None;
         }
 
-        // we use 0 to indicate that the iterator is done
         let item = self.file.get(self.end - 1)?;
         self.end -= 1;
         Some(item)
@@ -106,5 +126,17 @@ mod tests {
             let get = idx.get(id).unwrap();
             assert_eq!(exp, get);
         }
+
+        let mut iter = idx.iter_range(2..5);
+        assert_eq!(iter.len(), 3);
+        assert_eq!(iter.next(), Some(&inp[2][..]));
+        assert_eq!(iter.next_back(), Some(&inp[4][..]));
+        assert_eq!(iter.next(), Some(&inp[3][..]));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+
+        assert_eq!(idx.iter_range(7..).count(), inp.len() - 7);
+        assert_eq!(idx.iter_range(..100).count(), inp.len());
+        assert_eq!(idx.iter_range(20..30).count(), 0);
     }
 }
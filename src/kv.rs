@@ -0,0 +1,394 @@
+use crate::{
+    bloom::BloomFilter,
+    encoded_index::IndexEncoding,
+    format::{section, Layout},
+    hash::hash64,
+    hash_table::HashTable,
+    memory::MemFile,
+    traits::{ContainerFile, IndexedAccess, IndexedAccessMut},
+};
+use std::{
+    fs::File,
+    io::{BufWriter, Error, Write},
+    path::Path,
+};
+
+#[cfg(feature = "mapped")]
+use crate::{format::invalid_data, hash_table::HashTableRef, map::MappedFile};
+
+/// A key-value store that keeps (key, value) pairs as entries of a [`MemFile`] and finds them
+/// using a hash table that gets persisted with the file. Each pair has an ID that stays the
+/// same when its value gets replaced.
+///
+/// Entries are encoded as little endian u32 key length, followed by the key and the value.
+/// Removed pairs are kept as empty entries so IDs of other pairs don't change.
+#[derive(Clone, Default)]
+pub struct KvFile {
+    file: MemFile,
+    table: HashTable,
+    len: usize,
+}
+
+impl KvFile {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts a key-value pair and returns its ID. If the key already exists its value gets
+    /// replaced and the existing ID is returned.
+    pub fn insert(&mut self, key: &[u8], value: &[u8]) -> usize {
+        if let Some(id) = self.replace(key, value) {
+            return id;
+        }
+
+        let id = self.file.insert(&encode_entry(key, value));
+        self.table.insert(hash64(key), id as u32);
+        self.len += 1;
+        id
+    }
+
+    /// Replaces the value of an existing key. Returns the pairs ID or `None` if the key
+    /// doesn't exist.
+    pub fn replace(&mut self, key: &[u8], value: &[u8]) -> Option<usize> {
+        let id = self.get_id(key)?;
+        self.file.replace(id, &encode_entry(key, value))?;
+        Some(id)
+    }
+
+    /// Removes a key-value pair. Returns the ID the pair had or `None` if the key doesn't exist
+    pub fn remove(&mut self, key: &[u8]) -> Option<usize> {
+        let id = self.get_id(key)?;
+        self.table.remove(hash64(key), id as u32);
+        self.file.replace(id, &[])?;
+        self.len -= 1;
+        Some(id)
+    }
+
+    /// Returns the value of the given key
+    #[inline]
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        let id = self.get_id(key)?;
+        Some(decode_entry(self.file.get_unchecked(id))?.1)
+    }
+
+    /// Returns the ID of the given key
+    #[inline]
+    pub fn get_id(&self, key: &[u8]) -> Option<usize> {
+        let id = self.table.find(hash64(key), |id| {
+            decode_entry(self.file.get_unchecked(id as usize)).map(|i| i.0) == Some(key)
+        })?;
+        Some(id as usize)
+    }
+
+    /// Returns `true` if the key exists
+    #[inline]
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.get_id(key).is_some()
+    }
+
+    /// Returns the key-value pair with the given ID
+    #[inline]
+    pub fn entry(&self, id: usize) -> Option<(&[u8], &[u8])> {
+        decode_entry(self.file.get(id)?)
+    }
+
+    /// Returns an iterator over all key-value pairs
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], &[u8])> {
+        self.file.iter().filter_map(decode_entry)
+    }
+
+    /// Returns the amount of key-value pairs
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if there are no key-value pairs
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Saves the file including its hash table
+    pub fn save<P: AsRef<Path>>(&self, path: P, encoding: IndexEncoding) -> Result<(), Error> {
+        let out = BufWriter::new(File::create(path)?);
+        self.write_to(out, encoding)
+    }
+
+    /// Writes the file including its hash table
+    #[inline]
+    pub fn write_to<W: Write>(&self, w: W, encoding: IndexEncoding) -> Result<(), Error> {
+        self.write_with_sections(w, encoding, &[])
+    }
+
+    /// Builds a bloom filter over all keys, which can be saved with the file using
+    /// `BloomFilter::save_with` and used by [`MappedKvFile::bloom_filter`]
+    pub fn key_filter(&self, fp_rate: f64) -> BloomFilter {
+        let mut filter = BloomFilter::new(self.len, fp_rate);
+        for (key, _) in self.iter() {
+            filter.insert(key);
+        }
+        filter
+    }
+
+    /// Loads a file saved with `KvFile::save`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let bytes = std::fs::read(path)?;
+        let layout = Layout::parse(&bytes)?;
+        layout.required_section(section::KEY_VALUE)?;
+
+        let table = layout.required_section(section::HASH_TABLE)?;
+        let table = HashTable::decode(&bytes[table.range()])?;
+        let file = MemFile::from_layout(&bytes, &layout)?;
+        table.check_ids(file.len())?;
+        let len = file.iter().filter(|i| !i.is_empty()).count();
+
+        Ok(Self { file, table, len })
+    }
+}
+
+impl ContainerFile for KvFile {
+    fn write_with_sections<W: Write>(
+        &self,
+        w: W,
+        encoding: IndexEncoding,
+        sections: &[(u32, &[u8])],
+    ) -> Result<(), Error> {
+        let mut table = vec![];
+        self.table.write_to(&mut table)?;
+        let mut all = vec![(section::KEY_VALUE, &[][..]), (section::HASH_TABLE, &table)];
+        all.extend_from_slice(sections);
+        self.file.write_with_sections(w, encoding, &all)
+    }
+}
+
+/// Read only [`KvFile`] backed by a [`MappedFile`]. Lookups use the persisted hash table
+/// directly from the mapping without loading it into memory.
+#[cfg(feature = "mapped")]
+pub struct MappedKvFile {
+    file: MappedFile,
+}
+
+#[cfg(feature = "mapped")]
+impl MappedKvFile {
+    /// Opens a file saved with `KvFile::save`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = MappedFile::open(path)?;
+        if file.section(section::KEY_VALUE).is_none() {
+            return Err(invalid_data("Not a key-value file"));
+        }
+
+        let table = file
+            .section(section::HASH_TABLE)
+            .ok_or_else(|| invalid_data("Missing hash table"))?;
+        HashTableRef::new(table)?.check_ids(file.len())?;
+
+        Ok(Self { file })
+    }
+
+    /// Returns the value of the given key
+    #[inline]
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        let id = self.get_id(key)?;
+        Some(decode_entry(self.file.get(id)?)?.1)
+    }
+
+    /// Returns the ID of the given key
+    #[inline]
+    pub fn get_id(&self, key: &[u8]) -> Option<usize> {
+        let table = HashTableRef::new(self.file.section(section::HASH_TABLE)?).ok()?;
+        let id = table.find(hash64(key), |id| {
+            self.file
+                .get(id as usize)
+                .and_then(decode_entry)
+                .map(|i| i.0)
+                == Some(key)
+        })?;
+        Some(id as usize)
+    }
+
+    /// Returns `true` if the key exists
+    #[inline]
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.get_id(key).is_some()
+    }
+
+    /// Returns the key-value pair with the given ID
+    #[inline]
+    pub fn entry(&self, id: usize) -> Option<(&[u8], &[u8])> {
+        decode_entry(self.file.get(id)?)
+    }
+
+    /// Returns an iterator over all key-value pairs
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], &[u8])> {
+        self.file.iter().filter_map(decode_entry)
+    }
+
+    /// Returns the bloom filter saved with the file, eg. built using [`KvFile::key_filter`].
+    /// The filter is used directly from the mapping. Returns `None` if the file doesn't
+    /// contain a filter.
+    #[inline]
+    pub fn bloom_filter(&self) -> Option<Result<BloomFilter<&[u8]>, Error>> {
+        self.file.bloom_filter()
+    }
+}
+
+/// Encodes a key-value pair as single entry
+#[inline]
+fn encode_entry(key: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(4 + key.len() + value.len());
+    out.extend_from_slice(&(key.len() as u32).to_le_bytes());
+    out.extend_from_slice(key);
+    out.extend_from_slice(value);
+    out
+}
+
+/// Decodes an entry into its key and value. Returns `None` for removed or invalid entries
+#[inline]
+fn decode_entry(entry: &[u8]) -> Option<(&[u8], &[u8])> {
+    let key_len = u32::from_le_bytes(entry.get(..4)?.try_into().unwrap()) as usize;
+    let rest = &entry[4..];
+    if key_len > rest.len() {
+        return None;
+    }
+    Some(rest.split_at(key_len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::HashMap, fs::read_to_string};
+
+    fn pairs() -> Vec<(String, String)> {
+        let content = read_to_string("./testfiles/LICENSE").unwrap();
+        content
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (format!("line{i}"), line.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_kv() {
+        let pairs = pairs();
+        let mut kv = KvFile::new();
+        let mut exp = HashMap::new();
+
+        for (key, value) in pairs.iter() {
+            kv.insert(key.as_bytes(), value.as_bytes());
+            exp.insert(key.clone(), value.clone());
+        }
+        check(&kv, &exp);
+
+        // Replace every second and remove every third pair
+        for (i, (key, _)) in pairs.iter().enumerate() {
+            if i % 2 == 0 {
+                let id = kv.get_id(key.as_bytes());
+                assert_eq!(kv.insert(key.as_bytes(), b"new value"), id.unwrap());
+                exp.insert(key.clone(), "new value".to_string());
+            }
+            if i % 3 == 0 {
+                assert!(kv.remove(key.as_bytes()).is_some());
+                assert!(kv.remove(key.as_bytes()).is_none());
+                exp.remove(key);
+            }
+        }
+        check(&kv, &exp);
+
+        assert_eq!(kv.replace(b"line0", b"value"), None);
+        assert!(kv.get(b"").is_none());
+        kv.insert(b"", b"empty key");
+        assert_eq!(kv.get(b""), Some(&b"empty key"[..]));
+    }
+
+    #[test]
+    fn test_save_load() {
+        let mut kv = KvFile::new();
+        let mut exp = HashMap::new();
+        for (key, value) in pairs().into_iter().step_by(2) {
+            kv.insert(key.as_bytes(), value.as_bytes());
+            exp.insert(key, value);
+        }
+        kv.remove(b"line0");
+        exp.remove("line0");
+
+        kv.save("test_kv_save_load", IndexEncoding::Plain).unwrap();
+        let loaded = KvFile::load("test_kv_save_load").unwrap();
+        check(&loaded, &exp);
+
+        #[cfg(feature = "mapped")]
+        {
+            let mapped = MappedKvFile::open("test_kv_save_load").unwrap();
+            for (key, value) in exp.iter() {
+                assert_eq!(mapped.get(key.as_bytes()), Some(value.as_bytes()));
+            }
+            assert_eq!(mapped.get(b"line0"), None);
+            assert_eq!(mapped.get(b"line1"), None);
+            assert_eq!(mapped.iter().count(), exp.len());
+            assert!(mapped.bloom_filter().is_none());
+        }
+
+        // Saved together with a bloom filter over the keys
+        let filter = kv.key_filter(0.01);
+        assert!(exp.keys().all(|i| filter.may_contain(i.as_bytes())));
+        filter
+            .save_with(&kv, "test_kv_save_load", IndexEncoding::EliasFano)
+            .unwrap();
+        check(&KvFile::load("test_kv_save_load").unwrap(), &exp);
+
+        #[cfg(feature = "mapped")]
+        {
+            let mapped = MappedKvFile::open("test_kv_save_load").unwrap();
+            let mapped_filter = mapped.bloom_filter().unwrap().unwrap();
+            assert_eq!(mapped_filter.as_bytes(), filter.as_bytes());
+            for (key, value) in exp.iter() {
+                assert!(mapped_filter.may_contain(key.as_bytes()));
+                assert_eq!(mapped.get(key.as_bytes()), Some(value.as_bytes()));
+            }
+        }
+
+        std::fs::remove_file("test_kv_save_load").unwrap();
+    }
+
+    #[test]
+    fn test_invalid_table() {
+        let mut kv = KvFile::new();
+        kv.insert(b"a", b"b");
+
+        // Hash table pointing behind the last entry
+        let mut table = HashTable::new();
+        table.insert(hash64(b"x"), 1);
+        let mut buf = vec![];
+        table.write_to(&mut buf).unwrap();
+        let sections = [(section::KEY_VALUE, &[][..]), (section::HASH_TABLE, &buf)];
+        let mut out = vec![];
+        kv.file
+            .write_with_sections(&mut out, IndexEncoding::Plain, &sections)
+            .unwrap();
+        std::fs::write("test_kv_invalid_table", out).unwrap();
+
+        assert!(KvFile::load("test_kv_invalid_table").is_err());
+        #[cfg(feature = "mapped")]
+        assert!(MappedKvFile::open("test_kv_invalid_table").is_err());
+        std::fs::remove_file("test_kv_invalid_table").unwrap();
+    }
+
+    fn check(kv: &KvFile, exp: &HashMap<String, String>) {
+        assert_eq!(kv.len(), exp.len());
+        assert_eq!(kv.iter().count(), exp.len());
+
+        for (key, value) in exp.iter() {
+            assert_eq!(kv.get(key.as_bytes()), Some(value.as_bytes()));
+            let id = kv.get_id(key.as_bytes()).unwrap();
+            assert_eq!(kv.entry(id), Some((key.as_bytes(), value.as_bytes())));
+        }
+
+        for (key, value) in kv.iter() {
+            let key = std::str::from_utf8(key).unwrap();
+            assert_eq!(exp.get(key).unwrap().as_bytes(), value);
+        }
+    }
+}
@@ -1,16 +1,82 @@
+mod bits;
+pub mod bloom;
+pub mod dedup;
+pub mod encoded_index;
+pub mod export;
+#[cfg(feature = "mapped")]
+pub mod ext_sort;
+mod format;
+mod hash;
+mod hash_table;
+pub mod import;
+pub mod info;
+pub mod interner;
 pub mod iter;
+pub mod kv;
 pub mod mem_index;
 pub mod memory;
+pub mod merge;
+pub mod patch;
+#[cfg(feature = "typed")]
+pub mod secondary;
+pub mod sorted;
+pub mod split;
 pub mod traits;
+pub mod trigram;
 #[cfg(feature = "typed")]
 pub mod typed_iter;
 pub mod vec;
+pub mod versioned;
+pub mod writer;
 
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(feature = "encryption")]
+pub mod encrypted;
+#[cfg(feature = "fst")]
+pub mod fst_index;
 #[cfg(feature = "mapped")]
 pub mod map;
+#[cfg(feature = "merkle")]
+pub mod merkle;
+#[cfg(feature = "mapped")]
+pub mod segmented;
+#[cfg(feature = "mapped")]
+pub mod shared;
 
+pub use bloom::BloomFilter;
+pub use dedup::DedupFile;
+pub use encoded_index::IndexEncoding;
+pub use interner::StringInterner;
+pub use kv::KvFile;
 pub use memory::MemFile;
+#[cfg(feature = "typed")]
+pub use secondary::IndexedStore;
+pub use sorted::SortedFile;
+pub use trigram::{TextFile, TrigramIndex};
 pub use vec::VecFile;
+pub use versioned::VersionedFile;
+pub use writer::FileWriter;
 
+#[cfg(feature = "encryption")]
+pub use encrypted::EncryptedFile;
+#[cfg(all(feature = "encryption", feature = "mapped"))]
+pub use encrypted::MappedEncryptedFile;
+#[cfg(feature = "mapped")]
+pub use ext_sort::ExternalSorter;
+#[cfg(feature = "fst")]
+pub use fst_index::FstIndex;
+#[cfg(feature = "mapped")]
+pub use interner::MappedInterner;
+#[cfg(feature = "mapped")]
+pub use kv::MappedKvFile;
 #[cfg(feature = "mapped")]
 pub use map::MappedFile;
+#[cfg(feature = "merkle")]
+pub use merkle::MerkleTree;
+#[cfg(feature = "mapped")]
+pub use segmented::SegmentedFile;
+#[cfg(feature = "mapped")]
+pub use shared::SharedMappedFile;
+#[cfg(feature = "mapped")]
+pub use trigram::MappedTextFile;
@@ -1,8 +1,14 @@
-use crate::{mem_index::MemIndex, traits::IndexedAccess};
+use crate::{
+    bloom::BloomFilter,
+    encoded_index::{EncodedIndex, IndexEncoding},
+    format::{invalid_data, section, Layout, SectionEntry},
+    traits::{IndexedAccess, OffsetIndex, RawAccess},
+};
 use mmarinus::{perms, Map, Private};
 use std::{
     fs::File,
-    io::{BufReader, Error, Read, Seek, SeekFrom},
+    io::Error,
+    ops::Range,
     path::{Path, PathBuf},
 };
 
@@ -10,57 +16,156 @@ use std::{
 pub struct MappedFile {
     map: Map<perms::Read, Private>,
     path: PathBuf,
-    len: usize,
-    index: MemIndex,
+    data: Range<usize>,
+    index: MappedIndex,
+    sections: Vec<SectionEntry>,
+}
+
+/// Offset index of a mapped file
+enum MappedIndex {
+    /// Little endian u32 offsets that are read directly from the given range of the mapping
+    Plain(Range<usize>),
+    /// A compressed index, decoded into memory
+    Encoded(EncodedIndex),
 }
 
 impl MappedFile {
     /// Open a memory file mmapped
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
-        let index = Self::read_index(path.as_ref())?;
         let map = Self::open_map(path.as_ref())?;
+        let (index, layout) = Self::read_index(&map)?;
+        let data = layout.required_section(section::DATA)?.range();
         let path = path.as_ref().to_path_buf();
-        let len = File::open(&path)?.metadata()?.len() as usize;
         Ok(MappedFile {
             map,
             path,
+            data,
             index,
-            len,
+            sections: layout.sections,
         })
     }
 
-    /// Cleans the mapping by closing the old file and reopening it
+    /// Cleans the mapping by closing the old file and reopening it. The index gets reloaded
+    /// too, as its position within the file might have changed.
     pub fn reopen(&mut self) -> Result<(), Error> {
-        self.map = Self::open_map(&self.path)?;
+        let map = Self::open_map(&self.path)?;
+        let (index, layout) = Self::read_index(&map)?;
+        self.data = layout.required_section(section::DATA)?.range();
+        self.index = index;
+        self.sections = layout.sections;
+        self.map = map;
         Ok(())
     }
 
-    /// Reloads the data index
+    /// Reloads the data index. This remaps the file since the index is read from the mapping.
     #[inline]
     pub fn reload_index(&mut self) -> Result<(), Error> {
-        self.index = Self::read_index(&self.path)?;
-        Ok(())
+        self.reopen()
+    }
+
+    /// Returns the encoding of the files offset index
+    #[inline]
+    pub fn index_encoding(&self) -> IndexEncoding {
+        match &self.index {
+            MappedIndex::Plain(_) => IndexEncoding::Plain,
+            MappedIndex::Encoded(index) => index.encoding(),
+        }
+    }
+
+    /// Returns the amount of heap memory used by the offset index in bytes. Plain indexes are
+    /// read from the mapping and don't use any heap memory.
+    #[inline]
+    pub fn index_heap_size(&self) -> usize {
+        match &self.index {
+            MappedIndex::Plain(_) => 0,
+            MappedIndex::Encoded(index) => index.heap_size(),
+        }
+    }
+
+    /// Returns the data offset of the entry with the given ID
+    #[inline]
+    fn offset(&self, id: usize) -> Option<u32> {
+        match &self.index {
+            MappedIndex::Plain(range) => {
+                let start = range.start.checked_add(id.checked_mul(4)?)?;
+                if start + 4 > range.end {
+                    return None;
+                }
+                let bytes = &self.map[start..start + 4];
+                Some(u32::from_le_bytes(bytes.try_into().unwrap()))
+            }
+            MappedIndex::Encoded(index) => index.offset(id),
+        }
+    }
+
+    /// Returns the bloom filter saved with the file using `BloomFilter::save_with`. The filter
+    /// is used directly from the mapping. Returns `None` if the file doesn't contain a filter.
+    #[inline]
+    pub fn bloom_filter(&self) -> Option<Result<BloomFilter<&[u8]>, Error>> {
+        Some(BloomFilter::from_bytes(self.section(section::BLOOM)?))
+    }
+
+    /// Returns the merkle tree saved with the file using `MerkleTree::save_with`. The tree is
+    /// used directly from the mapping. Returns `None` if the file doesn't contain a tree.
+    #[cfg(feature = "merkle")]
+    #[inline]
+    pub fn merkle_tree(&self) -> Option<Result<crate::merkle::MerkleTree<&[u8]>, Error>> {
+        Some(crate::merkle::MerkleTree::from_bytes(
+            self.section(section::MERKLE)?,
+        ))
+    }
+
+    /// Returns the content of the first section of the given kind
+    #[inline]
+    pub(crate) fn section(&self, kind: u32) -> Option<&[u8]> {
+        Some(&self.map[self.section_range(kind)?])
+    }
+
+    /// Returns the byte range of the first section of the given kind within the mapping
+    #[inline]
+    pub(crate) fn section_range(&self, kind: u32) -> Option<Range<usize>> {
+        let section = self.sections.iter().find(|i| i.kind == kind)?;
+        Some(section.range())
+    }
+
+    /// Returns the given range of the mapping
+    #[inline]
+    pub(crate) fn bytes(&self, range: Range<usize>) -> &[u8] {
+        &self.map[range]
     }
 
-    /// Decodes the index of a file
-    fn read_index<P: AsRef<Path>>(path: P) -> Result<MemIndex, Error> {
-        let mut reader = BufReader::new(File::open(path)?);
-        let mut index_len_buf = [0u8; 8];
-        reader.read_exact(&mut index_len_buf[..])?;
-        let index_len = u64::from_le_bytes(index_len_buf);
+    /// Reads the index of a file and returns it together with the files layout. Plain
+    /// indexes are not copied but read from the mapping on access.
+    fn read_index(map: &[u8]) -> Result<(MappedIndex, Layout), Error> {
+        let layout = Layout::parse(map)?;
 
-        reader.seek(SeekFrom::Start(0))?;
+        let index_section = layout.required_section(section::INDEX)?;
+        let encoding = IndexEncoding::from_param(index_section.param)
+            .ok_or_else(|| invalid_data("Unknown index encoding"))?;
 
-        let to_read = index_len as usize * 4 + 8;
-        let mut buf = vec![0u8; to_read];
-        reader.read_exact(&mut buf[..])?;
+        let index = match encoding {
+            IndexEncoding::Plain => {
+                let len = (layout.entries as u64).checked_mul(4);
+                if len != Some(index_section.len) {
+                    return Err(invalid_data("Index length mismatch"));
+                }
+                MappedIndex::Plain(index_section.range())
+            }
+            encoding => {
+                let index = EncodedIndex::decode(encoding, &map[index_section.range()])?;
+                if index.len() != layout.entries {
+                    return Err(invalid_data("Index length mismatch"));
+                }
+                MappedIndex::Encoded(index)
+            }
+        };
 
-        Ok(bincode::deserialize(&buf).unwrap())
+        Ok((index, layout))
     }
 
     /// Opens a file as Mapped file
     #[inline]
-    fn open_map<P: AsRef<Path>>(path: P) -> Result<Map<perms::Read, Private>, Error> {
+    pub(crate) fn open_map<P: AsRef<Path>>(path: P) -> Result<Map<perms::Read, Private>, Error> {
         let mut file = File::open(path)?;
         let size = file.metadata()?.len() as usize;
         let map = Map::bytes(size)
@@ -74,20 +179,17 @@ impl MappedFile {
 
 impl IndexedAccess for MappedFile {
     fn get(&self, pos: usize) -> Option<&[u8]> {
-        let index_bytes = self.index.bytes_len();
-
-        // self.len is tot-length with index
-        // There are 8 bytes after index for the length of the encoded vec
-        let data_end = self.len - index_bytes - 8;
-        let range = self.index.index_item(pos, data_end)?;
+        let start = self.offset(pos)? as usize;
+        let end = self
+            .offset(pos + 1)
+            .map(|i| i as usize)
+            .unwrap_or(self.data.len());
 
-        // Add index and data-vec-offset
-        let data_offset = index_bytes + 8;
-
-        let start = range.start + data_offset;
-        let end = range.end + data_offset;
+        if start > end || end > self.data.len() {
+            return None;
+        }
 
-        Some(&self.map[start..end])
+        self.map.get(self.data.start + start..self.data.start + end)
     }
 
     #[inline]
@@ -97,7 +199,27 @@ impl IndexedAccess for MappedFile {
 
     #[inline]
     fn len(&self) -> usize {
-        self.index.len()
+        match &self.index {
+            MappedIndex::Plain(range) => range.len() / 4,
+            MappedIndex::Encoded(index) => index.len(),
+        }
+    }
+
+    #[inline]
+    fn as_raw(&self) -> Option<&dyn RawAccess> {
+        Some(self)
+    }
+}
+
+impl RawAccess for MappedFile {
+    #[inline]
+    fn raw_data(&self) -> &[u8] {
+        &self.map[self.data.clone()]
+    }
+
+    #[inline]
+    fn raw_offset(&self, id: usize) -> Option<u32> {
+        self.offset(id)
     }
 }
 
@@ -106,8 +228,9 @@ mod test {
     use std::{fs::File, io::BufWriter};
 
     use crate::{
-        traits::{TypedIndexedAccess, TypedIndexedAccessMut},
-        MappedFile, MemFile,
+        format::{section, ContainerWriter},
+        traits::{IndexedAccess, TypedIndexedAccess, TypedIndexedAccessMut},
+        IndexEncoding, MappedFile, MemFile,
     };
 
     #[test]
@@ -123,7 +246,11 @@ mod test {
 
         let mapped = MappedFile::open("test_mapped_file_test").unwrap();
 
-        assert_eq!(mapped.index.inner, mem.index.inner);
+        assert_eq!(mapped.index_heap_size(), 0);
+        for (id, offset) in mem.index.inner.iter().enumerate() {
+            assert_eq!(mapped.offset(id), Some(*offset));
+        }
+        assert_eq!(mapped.offset(mem.len()), None);
 
         let mut mapped_iter = mem.iter_typed::<u32>();
         for i in mapped.iter_typed::<u32>() {
@@ -137,4 +264,47 @@ mod test {
 
         std::fs::remove_file("test_mapped_file_test").unwrap();
     }
+
+    #[test]
+    fn test_open_encoded() {
+        let mut mem = MemFile::new();
+        for i in (0..100_000u32).step_by(7) {
+            mem.insert_typed(&format!("entry {i}")).unwrap();
+        }
+
+        for (encoding, path) in [
+            (IndexEncoding::Plain, "test_mapped_plain"),
+            (IndexEncoding::BlockPacked, "test_mapped_block_packed"),
+            (IndexEncoding::EliasFano, "test_mapped_elias_fano"),
+        ] {
+            mem.save(path, encoding).unwrap();
+
+            let mapped = MappedFile::open(path).unwrap();
+            assert_eq!(mapped.index_encoding(), encoding);
+            assert_eq!(mapped.len(), mem.len());
+            assert!(mapped.iter().eq(mem.iter()));
+            assert_eq!(mapped.get(mem.len()), None);
+
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_invalid_offsets() {
+        // Offsets that are not monotonic or point past the data section
+        let mut writer = ContainerWriter::new(vec![]).unwrap();
+        writer.write_section(section::DATA, 0, b"abcd").unwrap();
+        writer
+            .write_u32_section(section::INDEX, IndexEncoding::Plain.param(), &[3, 1, 8])
+            .unwrap();
+        let bytes = writer.finish(3).unwrap();
+        std::fs::write("test_mapped_invalid_offsets", bytes).unwrap();
+
+        let mapped = MappedFile::open("test_mapped_invalid_offsets").unwrap();
+        assert_eq!(mapped.get(0), None);
+        assert_eq!(mapped.get(1), None);
+        assert_eq!(mapped.get(2), None);
+
+        std::fs::remove_file("test_mapped_invalid_offsets").unwrap();
+    }
 }
@@ -1,3 +1,4 @@
+use crate::traits::OffsetIndex;
 use serde::{Deserialize, Serialize};
 use std::ops::Range;
 
@@ -98,6 +99,28 @@ impl MemIndex {
     }
 }
 
+impl OffsetIndex for MemIndex {
+    #[inline]
+    fn offset(&self, id: usize) -> Option<u32> {
+        self.get(id)
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    #[inline]
+    fn data_range(&self, id: usize, end: usize) -> Option<Range<usize>> {
+        self.index_item(id, end)
+    }
+
+    #[inline]
+    fn heap_size(&self) -> usize {
+        self.inner.capacity() * 4
+    }
+}
+
 impl From<Vec<u32>> for MemIndex {
     #[inline]
     fn from(inner: Vec<u32>) -> Self {
@@ -1,18 +1,98 @@
 use crate::{
+    encoded_index::{EncodedIndex, IndexEncoding},
+    format::{invalid_data, section, ContainerWriter, Layout},
     mem_index::MemIndex,
-    traits::{IndexedAccess, IndexedAccessMut},
+    traits::{ContainerFile, IndexedAccess, IndexedAccessMut, OffsetIndex, RawAccess},
 };
 use serde::{Deserialize, Serialize};
-use std::ops::Index;
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{BufWriter, Error, ErrorKind, Write},
+    ops::Index,
+    path::Path,
+};
 
 /// An In-memory indexable "file" that allows inserting, getting and replacing
 /// variable length [u8] arrays using an ID.
 #[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(try_from = "RawMemFile")]
 pub struct MemFile {
     pub(crate) index: MemIndex,
     pub(crate) data: Vec<u8>,
 }
 
+/// A deserialized `MemFile` whose offsets haven't been validated yet
+#[derive(Deserialize)]
+struct RawMemFile {
+    index: MemIndex,
+    data: Vec<u8>,
+}
+
+impl TryFrom<RawMemFile> for MemFile {
+    type Error = OffsetError;
+
+    #[inline]
+    fn try_from(raw: RawMemFile) -> Result<Self, Self::Error> {
+        Self::try_new_raw(raw.data, raw.index.inner)
+    }
+}
+
+/// Error for offsets that don't describe valid entries of a file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OffsetError {
+    /// The offset of entry `id` is smaller than the offset of the entry before
+    NotAscending {
+        id: usize,
+        offset: u32,
+        previous: u32,
+    },
+    /// The offset of entry `id` points behind the end of the data
+    OutOfBounds {
+        id: usize,
+        offset: u32,
+        data_len: usize,
+    },
+    /// The offset of entry `id` doesn't fit into the requested offset type
+    OutOfRange { id: usize },
+    /// Arrow offsets are missing the end offset of the last entry
+    MissingEnd,
+}
+
+impl Display for OffsetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotAscending {
+                id,
+                offset,
+                previous,
+            } => write!(
+                f,
+                "Offset {offset} of entry {id} is smaller than the previous offset {previous}"
+            ),
+            Self::OutOfBounds {
+                id,
+                offset,
+                data_len,
+            } => write!(
+                f,
+                "Offset {offset} of entry {id} exceeds the data length {data_len}"
+            ),
+            Self::OutOfRange { id } => write!(f, "Offset of entry {id} is out of range"),
+            Self::MissingEnd => write!(f, "Missing end offset"),
+        }
+    }
+}
+
+impl std::error::Error for OffsetError {}
+
+impl From<OffsetError> for Error {
+    #[inline]
+    fn from(err: OffsetError) -> Self {
+        Error::new(ErrorKind::InvalidData, err)
+    }
+}
+
 impl IndexedAccessMut for MemFile {
     #[inline]
     fn insert(&mut self, data: &[u8]) -> usize {
@@ -54,6 +134,23 @@ impl IndexedAccess for MemFile {
     fn len(&self) -> usize {
         self.index.len()
     }
+
+    #[inline]
+    fn as_raw(&self) -> Option<&dyn RawAccess> {
+        Some(self)
+    }
+}
+
+impl RawAccess for MemFile {
+    #[inline]
+    fn raw_data(&self) -> &[u8] {
+        &self.data
+    }
+
+    #[inline]
+    fn raw_offset(&self, id: usize) -> Option<u32> {
+        self.index.get(id)
+    }
 }
 
 impl MemFile {
@@ -70,19 +167,218 @@ impl MemFile {
         }
     }
 
+    /// Creates a new MemFile from its data and the start offset of each entry. Like
+    /// [`MemFile::new_raw_unchecked`], the offsets aren't validated. Use
+    /// [`MemFile::try_new_raw`] for untrusted input.
     #[inline]
     pub fn new_raw(data: Vec<u8>, index: Vec<u32>) -> Self {
+        Self::new_raw_unchecked(data, index)
+    }
+
+    /// Creates a new MemFile from its data and the start offset of each entry. Fails if the
+    /// offsets aren't ascending or point behind the end of `data`.
+    #[inline]
+    pub fn try_new_raw(data: Vec<u8>, index: Vec<u32>) -> Result<Self, OffsetError> {
+        validate_offsets(&index, data.len())?;
+        Ok(Self::new_raw_unchecked(data, index))
+    }
+
+    /// Creates a new MemFile from its data and the start offset of each entry without
+    /// validating the offsets. Invalid offsets make accessing entries panic, so this should
+    /// only be used for trusted input.
+    #[inline]
+    pub fn new_raw_unchecked(data: Vec<u8>, index: Vec<u32>) -> Self {
         Self {
             data,
             index: MemIndex::from(index),
         }
     }
 
+    /// Inverse of [`MemFile::into_raw_parts`], same as [`MemFile::try_new_raw`]
+    #[inline]
+    pub fn from_raw_parts(data: Vec<u8>, offsets: Vec<u32>) -> Result<Self, OffsetError> {
+        Self::try_new_raw(data, offsets)
+    }
+
+    /// Checks that all offsets are ascending and point into the data
+    #[inline]
+    pub fn validate(&self) -> Result<(), OffsetError> {
+        validate_offsets(&self.index.inner, self.data.len())
+    }
+
+    /// Creates a new MemFile from a buffer pair as used by Arrow's binary arrays: `offsets`
+    /// holds one offset more than there are entries, the last one being the end of the last
+    /// entry. Only the bytes between the first and last offset are kept.
+    pub fn from_arrow_offsets<O>(mut data: Vec<u8>, offsets: Vec<O>) -> Result<Self, OffsetError>
+    where
+        O: Copy + TryInto<u32>,
+    {
+        let offsets = offsets
+            .into_iter()
+            .enumerate()
+            .map(|(id, i)| i.try_into().map_err(|_| OffsetError::OutOfRange { id }))
+            .collect::<Result<Vec<u32>, _>>()?;
+        let Some((&end, offsets)) = offsets.split_last() else {
+            return Err(OffsetError::MissingEnd);
+        };
+        validate_offsets(offsets, end as usize)?;
+        if end as usize > data.len() {
+            return Err(OffsetError::OutOfBounds {
+                id: offsets.len(),
+                offset: end,
+                data_len: data.len(),
+            });
+        }
+
+        data.truncate(end as usize);
+        let first = offsets.first().copied().unwrap_or(end);
+        if first > 0 {
+            data.drain(..first as usize);
+        }
+        let offsets = offsets.iter().map(|i| i - first).collect();
+        Ok(Self::new_raw_unchecked(data, offsets))
+    }
+
+    /// Returns the data and the start offset of each entry without copying
+    #[inline]
+    pub fn into_raw_parts(self) -> (Vec<u8>, Vec<u32>) {
+        (self.data, self.index.inner)
+    }
+
+    /// Returns the data and the offsets of all entries in the layout of Arrow's binary
+    /// arrays, with an additional end offset. The data is moved, only the offsets get
+    /// converted. Fails if the data is too large for `O`.
+    pub fn into_arrow_offsets<O: TryFrom<u32>>(self) -> Result<(Vec<u8>, Vec<O>), OffsetError> {
+        let end = self.data.len() as u32;
+        let offsets = self
+            .index
+            .inner
+            .into_iter()
+            .chain(std::iter::once(end))
+            .enumerate()
+            .map(|(id, i)| O::try_from(i).map_err(|_| OffsetError::OutOfRange { id }))
+            .collect::<Result<_, _>>()?;
+        Ok((self.data, offsets))
+    }
+
+    /// Appends `data` containing multiple entries at once. `offsets` are the start offsets
+    /// of the entries within `data` and have to be monotonic.
+    pub(crate) fn insert_raw<I>(&mut self, data: &[u8], offsets: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = u32>,
+    {
+        let base = self.data.len();
+        if base + data.len() > u32::MAX as usize {
+            return Err(Error::new(ErrorKind::InvalidInput, "File too large"));
+        }
+        extend_offsets(&mut self.index.inner, base as u32, data.len(), offsets)?;
+        self.data.extend_from_slice(data);
+        Ok(())
+    }
+
     /// Returns the amount of bytes stored in the file
     #[inline]
     pub fn raw_len(&self) -> usize {
         self.data.len()
     }
+
+    /// Saves the file to `path` using the given encoding for the offset index. The saved
+    /// file can be opened with `MemFile::load` or `MappedFile::open`.
+    pub fn save<P: AsRef<Path>>(&self, path: P, encoding: IndexEncoding) -> Result<(), Error> {
+        let out = BufWriter::new(File::create(path)?);
+        self.write_to(out, encoding)
+    }
+
+    /// Writes the file in the container format using the given encoding for the offset index
+    #[inline]
+    pub fn write_to<W: Write>(&self, w: W, encoding: IndexEncoding) -> Result<(), Error> {
+        self.write_with_sections(w, encoding, &[])
+    }
+
+    /// Writes the file in the container format with additional sections
+    pub(crate) fn write_with_sections<W: Write>(
+        &self,
+        w: W,
+        encoding: IndexEncoding,
+        sections: &[(u32, &[u8])],
+    ) -> Result<(), Error> {
+        let mut writer = ContainerWriter::new(w)?;
+        writer.write_section(section::DATA, 0, &self.data)?;
+        EncodedIndex::write_section(&mut writer, &self.index.inner, encoding)?;
+
+        for (kind, data) in sections {
+            writer.write_section(*kind, 0, data)?;
+        }
+
+        writer.finish(self.len())?;
+        Ok(())
+    }
+
+    /// Loads a file that has been saved with `MemFile::save` or serialized using bincode
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// Decodes a file from its saved representation
+    #[inline]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Self::from_layout(bytes, &Layout::parse(bytes)?)
+    }
+
+    /// Decodes a file from its saved representation with an already parsed layout
+    pub(crate) fn from_layout(bytes: &[u8], layout: &Layout) -> Result<Self, Error> {
+        let index_section = layout.required_section(section::INDEX)?;
+        let encoding = IndexEncoding::from_param(index_section.param)
+            .ok_or_else(|| invalid_data("Unknown index encoding"))?;
+        let index = EncodedIndex::decode(encoding, &bytes[index_section.range()])?;
+        if index.len() != layout.entries {
+            return Err(invalid_data("Index length mismatch"));
+        }
+
+        let offsets = match index {
+            EncodedIndex::Plain(index) => index.inner,
+            index => (0..index.len()).map(|i| index.offset(i).unwrap()).collect(),
+        };
+
+        let data = bytes[layout.required_section(section::DATA)?.range()].to_vec();
+
+        Ok(Self::try_new_raw(data, offsets)?)
+    }
+}
+
+/// Checks that `offsets` are ascending and not larger than `data_len`
+fn validate_offsets(offsets: &[u32], data_len: usize) -> Result<(), OffsetError> {
+    let mut previous = 0;
+    for (id, offset) in offsets.iter().copied().enumerate() {
+        if offset < previous {
+            return Err(OffsetError::NotAscending {
+                id,
+                offset,
+                previous,
+            });
+        }
+        if offset as usize > data_len {
+            return Err(OffsetError::OutOfBounds {
+                id,
+                offset,
+                data_len,
+            });
+        }
+        previous = offset;
+    }
+    Ok(())
+}
+
+impl ContainerFile for MemFile {
+    #[inline]
+    fn write_with_sections<W: Write>(
+        &self,
+        w: W,
+        encoding: IndexEncoding,
+        sections: &[(u32, &[u8])],
+    ) -> Result<(), Error> {
+        MemFile::write_with_sections(self, w, encoding, sections)
+    }
 }
 
 impl<I: AsRef<[u8]>> Extend<I> for MemFile {
@@ -114,6 +410,30 @@ impl Index<usize> for MemFile {
     }
 }
 
+/// Appends `offsets` rebased by `base` to `index`. Fails without changing `index` if the
+/// offsets aren't monotonic or exceed `data_len`.
+pub(crate) fn extend_offsets<I>(
+    index: &mut Vec<u32>,
+    base: u32,
+    data_len: usize,
+    offsets: I,
+) -> Result<(), Error>
+where
+    I: IntoIterator<Item = u32>,
+{
+    let len = index.len();
+    let mut last = 0;
+    for offset in offsets {
+        if offset < last || offset as usize > data_len {
+            index.truncate(len);
+            return Err(invalid_data("Raw offsets are invalid"));
+        }
+        last = offset;
+        index.push(base + offset);
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use std::{collections::HashMap, fs::read_to_string};
@@ -214,6 +534,137 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_save_load() {
+        let content = read_to_string("./testfiles/LICENSE").unwrap();
+        let file = MemFile::from(content.split(' '));
+
+        for encoding in [
+            IndexEncoding::Plain,
+            IndexEncoding::BlockPacked,
+            IndexEncoding::EliasFano,
+        ] {
+            let mut buf = vec![];
+            file.write_to(&mut buf, encoding).unwrap();
+
+            let loaded = MemFile::from_bytes(&buf).unwrap();
+            assert_eq!(loaded.index.inner, file.index.inner);
+            assert_eq!(loaded.data, file.data);
+        }
+
+        // bincode encoded MemFile
+        let mut legacy = vec![];
+        legacy.extend((file.len() as u64).to_le_bytes());
+        legacy.extend(file.index.inner.iter().flat_map(|i| i.to_le_bytes()));
+        legacy.extend((file.raw_len() as u64).to_le_bytes());
+        legacy.extend(&file.data);
+        let loaded = MemFile::from_bytes(&legacy).unwrap();
+        assert_eq!(loaded.index.inner, file.index.inner);
+        assert_eq!(loaded.data, file.data);
+    }
+
+    #[test]
+    fn test_insert_raw() {
+        let mut file = MemFile::from(["a"].iter());
+        file.insert_raw(b"bcd", [0, 1, 3]).unwrap();
+        assert!(file.iter().eq([&b"a"[..], b"b", b"cd", b""]));
+
+        for offsets in [[0, 3, 2], [0, 1, 5]] {
+            let err = file.insert_raw(b"efgh", offsets).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+            assert_eq!(file.len(), 4);
+            assert_eq!(file.raw_len(), 4);
+        }
+    }
+
+    #[test]
+    fn test_raw_parts() {
+        let file = MemFile::from(test_data().iter());
+
+        let copy = file.clone();
+        let data_ptr = copy.data.as_ptr();
+        let (data, offsets) = copy.into_raw_parts();
+        assert_eq!(data.as_ptr(), data_ptr);
+        let back = MemFile::from_raw_parts(data, offsets).unwrap();
+        assert_eq!(back.data.as_ptr(), data_ptr);
+        assert!(back.iter().eq(file.iter()));
+
+        assert!(MemFile::from_raw_parts(vec![0; 4], vec![0, 3, 2]).is_err());
+        assert!(MemFile::from_raw_parts(vec![0; 4], vec![0, 5]).is_err());
+
+        let (data, offsets) = back.into_arrow_offsets::<i64>().unwrap();
+        assert_eq!(data.as_ptr(), data_ptr);
+        assert_eq!(offsets.len(), file.len() + 1);
+        assert_eq!(*offsets.last().unwrap(), file.raw_len() as i64);
+        let back = MemFile::from_arrow_offsets(data, offsets).unwrap();
+        assert!(back.iter().eq(file.iter()));
+
+        // Offsets not starting at zero and data beyond the last offset
+        let back = MemFile::from_arrow_offsets(b"xxabcdyy".to_vec(), vec![2i32, 3, 6]).unwrap();
+        assert!(back.iter().eq([&b"a"[..], b"bcd"]));
+        assert_eq!(back.raw_len(), 4);
+
+        assert_eq!(
+            MemFile::from_arrow_offsets(vec![0; 4], vec![0i32, -1]).err(),
+            Some(OffsetError::OutOfRange { id: 1 })
+        );
+        assert!(MemFile::from_arrow_offsets(vec![0; 4], vec![0i64, 5]).is_err());
+        assert_eq!(
+            MemFile::from_arrow_offsets(vec![], Vec::<i32>::new()).err(),
+            Some(OffsetError::MissingEnd)
+        );
+        assert!(MemFile::from_arrow_offsets(vec![], vec![0i32])
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_validate() {
+        let file = MemFile::try_new_raw(b"abcd".to_vec(), vec![0, 1, 1, 4]).unwrap();
+        assert!(file.iter().eq([&b"a"[..], b"", b"bcd", b""]));
+        assert!(file.validate().is_ok());
+
+        let err = MemFile::try_new_raw(b"abcd".to_vec(), vec![0, 3, 2]).err();
+        assert_eq!(
+            err,
+            Some(OffsetError::NotAscending {
+                id: 2,
+                offset: 2,
+                previous: 3
+            })
+        );
+        let err = MemFile::try_new_raw(b"abcd".to_vec(), vec![0, 5]).err();
+        assert_eq!(
+            err,
+            Some(OffsetError::OutOfBounds {
+                id: 1,
+                offset: 5,
+                data_len: 4
+            })
+        );
+
+        let file = MemFile::new_raw(b"abcd".to_vec(), vec![0, 5]);
+        assert!(file.validate().is_err());
+
+        // Corrupted offsets in a saved file
+        let mut buf = vec![];
+        file.write_to(&mut buf, IndexEncoding::Plain).unwrap();
+        assert_eq!(
+            MemFile::from_bytes(&buf).err().map(|i| i.kind()),
+            Some(ErrorKind::InvalidData)
+        );
+
+        #[cfg(feature = "typed")]
+        {
+            let bytes = bincode::serialize(&file).unwrap();
+            assert!(bincode::deserialize::<MemFile>(&bytes).is_err());
+            let valid = MemFile::from(test_data().iter());
+            let bytes = bincode::serialize(&valid).unwrap();
+            let back: MemFile = bincode::deserialize(&bytes).unwrap();
+            assert!(back.iter().eq(valid.iter()));
+        }
+    }
+
     fn test_from_iter(entries: &[&str]) {
         let new_file = MemFile::from(entries.iter());
 
@@ -0,0 +1,147 @@
+use crate::{
+    memory::MemFile,
+    traits::{IndexedAccess, IndexedAccessMut, RawAccess},
+    writer::FileWriter,
+};
+use std::io::{Error, Write};
+
+/// Concatenates all `sources` into a new MemFile. The data of sources storing their entries
+/// contiguously, like [`MemFile`] and [`MappedFile`](crate::MappedFile), is copied in bulk and
+/// its offsets are rebased. Entries of other sources are copied one by one. Returns the new
+/// file and the ID of the first entry of each source within it, so entry `id` of source `i`
+/// has the ID `offsets[i] + id`.
+pub fn merge<'a, F, I>(sources: I) -> Result<(MemFile, Vec<usize>), Error>
+where
+    F: IndexedAccess + ?Sized + 'a,
+    I: IntoIterator<Item = &'a F>,
+{
+    let sources: Vec<_> = sources.into_iter().collect();
+    let data_len = sources
+        .iter()
+        .filter_map(|i| Some(i.as_raw()?.raw_data().len()))
+        .sum();
+    let entries = sources.iter().map(|i| i.len()).sum();
+
+    let mut file = MemFile::with_capacity(data_len);
+    file.index.inner.reserve(entries);
+    let mut ids = Vec::with_capacity(sources.len());
+    for source in sources {
+        ids.push(file.len());
+        match source.as_raw() {
+            Some(raw) => file.insert_raw(raw.raw_data(), raw_offsets(raw))?,
+            None => {
+                for entry in entries_of(source) {
+                    file.insert(entry);
+                }
+            }
+        }
+    }
+    Ok((file, ids))
+}
+
+/// Writes all `sources` into `writer`, copying the data of contiguous sources in bulk and
+/// entries of other sources one by one. Returns the ID of the first entry of each source
+/// within the written file.
+pub fn merge_into<'a, F, I, W>(sources: I, writer: &mut FileWriter<W>) -> Result<Vec<usize>, Error>
+where
+    F: IndexedAccess + ?Sized + 'a,
+    I: IntoIterator<Item = &'a F>,
+    W: Write,
+{
+    let mut ids = vec![];
+    for source in sources {
+        ids.push(writer.len());
+        match source.as_raw() {
+            Some(raw) => writer.insert_raw(raw.raw_data(), raw_offsets(raw))?,
+            None => {
+                for entry in entries_of(source) {
+                    writer.insert(entry)?;
+                }
+            }
+        }
+    }
+    Ok(ids)
+}
+
+#[inline]
+fn raw_offsets(source: &dyn RawAccess) -> impl Iterator<Item = u32> + '_ {
+    (0..source.len()).map(|id| source.raw_offset(id).unwrap())
+}
+
+#[inline]
+fn entries_of<F: IndexedAccess + ?Sized>(source: &F) -> impl Iterator<Item = &[u8]> {
+    (0..source.len()).map(|id| source.get_unchecked(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IndexEncoding;
+    use std::fs::read_to_string;
+
+    #[test]
+    fn test_merge() {
+        let content = read_to_string("./testfiles/LICENSE").unwrap();
+        let lines: Vec<_> = content.lines().collect();
+        let parts: Vec<_> = lines
+            .chunks(37)
+            .map(|i| MemFile::from(i.iter()))
+            .chain(std::iter::once(MemFile::new()))
+            .collect();
+
+        let (file, ids) = merge(&parts).unwrap();
+        assert!(file.iter().eq(lines.iter().map(|i| i.as_bytes())));
+        assert_eq!(ids.len(), parts.len());
+        for (part, start) in parts.iter().zip(&ids) {
+            assert!(part.iter().eq(file.iter_range(*start..*start + part.len())));
+        }
+
+        let mut writer = FileWriter::new(vec![], IndexEncoding::EliasFano).unwrap();
+        writer.insert(b"first").unwrap();
+        let sources: [&dyn RawAccess; 2] = [&parts[1], &file];
+        assert_eq!(merge_into(sources, &mut writer).unwrap(), [1, 38]);
+        let merged = MemFile::from_bytes(&writer.finish().unwrap()).unwrap();
+        assert_eq!(merged.len(), 1 + parts[1].len() + file.len());
+        assert!(merged.iter_range(38..).eq(file.iter()));
+    }
+
+    #[test]
+    fn test_merge_indexed() {
+        use crate::{traits::IndexedAccessMut, VersionedFile};
+
+        let mut versioned = VersionedFile::new();
+        versioned.insert(b"old");
+        versioned.insert(b"b");
+        versioned.replace(0, b"a").unwrap();
+        let mem = MemFile::from(["c", "dd"].iter());
+
+        let sources: [&dyn IndexedAccess; 3] = [&versioned, &mem, &versioned];
+        let (file, ids) = merge(sources).unwrap();
+        assert_eq!(ids, [0, 2, 4]);
+        assert!(file.iter().eq([&b"a"[..], b"b", b"c", b"dd", b"a", b"b"]));
+
+        let mut writer = FileWriter::new(vec![], IndexEncoding::Plain).unwrap();
+        assert_eq!(merge_into(sources, &mut writer).unwrap(), [0, 2, 4]);
+        let merged = MemFile::from_bytes(&writer.finish().unwrap()).unwrap();
+        assert!(merged.iter().eq(file.iter()));
+    }
+
+    #[cfg(feature = "mapped")]
+    #[test]
+    fn test_merge_mapped() {
+        use crate::MappedFile;
+
+        let a = MemFile::from(["a", "bb", ""].iter());
+        a.save("test_merge_mapped", IndexEncoding::BlockPacked)
+            .unwrap();
+        let mapped = MappedFile::open("test_merge_mapped").unwrap();
+        let b = MemFile::from(["ccc"].iter());
+
+        let sources: [&dyn RawAccess; 3] = [&b, &mapped, &b];
+        let (file, ids) = merge(sources).unwrap();
+        assert_eq!(ids, [0, 1, 4]);
+        assert!(file.iter().eq([&b"ccc"[..], b"a", b"bb", b"", b"ccc"]));
+
+        std::fs::remove_file("test_merge_mapped").unwrap();
+    }
+}
@@ -0,0 +1,446 @@
+use crate::{
+    encoded_index::IndexEncoding,
+    format::{invalid_data, section, ByteReader, Layout},
+    traits::{ContainerFile, IndexedAccess},
+};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::File,
+    io::{BufWriter, Error, Write},
+    ops::Range,
+    path::Path,
+};
+
+/// A SHA-256 hash
+pub type Hash = [u8; 32];
+
+/// block size (4) + reserved (4) + entry count (8)
+const HEADER_LEN: usize = 16;
+
+/// Prefixes of hashed data, so entries, blocks and inner nodes can't be confused
+const ENTRY_PREFIX: u8 = 0;
+const BLOCK_PREFIX: u8 = 1;
+const NODE_PREFIX: u8 = 2;
+
+/// Merkle tree over the entries of a file, allowing to verify single entries against a
+/// published root hash and to find the ranges of entries that differ between two files.
+///
+/// Each entry is hashed on its own and the hashes of `block_size` consecutive entries form
+/// a leaf. The tree stores all leaves and inner nodes level by level after a 16 byte header;
+/// entry hashes are computed from the file when creating a proof. Nodes without a sibling are
+/// moved up a level unchanged. The tree can be saved together with a file and used directly
+/// from the mapping of a [`MappedFile`](crate::MappedFile) using `MappedFile::merkle_tree`.
+#[derive(Clone, Debug)]
+pub struct MerkleTree<D = Vec<u8>> {
+    bytes: D,
+    block_size: usize,
+    entries: usize,
+}
+
+/// Proof that an entry is part of a file with a given root hash
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Proof {
+    /// ID of the proven entry
+    pub id: usize,
+    /// Hashes of all entries in the block of the entry
+    pub block: Vec<Hash>,
+    /// Siblings on the path from the leaf to the root and whether they are the left node
+    pub path: Vec<(bool, Hash)>,
+}
+
+impl MerkleTree<Vec<u8>> {
+    /// Builds a tree over all entries of `file` with `block_size` entries per leaf
+    pub fn build<F: IndexedAccess>(file: &F, block_size: usize) -> Self {
+        let block_size = block_size.max(1);
+        let mut bytes = Vec::with_capacity(HEADER_LEN);
+        bytes.extend_from_slice(&(block_size as u32).to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&(file.len() as u64).to_le_bytes());
+
+        let leaves = leaf_count(file.len(), block_size);
+        let mut level: Vec<Hash> = (0..leaves)
+            .map(|block| {
+                let start = block * block_size;
+                let hashes: Vec<_> = file
+                    .iter_range(start..(start + block_size).min(file.len()))
+                    .map(hash_entry)
+                    .collect();
+                hash_block(&hashes)
+            })
+            .collect();
+
+        loop {
+            level.iter().for_each(|i| bytes.extend_from_slice(i));
+            if level.len() == 1 {
+                break;
+            }
+            level = level
+                .chunks(2)
+                .map(|i| match i {
+                    [left, right] => hash_node(left, right),
+                    [single] => *single,
+                    _ => unreachable!(),
+                })
+                .collect();
+        }
+
+        Self {
+            bytes,
+            block_size,
+            entries: file.len(),
+        }
+    }
+
+    /// Saves `file` together with the tree
+    pub fn save_with<F: ContainerFile, P: AsRef<Path>>(
+        &self,
+        file: &F,
+        path: P,
+        encoding: IndexEncoding,
+    ) -> Result<(), Error> {
+        let out = BufWriter::new(File::create(path)?);
+        self.write_with(file, out, encoding)
+    }
+
+    /// Writes `file` together with the tree
+    #[inline]
+    pub fn write_with<F: ContainerFile, W: Write>(
+        &self,
+        file: &F,
+        w: W,
+        encoding: IndexEncoding,
+    ) -> Result<(), Error> {
+        file.write_with_sections(w, encoding, &[(section::MERKLE, &self.bytes)])
+    }
+
+    /// Loads the tree of a file saved with `MerkleTree::save_with`. Returns `None` if the
+    /// file doesn't contain a tree.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Option<Self>, Error> {
+        let bytes = std::fs::read(path)?;
+        let layout = Layout::parse(&bytes)?;
+        let Some(tree) = layout.section(section::MERKLE) else {
+            return Ok(None);
+        };
+        Ok(Some(Self::from_bytes(bytes[tree.range()].to_vec())?))
+    }
+}
+
+impl<D: AsRef<[u8]>> MerkleTree<D> {
+    /// Creates a tree from its encoding
+    pub fn from_bytes(bytes: D) -> Result<Self, Error> {
+        let buf = bytes.as_ref();
+        if buf.len() < HEADER_LEN {
+            return Err(invalid_data("Invalid merkle tree length"));
+        }
+
+        let block_size = u32::from_le_bytes(buf[..4].try_into().unwrap()) as usize;
+        let entries = u64::from_le_bytes(buf[8..16].try_into().unwrap()) as usize;
+        if block_size == 0 {
+            return Err(invalid_data("Invalid merkle tree"));
+        }
+        let nodes = level_sizes(leaf_count(entries, block_size))
+            .try_fold(0usize, |sum, size| sum.checked_add(size));
+        if Some(buf.len() - HEADER_LEN) != nodes.and_then(|i| i.checked_mul(32)) {
+            return Err(invalid_data("Invalid merkle tree length"));
+        }
+
+        Ok(Self {
+            bytes,
+            block_size,
+            entries,
+        })
+    }
+
+    /// Returns the root hash of the tree
+    #[inline]
+    pub fn root_hash(&self) -> Hash {
+        let buf = self.bytes.as_ref();
+        buf[buf.len() - 32..].try_into().unwrap()
+    }
+
+    /// Creates a proof for the entry `id` of `file`, which has to be the file the tree has
+    /// been built for. Returns `None` if there is no such entry.
+    pub fn proof<F: IndexedAccess>(&self, file: &F, id: usize) -> Option<Proof> {
+        if id >= self.entries || id >= file.len() {
+            return None;
+        }
+
+        let start = id / self.block_size * self.block_size;
+        let end = (start + self.block_size).min(self.entries);
+        let block = file.iter_range(start..end).map(hash_entry).collect();
+
+        let path = path_nodes(self.leaves(), id / self.block_size)
+            .map(|(node, left)| (left, self.node(node)))
+            .collect();
+
+        Some(Proof { id, block, path })
+    }
+
+    /// Returns the ranges of IDs whose blocks differ from the blocks of `other`. Both trees
+    /// must use the same block size. Entries only contained in one of the files are
+    /// included.
+    pub fn changed_ranges<E: AsRef<[u8]>>(&self, other: &MerkleTree<E>) -> Vec<Range<usize>> {
+        let entries = self.entries.max(other.entries);
+        let mut ranges: Vec<Range<usize>> = vec![];
+        for block in 0..leaf_count(entries, self.block_size) {
+            let same = block < self.leaves()
+                && block < other.leaves()
+                && self.node(block) == other.node(block);
+            if same {
+                continue;
+            }
+            let start = block * self.block_size;
+            let end = (start + self.block_size).min(entries);
+            match ranges.last_mut() {
+                Some(last) if last.end == start => last.end = end,
+                _ => ranges.push(start..end),
+            }
+        }
+        ranges.retain(|i| !i.is_empty());
+        ranges
+    }
+
+    /// Returns the encoded tree
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        self.bytes.as_ref()
+    }
+
+    /// Returns the amount of entries per leaf
+    #[inline]
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Returns the amount of entries of the file the tree has been built for
+    #[inline]
+    pub fn entries(&self) -> usize {
+        self.entries
+    }
+
+    #[inline]
+    fn leaves(&self) -> usize {
+        leaf_count(self.entries, self.block_size)
+    }
+
+    #[inline]
+    fn node(&self, pos: usize) -> Hash {
+        let start = HEADER_LEN + pos * 32;
+        self.bytes.as_ref()[start..start + 32].try_into().unwrap()
+    }
+}
+
+/// Returns `true` if `proof` proves that `data` is the entry `proof.id` of a file with the
+/// given root hash, block size and amount of entries. The position of the entry is derived
+/// from its ID, so proofs with a changed ID or path are rejected.
+pub fn verify_proof(
+    root: &Hash,
+    block_size: usize,
+    entries: usize,
+    data: &[u8],
+    proof: &Proof,
+) -> bool {
+    let block_size = block_size.max(1);
+    if proof.id >= entries {
+        return false;
+    }
+    let start = proof.id / block_size * block_size;
+    let block_len = block_size.min(entries - start);
+    if proof.block.len() != block_len || proof.block[proof.id - start] != hash_entry(data) {
+        return false;
+    }
+
+    let mut expected = path_nodes(leaf_count(entries, block_size), proof.id / block_size);
+    let mut hash = hash_block(&proof.block);
+    for (left, sibling) in proof.path.iter() {
+        match expected.next() {
+            Some((_, exp_left)) if exp_left == *left => (),
+            _ => return false,
+        }
+        hash = if *left {
+            hash_node(sibling, &hash)
+        } else {
+            hash_node(&hash, sibling)
+        };
+    }
+    expected.next().is_none() && hash == *root
+}
+
+/// Returns the position of each sibling on the path from `leaf` to the root and whether it
+/// is the left node. Levels on which the node has no sibling and gets promoted are skipped.
+fn path_nodes(leaves: usize, leaf: usize) -> impl Iterator<Item = (usize, bool)> {
+    let mut pos = leaf;
+    let mut level_start = 0;
+    level_sizes(leaves)
+        .take_while(|size| *size > 1)
+        .filter_map(move |size| {
+            let sibling = pos ^ 1;
+            let node = (sibling < size).then_some((level_start + sibling, sibling < pos));
+            level_start += size;
+            pos /= 2;
+            node
+        })
+}
+
+/// Returns the amount of leaves of a tree. Empty files have a single empty leaf
+#[inline]
+fn leaf_count(entries: usize, block_size: usize) -> usize {
+    entries.div_ceil(block_size).max(1)
+}
+
+/// Returns the amount of nodes of each level, starting with the leaves
+fn level_sizes(leaves: usize) -> impl Iterator<Item = usize> {
+    let mut next = Some(leaves);
+    std::iter::from_fn(move || {
+        let size = next?;
+        next = (size > 1).then(|| size.div_ceil(2));
+        Some(size)
+    })
+}
+
+#[inline]
+fn hash_entry(data: &[u8]) -> Hash {
+    Sha256::new()
+        .chain_update([ENTRY_PREFIX])
+        .chain_update(data)
+        .finalize()
+        .into()
+}
+
+#[inline]
+fn hash_block(hashes: &[Hash]) -> Hash {
+    let mut hasher = Sha256::new().chain_update([BLOCK_PREFIX]);
+    for hash in hashes {
+        hasher.update(hash);
+    }
+    hasher.finalize().into()
+}
+
+#[inline]
+fn hash_node(left: &Hash, right: &Hash) -> Hash {
+    Sha256::new()
+        .chain_update([NODE_PREFIX])
+        .chain_update(left)
+        .chain_update(right)
+        .finalize()
+        .into()
+}
+
+impl Proof {
+    /// Encodes the proof as u64 ID, u32 block length, u32 path length, the block hashes and
+    /// each path node as one byte side followed by the hash
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(16 + (self.block.len() + self.path.len()) * 33);
+        out.extend_from_slice(&(self.id as u64).to_le_bytes());
+        out.extend_from_slice(&(self.block.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(self.path.len() as u32).to_le_bytes());
+        self.block.iter().for_each(|i| out.extend_from_slice(i));
+        for (left, hash) in self.path.iter() {
+            out.push(*left as u8);
+            out.extend_from_slice(hash);
+        }
+        out
+    }
+
+    /// Decodes a proof encoded with `Proof::to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut r = ByteReader::new(bytes);
+        let id = r.u64()? as usize;
+        let block_len = r.u32()? as usize;
+        let path_len = r.u32()? as usize;
+
+        let read_hash =
+            |r: &mut ByteReader| -> Result<Hash, Error> { Ok(r.bytes(32)?.try_into().unwrap()) };
+        let block = (0..block_len)
+            .map(|_| read_hash(&mut r))
+            .collect::<Result<_, _>>()?;
+        let path = (0..path_len)
+            .map(|_| Ok((r.bytes(1)?[0] != 0, read_hash(&mut r)?)))
+            .collect::<Result<_, Error>>()?;
+        if r.pos != bytes.len() {
+            return Err(invalid_data("Trailing bytes after proof"));
+        }
+
+        Ok(Self { id, block, path })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{traits::IndexedAccessMut, MemFile};
+    use std::fs::read_to_string;
+
+    #[test]
+    fn test_proofs() {
+        let content = read_to_string("./testfiles/LICENSE").unwrap();
+        let file = MemFile::from(content.lines());
+        let tree = MerkleTree::build(&file, 8);
+        let root = tree.root_hash();
+
+        for id in 0..file.len() {
+            let proof = tree.proof(&file, id).unwrap();
+            let decoded = Proof::from_bytes(&proof.to_bytes()).unwrap();
+            assert_eq!(decoded, proof);
+            let entry = file.get_unchecked(id);
+            assert!(verify_proof(&root, 8, file.len(), entry, &proof));
+            assert!(!verify_proof(&root, 8, file.len(), b"forged", &proof));
+        }
+        assert!(tree.proof(&file, file.len()).is_none());
+
+        // Relabelled proofs and proofs with a changed path are rejected
+        let entry = file.get_unchecked(13);
+        let mut proof = tree.proof(&file, 13).unwrap();
+        proof.id = 5;
+        assert!(!verify_proof(&root, 8, file.len(), entry, &proof));
+        proof.id = 13;
+        proof.path[0].0 ^= true;
+        assert!(!verify_proof(&root, 8, file.len(), entry, &proof));
+        proof.path[0].0 ^= true;
+        proof.path.push(proof.path[0]);
+        assert!(!verify_proof(&root, 8, file.len(), entry, &proof));
+        proof.path.pop();
+        proof.block.pop();
+        assert!(!verify_proof(&root, 8, file.len(), entry, &proof));
+
+        let mut buf = vec![];
+        tree.write_with(&file, &mut buf, IndexEncoding::Plain)
+            .unwrap();
+        crate::info::verify(&buf).unwrap();
+        let layout = Layout::parse(&buf).unwrap();
+        let section = layout.required_section(section::MERKLE).unwrap();
+        let loaded = MerkleTree::from_bytes(&buf[section.range()]).unwrap();
+        assert_eq!(loaded.root_hash(), root);
+
+        // Entry counts with more nodes than fit into usize
+        let mut header = [0u8; HEADER_LEN + 32];
+        header[..4].copy_from_slice(&1u32.to_le_bytes());
+        header[8..16].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(MerkleTree::from_bytes(&header[..]).is_err());
+
+        let empty = MerkleTree::build(&MemFile::new(), 8);
+        assert_ne!(empty.root_hash(), root);
+    }
+
+    #[test]
+    fn test_changed_ranges() {
+        let content = read_to_string("./testfiles/LICENSE").unwrap();
+        let old = MemFile::from(content.lines());
+        let mut new = old.clone();
+        new.replace(20, b"changed").unwrap();
+        new.insert(b"appended");
+
+        let old_tree = MerkleTree::build(&old, 4);
+        let new_tree = MerkleTree::build(&new, 4);
+        assert_ne!(old_tree.root_hash(), new_tree.root_hash());
+
+        let last_block = old.len() / 4 * 4;
+        assert_eq!(
+            new_tree.changed_ranges(&old_tree),
+            [20..24, last_block..new.len()]
+        );
+        assert!(old_tree
+            .changed_ranges(&MerkleTree::build(&old, 4))
+            .is_empty());
+    }
+}
@@ -0,0 +1,295 @@
+use crate::{
+    format::{invalid_data, ByteReader},
+    hash::hash64_seeded,
+    memory::MemFile,
+    traits::{IndexedAccess, IndexedAccessMut},
+};
+use std::{
+    fs::File,
+    io::{BufWriter, Error, Write},
+    path::Path,
+};
+
+/// Magic bytes at the start of a serialized patch
+const PATCH_MAGIC: [u8; 8] = *b"STPATCH\0";
+
+/// Version of the patch format
+const PATCH_VERSION: u32 = 1;
+
+/// Changes that turn one version of a file into another. As IDs are positions, entries can
+/// only be replaced in place, appended or removed from the end. Patches can only be created
+/// using [`diff`] and contain a hash of the file they have been created for, so they can't
+/// be applied to a different file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Patch {
+    /// Amount of entries of the file the patch applies to
+    base_len: usize,
+    /// Hash of all entries of the file the patch applies to
+    base_hash: u64,
+    /// Entries with a changed payload, ordered by ID
+    replaced: Vec<(usize, Vec<u8>)>,
+    /// Entries appended after the end of the base file
+    inserted: Vec<Vec<u8>>,
+    /// Amount of entries removed from the end of the base file
+    deleted: usize,
+}
+
+impl Patch {
+    /// Returns the amount of entries of the file the patch applies to
+    #[inline]
+    pub fn base_len(&self) -> usize {
+        self.base_len
+    }
+
+    /// Returns the hash of the file the patch applies to, see [`content_hash`]
+    #[inline]
+    pub fn base_hash(&self) -> u64 {
+        self.base_hash
+    }
+
+    /// Returns the IDs and new data of all replaced entries, ordered by ID
+    #[inline]
+    pub fn replaced(&self) -> &[(usize, Vec<u8>)] {
+        &self.replaced
+    }
+
+    /// Returns the entries appended after the end of the base file
+    #[inline]
+    pub fn inserted(&self) -> &[Vec<u8>] {
+        &self.inserted
+    }
+
+    /// Returns the amount of entries removed from the end of the base file
+    #[inline]
+    pub fn deleted(&self) -> usize {
+        self.deleted
+    }
+
+    /// Returns the amount of entries the file has after applying the patch
+    #[inline]
+    pub fn new_len(&self) -> usize {
+        self.base_len - self.deleted + self.inserted.len()
+    }
+
+    /// Returns `true` if the patch doesn't change anything
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.replaced.is_empty() && self.inserted.is_empty() && self.deleted == 0
+    }
+
+    /// Returns the IDs of all deleted entries
+    #[inline]
+    pub fn deleted_ids(&self) -> std::ops::Range<usize> {
+        self.base_len - self.deleted..self.base_len
+    }
+
+    /// Returns the IDs the inserted entries get
+    #[inline]
+    pub fn inserted_ids(&self) -> std::ops::Range<usize> {
+        let start = self.base_len - self.deleted;
+        start..start + self.inserted.len()
+    }
+
+    /// Saves the patch to `path`
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_to(&mut out)?;
+        out.flush()
+    }
+
+    /// Writes the patch in its serialized format
+    pub fn write_to<W: Write>(&self, mut w: W) -> Result<(), Error> {
+        w.write_all(&PATCH_MAGIC)?;
+        w.write_all(&PATCH_VERSION.to_le_bytes())?;
+        w.write_all(&0u32.to_le_bytes())?;
+        w.write_all(&self.base_hash.to_le_bytes())?;
+        for value in [
+            self.base_len,
+            self.deleted,
+            self.replaced.len(),
+            self.inserted.len(),
+        ] {
+            w.write_all(&(value as u64).to_le_bytes())?;
+        }
+
+        for (id, data) in self.replaced.iter() {
+            w.write_all(&(*id as u64).to_le_bytes())?;
+            w.write_all(&(data.len() as u64).to_le_bytes())?;
+            w.write_all(data)?;
+        }
+        for data in self.inserted.iter() {
+            w.write_all(&(data.len() as u64).to_le_bytes())?;
+            w.write_all(data)?;
+        }
+        Ok(())
+    }
+
+    /// Loads a patch that has been saved with `Patch::save`
+    #[inline]
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// Decodes a patch from its serialized format
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut r = ByteReader::new(bytes);
+        if r.bytes(PATCH_MAGIC.len())? != PATCH_MAGIC {
+            return Err(invalid_data("Not a patch"));
+        }
+        let version = r.u32()?;
+        if version != PATCH_VERSION {
+            return Err(invalid_data(format!("Unsupported patch version {version}")));
+        }
+        r.u32()?;
+
+        let base_hash = r.u64()?;
+        let base_len = r.u64()? as usize;
+        let deleted = r.u64()? as usize;
+        let replaced_count = r.u64()? as usize;
+        let inserted_count = r.u64()? as usize;
+        if deleted > base_len {
+            return Err(invalid_data("More entries deleted than available"));
+        }
+
+        let mut replaced = Vec::with_capacity(replaced_count.min(bytes.len() / 16));
+        for _ in 0..replaced_count {
+            let id = r.u64()? as usize;
+            if id >= base_len - deleted || replaced.last().is_some_and(|i: &(usize, _)| i.0 >= id) {
+                return Err(invalid_data(format!("Invalid replaced ID {id}")));
+            }
+            let len = r.u64()? as usize;
+            replaced.push((id, r.bytes(len)?.to_vec()));
+        }
+
+        let mut inserted = Vec::with_capacity(inserted_count.min(bytes.len() / 8));
+        for _ in 0..inserted_count {
+            let len = r.u64()? as usize;
+            inserted.push(r.bytes(len)?.to_vec());
+        }
+
+        if r.pos != bytes.len() {
+            return Err(invalid_data("Trailing bytes after patch"));
+        }
+
+        Ok(Self {
+            base_len,
+            base_hash,
+            replaced,
+            inserted,
+            deleted,
+        })
+    }
+}
+
+/// Returns the changes that turn `old` into `new`
+pub fn diff<A, B>(old: &A, new: &B) -> Patch
+where
+    A: IndexedAccess,
+    B: IndexedAccess,
+{
+    let common = old.len().min(new.len());
+    let replaced = (0..common)
+        .filter_map(|id| {
+            let entry = new.get_unchecked(id);
+            (old.get_unchecked(id) != entry).then(|| (id, entry.to_vec()))
+        })
+        .collect();
+    let inserted = new.iter_range(common..).map(|i| i.to_vec()).collect();
+
+    Patch {
+        base_len: old.len(),
+        base_hash: content_hash(old),
+        replaced,
+        inserted,
+        deleted: old.len() - common,
+    }
+}
+
+/// Returns a hash over the entries of `file`, which is used to check that a patch gets
+/// applied to the file it has been created for. The hash is not cryptographically secure.
+pub fn content_hash<F: IndexedAccess + ?Sized>(file: &F) -> u64 {
+    (0..file.len()).fold(file.len() as u64, |hash, id| {
+        let entry = file.get_unchecked(id);
+        hash64_seeded(entry, hash ^ entry.len() as u64)
+    })
+}
+
+/// Applies `patch` to `file`. Fails without changing the file if the file isn't the file the
+/// patch has been created for.
+pub fn apply_patch(file: &mut MemFile, patch: &Patch) -> Result<(), Error> {
+    if file.len() != patch.base_len {
+        return Err(invalid_data(format!(
+            "Patch expects {} entries but the file has {}",
+            patch.base_len,
+            file.len()
+        )));
+    }
+    if content_hash(file) != patch.base_hash {
+        return Err(invalid_data("Patch has been created for a different file"));
+    }
+
+    // Rebuilding the file is linear, while replacing entries in place moves all data behind
+    // each replaced entry
+    let mut patched = MemFile::with_capacity(file.raw_len());
+    let mut replaced = patch.replaced.iter().peekable();
+    for (id, entry) in file
+        .iter_range(..patch.base_len - patch.deleted)
+        .enumerate()
+    {
+        match replaced.next_if(|i| i.0 == id) {
+            Some((_, data)) => patched.insert(data),
+            None => patched.insert(entry),
+        };
+    }
+    patched.extend(patch.inserted.iter());
+
+    *file = patched;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::read_to_string;
+
+    #[test]
+    fn test_diff_apply() {
+        let content = read_to_string("./testfiles/LICENSE").unwrap();
+        let old = MemFile::from(content.lines());
+        let mut lines: Vec<String> = content.lines().map(|i| i.to_string()).collect();
+        lines[3] = "changed".to_string();
+        lines[10].push('!');
+        let new = MemFile::from(lines.iter().chain(["appended".to_string()].iter()));
+
+        let patch = diff(&old, &new);
+        assert_eq!(patch.replaced.len(), 2);
+        assert_eq!(patch.inserted_ids(), old.len()..old.len() + 1);
+        assert_eq!(patch.new_len(), new.len());
+
+        let mut buf = vec![];
+        patch.write_to(&mut buf).unwrap();
+        let decoded = Patch::from_bytes(&buf).unwrap();
+        assert_eq!(decoded, patch);
+
+        // Same amount of entries but different content
+        let mut other = old.clone();
+        other.replace(0, b"other").unwrap();
+        assert!(apply_patch(&mut other, &decoded).is_err());
+
+        let mut file = old.clone();
+        apply_patch(&mut file, &decoded).unwrap();
+        assert!(file.iter().eq(new.iter()));
+        assert!(apply_patch(&mut file, &decoded).is_err());
+        assert!(diff(&file, &new).is_empty());
+
+        // Removing entries from the end
+        let patch = diff(&new, &old);
+        assert_eq!(patch.deleted_ids(), old.len()..new.len());
+        apply_patch(&mut file, &patch).unwrap();
+        assert!(file.iter().eq(old.iter()));
+
+        buf[8] = 2;
+        assert!(Patch::from_bytes(&buf).is_err());
+        assert!(Patch::from_bytes(&buf[..buf.len() - 1]).is_err());
+    }
+}
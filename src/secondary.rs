@@ -0,0 +1,445 @@
+use crate::{
+    encoded_index::IndexEncoding,
+    format::{invalid_data, section, ByteReader, Layout},
+    memory::MemFile,
+    traits::{IndexedAccess, IndexedAccessMut},
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Error, ErrorKind, Write},
+    marker::PhantomData,
+    path::Path,
+};
+
+type BincodeError = Box<bincode::ErrorKind>;
+
+/// Maps encoded keys to the sorted IDs of all entries having that key
+type KeyMap = HashMap<Vec<u8>, Vec<u32>>;
+
+/// Extracts the encoded key of an entry
+type KeyFn<T> = Box<dyn Fn(&T) -> Result<Vec<u8>, BincodeError>>;
+
+/// Typed store that keeps secondary indexes up to date, allowing to find all entries whose
+/// extracted key equals a given value. Each index is defined by a name and a function that
+/// extracts the key of an entry. The indexes get saved together with the entries.
+///
+/// Entries are stored bincode encoded in a [`MemFile`]. Removed entries are kept as empty
+/// entries so IDs of other entries don't change, thus `T` must not encode to zero bytes.
+pub struct IndexedStore<T> {
+    file: MemFile,
+    indexes: Vec<SecondaryIndex<T>>,
+    /// Indexes loaded from a file that haven't been registered again yet. They can be searched
+    /// but not kept up to date, so the store can't be modified while there are any.
+    persisted: HashMap<String, KeyMap>,
+    len: usize,
+    p: PhantomData<T>,
+}
+
+struct SecondaryIndex<T> {
+    name: String,
+    key: KeyFn<T>,
+    ids: KeyMap,
+}
+
+impl<T: Serialize + DeserializeOwned> IndexedStore<T> {
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            file: MemFile::new(),
+            indexes: vec![],
+            persisted: HashMap::new(),
+            len: 0,
+            p: PhantomData,
+        }
+    }
+
+    /// Registers a secondary index. `key` extracts the key of an entry which is compared
+    /// using its bincode encoding. If the store has been loaded from a file that contains an
+    /// index with the same name, the persisted index is used and `key` has to be the same
+    /// function it was built with. Otherwise the index gets built from all entries.
+    /// An existing index with the same name gets replaced.
+    pub fn add_index<K, F>(&mut self, name: &str, key: F) -> Result<(), BincodeError>
+    where
+        K: Serialize,
+        F: Fn(&T) -> K + 'static,
+    {
+        let mut index = SecondaryIndex {
+            name: name.to_string(),
+            key: Box::new(move |item| bincode::serialize(&key(item))),
+            ids: KeyMap::new(),
+        };
+
+        if let Some(ids) = self.persisted.remove(name) {
+            index.ids = ids;
+        } else {
+            for id in 0..self.file.len() {
+                if let Some(item) = self.get(id)? {
+                    let key = (index.key)(&item)?;
+                    index.ids.entry(key).or_default().push(id as u32);
+                }
+            }
+        }
+
+        self.indexes.retain(|i| i.name != name);
+        self.indexes.push(index);
+        Ok(())
+    }
+
+    /// Removes a secondary index. Returns `false` if there is no index with the given name
+    pub fn remove_index(&mut self, name: &str) -> bool {
+        let len = self.indexes.len();
+        self.indexes.retain(|i| i.name != name);
+        self.persisted.remove(name).is_some() || len != self.indexes.len()
+    }
+
+    /// Returns the IDs of all entries whose key in the index `name` equals `key`, in
+    /// ascending order. Persisted indexes that haven't been registered again can be searched
+    /// as well. Returns `None` if there is no such index.
+    pub fn find<K: Serialize>(
+        &self,
+        name: &str,
+        key: &K,
+    ) -> Result<Option<Vec<usize>>, BincodeError> {
+        let index = self.indexes.iter().find(|i| i.name == name).map(|i| &i.ids);
+        let Some(index) = index.or_else(|| self.persisted.get(name)) else {
+            return Ok(None);
+        };
+        let key = bincode::serialize(key)?;
+        let ids = index.get(&key).map(|i| i.as_slice()).unwrap_or_default();
+        Ok(Some(ids.iter().map(|i| *i as usize).collect()))
+    }
+
+    /// Inserts a new entry and returns its ID. Fails if there are persisted indexes that
+    /// haven't been registered again.
+    pub fn insert(&mut self, item: &T) -> Result<usize, BincodeError> {
+        self.check_persisted()?;
+        let keys = self.keys(item)?;
+        let id = self.file.insert(&encode(item)?);
+        for (index, key) in self.indexes.iter_mut().zip(keys) {
+            index.ids.entry(key).or_default().push(id as u32);
+        }
+        self.len += 1;
+        Ok(id)
+    }
+
+    /// Replaces the entry with the given ID. Returns `false` if there is no such entry. Fails
+    /// if there are persisted indexes that haven't been registered again.
+    pub fn replace(&mut self, id: usize, item: &T) -> Result<bool, BincodeError> {
+        self.check_persisted()?;
+        let Some(old) = self.get(id)? else {
+            return Ok(false);
+        };
+        let old_keys = self.keys(&old)?;
+        let new_keys = self.keys(item)?;
+        self.file.replace(id, &encode(item)?);
+
+        for ((index, old), new) in self.indexes.iter_mut().zip(old_keys).zip(new_keys) {
+            if old != new {
+                remove_id(&mut index.ids, &old, id as u32);
+                insert_id(&mut index.ids, new, id as u32);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Removes the entry with the given ID and returns it. Fails if there are persisted
+    /// indexes that haven't been registered again.
+    pub fn remove(&mut self, id: usize) -> Result<Option<T>, BincodeError> {
+        self.check_persisted()?;
+        let Some(old) = self.get(id)? else {
+            return Ok(None);
+        };
+        let keys = self.keys(&old)?;
+        self.file.replace(id, &[]);
+
+        for (index, key) in self.indexes.iter_mut().zip(keys) {
+            remove_id(&mut index.ids, &key, id as u32);
+        }
+        self.len -= 1;
+        Ok(Some(old))
+    }
+
+    /// Returns the entry with the given ID or `None` if it doesn't exist or has been removed
+    #[inline]
+    pub fn get(&self, id: usize) -> Result<Option<T>, BincodeError> {
+        match self.file.get(id) {
+            Some(data) if !data.is_empty() => Ok(Some(bincode::deserialize(data)?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns an iterator over the IDs and values of all entries
+    pub fn iter(&self) -> impl Iterator<Item = Result<(usize, T), BincodeError>> + '_ {
+        self.file
+            .iter()
+            .enumerate()
+            .filter(|(_, data)| !data.is_empty())
+            .map(|(id, data)| Ok((id, bincode::deserialize(data)?)))
+    }
+
+    /// Returns the amount of entries
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if there are no entries
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the underlying file
+    #[inline]
+    pub fn file(&self) -> &MemFile {
+        &self.file
+    }
+
+    /// Saves the store including its indexes
+    pub fn save<P: AsRef<Path>>(&self, path: P, encoding: IndexEncoding) -> Result<(), Error> {
+        let out = BufWriter::new(File::create(path)?);
+        self.write_to(out, encoding)
+    }
+
+    /// Writes the store including its indexes. Loaded indexes that haven't been registered
+    /// again are written unchanged.
+    pub fn write_to<W: Write>(&self, w: W, encoding: IndexEncoding) -> Result<(), Error> {
+        let indexes = self.indexes.iter().map(|i| (&i.name, &i.ids));
+        let indexes: Vec<_> = indexes.chain(self.persisted.iter()).collect();
+
+        let mut buf = vec![];
+        buf.extend_from_slice(&(indexes.len() as u32).to_le_bytes());
+        for (name, ids) in indexes {
+            write_bytes(&mut buf, name.as_bytes());
+            write_key_map(&mut buf, ids);
+        }
+        self.file
+            .write_with_sections(w, encoding, &[(section::SECONDARY_INDEX, &buf)])
+    }
+
+    /// Loads a store saved with `IndexedStore::save`. Persisted indexes can be searched right
+    /// away, but have to be registered again using `add_index` or dropped using
+    /// `remove_index` before the store can be modified.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let bytes = std::fs::read(path)?;
+        let layout = Layout::parse(&bytes)?;
+        let indexes = layout.required_section(section::SECONDARY_INDEX)?;
+        let file = MemFile::from_layout(&bytes, &layout)?;
+
+        let mut reader = ByteReader::new(&bytes[indexes.range()]);
+        let mut persisted = HashMap::new();
+        for _ in 0..reader.u32()? {
+            let name = read_bytes(&mut reader)?;
+            let name = String::from_utf8(name.to_vec()).map_err(invalid_data)?;
+            persisted.insert(name, read_key_map(&mut reader, file.len())?);
+        }
+
+        let len = file.iter().filter(|i| !i.is_empty()).count();
+        Ok(Self {
+            file,
+            indexes: vec![],
+            persisted,
+            len,
+            p: PhantomData,
+        })
+    }
+
+    /// Fails if there are persisted indexes that would get out of date by a modification
+    fn check_persisted(&self) -> Result<(), BincodeError> {
+        let Some(name) = self.persisted.keys().next() else {
+            return Ok(());
+        };
+        let msg = format!("Persisted index {name:?} has to be registered before modifying");
+        Err(Error::new(ErrorKind::InvalidInput, msg).into())
+    }
+
+    /// Returns the keys of `item` for all registered indexes
+    fn keys(&self, item: &T) -> Result<Vec<Vec<u8>>, BincodeError> {
+        self.indexes.iter().map(|i| (i.key)(item)).collect()
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> Default for IndexedStore<T> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[inline]
+fn encode<T: Serialize>(item: &T) -> Result<Vec<u8>, BincodeError> {
+    let enc = bincode::serialize(item)?;
+    if enc.is_empty() {
+        let msg = "Entries must not encode to zero bytes";
+        return Err(Error::new(ErrorKind::InvalidInput, msg).into());
+    }
+    Ok(enc)
+}
+
+/// Inserts `id` into the ID list of `key`, keeping it sorted
+fn insert_id(ids: &mut KeyMap, key: Vec<u8>, id: u32) {
+    let list = ids.entry(key).or_default();
+    if let Err(pos) = list.binary_search(&id) {
+        list.insert(pos, id);
+    }
+}
+
+/// Removes `id` from the ID list of `key`
+fn remove_id(ids: &mut KeyMap, key: &[u8], id: u32) {
+    let Some(list) = ids.get_mut(key) else {
+        return;
+    };
+    if let Ok(pos) = list.binary_search(&id) {
+        list.remove(pos);
+    }
+    if list.is_empty() {
+        ids.remove(key);
+    }
+}
+
+#[inline]
+fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+#[inline]
+fn read_bytes<'a>(reader: &mut ByteReader<'a>) -> Result<&'a [u8], Error> {
+    let len = reader.u32()? as usize;
+    reader.bytes(len)
+}
+
+/// Writes the key count followed by each key and its ID list
+fn write_key_map(buf: &mut Vec<u8>, ids: &KeyMap) {
+    buf.extend_from_slice(&(ids.len() as u64).to_le_bytes());
+    for (key, list) in ids {
+        write_bytes(buf, key);
+        buf.extend_from_slice(&(list.len() as u32).to_le_bytes());
+        for id in list {
+            buf.extend_from_slice(&id.to_le_bytes());
+        }
+    }
+}
+
+fn read_key_map(reader: &mut ByteReader, entries: usize) -> Result<KeyMap, Error> {
+    let count = reader.u64()?;
+    let mut ids = KeyMap::new();
+    for _ in 0..count {
+        let key = read_bytes(reader)?.to_vec();
+        let len = reader.u32()? as usize;
+        let list = reader.u32_vec(len)?;
+        if list.iter().any(|i| *i as usize >= entries) || !list.windows(2).all(|i| i[0] < i[1]) {
+            return Err(invalid_data("Invalid secondary index"));
+        }
+        ids.insert(key, list);
+    }
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    struct Person {
+        name: String,
+        city: String,
+        age: u32,
+    }
+
+    fn person(name: &str, city: &str, age: u32) -> Person {
+        Person {
+            name: name.to_string(),
+            city: city.to_string(),
+            age,
+        }
+    }
+
+    fn store() -> IndexedStore<Person> {
+        let mut store = IndexedStore::new();
+        store.insert(&person("alice", "berlin", 30)).unwrap();
+        store
+            .add_index("city", |p: &Person| p.city.clone())
+            .unwrap();
+        store.insert(&person("bob", "paris", 30)).unwrap();
+        store.insert(&person("carol", "berlin", 25)).unwrap();
+        store.add_index("age", |p: &Person| p.age).unwrap();
+        store
+    }
+
+    #[test]
+    fn test_find() {
+        let mut store = store();
+        assert_eq!(store.find("city", &"berlin").unwrap().unwrap(), vec![0, 2]);
+        assert_eq!(store.find("city", &"paris").unwrap().unwrap(), vec![1]);
+        assert_eq!(store.find("age", &30u32).unwrap().unwrap(), vec![0, 1]);
+        assert!(store.find("city", &"rome").unwrap().unwrap().is_empty());
+        assert_eq!(store.find("name", &"alice").unwrap(), None);
+
+        assert!(store.replace(0, &person("alice", "rome", 31)).unwrap());
+        assert_eq!(store.find("city", &"berlin").unwrap().unwrap(), vec![2]);
+        assert_eq!(store.find("city", &"rome").unwrap().unwrap(), vec![0]);
+        assert_eq!(store.find("age", &30u32).unwrap().unwrap(), vec![1]);
+        assert!(!store.replace(10, &person("dave", "rome", 1)).unwrap());
+
+        assert_eq!(store.remove(2).unwrap().unwrap().name, "carol");
+        assert_eq!(store.remove(2).unwrap(), None);
+        assert!(store.find("city", &"berlin").unwrap().unwrap().is_empty());
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.get(2).unwrap(), None);
+
+        assert_eq!(store.insert(&person("dave", "rome", 30)).unwrap(), 3);
+        assert_eq!(store.find("city", &"rome").unwrap().unwrap(), vec![0, 3]);
+        assert_eq!(store.iter().count(), 3);
+
+        assert!(store.remove_index("age"));
+        assert!(!store.remove_index("age"));
+        assert_eq!(store.find("age", &30u32).unwrap(), None);
+    }
+
+    #[test]
+    fn test_save_load() {
+        let mut store = store();
+        store.remove(1).unwrap();
+        store
+            .save("test_secondary_save_load", IndexEncoding::Plain)
+            .unwrap();
+
+        let mut loaded = IndexedStore::<Person>::load("test_secondary_save_load").unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded.find("city", &"berlin").unwrap(), Some(vec![0, 2]));
+        assert_eq!(loaded.find("age", &25u32).unwrap(), Some(vec![2]));
+        let err = loaded.insert(&person("bob", "paris", 40)).unwrap_err();
+        assert!(
+            matches!(*err, bincode::ErrorKind::Io(ref e) if e.kind() == ErrorKind::InvalidInput)
+        );
+        assert!(loaded.remove(0).is_err());
+
+        loaded
+            .add_index("city", |p: &Person| p.city.clone())
+            .unwrap();
+        loaded
+            .add_index("name", |p: &Person| p.name.clone())
+            .unwrap();
+        assert_eq!(loaded.find("city", &"berlin").unwrap().unwrap(), vec![0, 2]);
+        assert_eq!(loaded.find("name", &"carol").unwrap().unwrap(), vec![2]);
+
+        assert!(loaded.insert(&person("bob", "paris", 40)).is_err());
+
+        loaded.add_index("age", |p: &Person| p.age).unwrap();
+        loaded.insert(&person("bob", "paris", 40)).unwrap();
+        assert_eq!(loaded.find("city", &"paris").unwrap().unwrap(), vec![3]);
+        assert_eq!(loaded.find("age", &40u32).unwrap().unwrap(), vec![3]);
+
+        store
+            .file()
+            .save("test_secondary_save_load", IndexEncoding::Plain)
+            .unwrap();
+        assert!(IndexedStore::<Person>::load("test_secondary_save_load").is_err());
+
+        std::fs::remove_file("test_secondary_save_load").unwrap();
+    }
+}
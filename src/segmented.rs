@@ -0,0 +1,267 @@
+use crate::{
+    encoded_index::IndexEncoding,
+    map::MappedFile,
+    memory::MemFile,
+    traits::{IndexedAccess, IndexedAccessMut},
+};
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Error, Write},
+    path::{Path, PathBuf},
+};
+
+/// File extension of sealed segments
+const SEGMENT_EXT: &str = "seg";
+
+/// Suffix of segments that are being written
+const TEMP_SUFFIX: &str = ".tmp";
+
+/// Storage spanning multiple files. Entries are inserted into an in-memory active segment
+/// which gets written to a new immutable, mmapped segment file in the directory once it
+/// reaches the size limit. IDs are global across all segments.
+///
+/// The active segment is only kept in memory; call [`SegmentedFile::seal`] before dropping
+/// to persist its entries.
+pub struct SegmentedFile {
+    dir: PathBuf,
+    segments: Vec<MappedFile>,
+    /// Global ID of the first entry of each sealed segment
+    starts: Vec<usize>,
+    active: MemFile,
+    max_segment_size: usize,
+    encoding: IndexEncoding,
+    /// Number of the next segment file
+    next_segment: u64,
+}
+
+impl SegmentedFile {
+    /// Opens all segments within `dir`, creating the directory if it doesn't exist. The active
+    /// segment gets sealed as soon as its data reaches `max_segment_size` bytes.
+    pub fn open<P: AsRef<Path>>(dir: P, max_segment_size: usize) -> Result<Self, Error> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let mut paths = vec![];
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let name = path
+                .file_name()
+                .and_then(|i| i.to_str())
+                .unwrap_or_default();
+            if name.ends_with(&format!(".{SEGMENT_EXT}{TEMP_SUFFIX}")) {
+                // Left over by an interrupted seal
+                fs::remove_file(&path)?;
+                continue;
+            }
+            let number = name
+                .strip_suffix(&format!(".{SEGMENT_EXT}"))
+                .and_then(|i| i.parse::<u64>().ok());
+            if let Some(number) = number {
+                paths.push((number, path));
+            }
+        }
+        paths.sort();
+        let next_segment = paths.last().map(|i| i.0 + 1).unwrap_or(0);
+
+        let mut segments = Vec::with_capacity(paths.len());
+        let mut starts = Vec::with_capacity(paths.len());
+        let mut len = 0;
+        for (_, path) in paths {
+            let segment = MappedFile::open(path)?;
+            starts.push(len);
+            len += segment.len();
+            segments.push(segment);
+        }
+
+        Ok(Self {
+            dir,
+            segments,
+            starts,
+            active: MemFile::new(),
+            max_segment_size,
+            encoding: IndexEncoding::Plain,
+            next_segment,
+        })
+    }
+
+    /// Sets the index encoding used for new segments
+    #[inline]
+    pub fn with_encoding(mut self, encoding: IndexEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Inserts a new entry into the active segment and returns its global ID. Seals the
+    /// active segment if it reached the size limit.
+    pub fn insert(&mut self, data: &[u8]) -> Result<usize, Error> {
+        let id = self.active_start() + self.active.insert(data);
+        if self.active.raw_len() >= self.max_segment_size {
+            self.seal()?;
+        }
+        Ok(id)
+    }
+
+    /// Writes the active segment to a new segment file and maps it. Does nothing if the
+    /// active segment is empty. The segment is written to a temporary file which is synced
+    /// and renamed, so an interrupted seal never leaves a partially written segment.
+    pub fn seal(&mut self) -> Result<(), Error> {
+        if self.active.is_empty() {
+            return Ok(());
+        }
+
+        let path = self.segment_path(self.next_segment);
+        let mut temp = path.clone().into_os_string();
+        temp.push(TEMP_SUFFIX);
+
+        let mut out = BufWriter::new(File::create(&temp)?);
+        self.active.write_to(&mut out, self.encoding)?;
+        out.flush()?;
+        out.get_ref().sync_all()?;
+        drop(out);
+        fs::rename(&temp, &path)?;
+
+        let segment = MappedFile::open(&path)?;
+        self.next_segment += 1;
+        self.starts.push(self.active_start());
+        self.segments.push(segment);
+        self.active = MemFile::new();
+        Ok(())
+    }
+
+    /// Returns the segment and its local ID of the entry with the given global ID. The
+    /// active segment has the position `segment_count()`.
+    pub fn locate(&self, id: usize) -> Option<(usize, usize)> {
+        let active_start = self.active_start();
+        if id >= active_start {
+            let local = id - active_start;
+            return (local < self.active.len()).then_some((self.segments.len(), local));
+        }
+        let segment = self.starts.partition_point(|i| *i <= id) - 1;
+        Some((segment, id - self.starts[segment]))
+    }
+
+    /// Returns the amount of sealed segments
+    #[inline]
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+
+    /// Returns all sealed segments
+    #[inline]
+    pub fn segments(&self) -> &[MappedFile] {
+        &self.segments
+    }
+
+    /// Returns the active segment
+    #[inline]
+    pub fn active(&self) -> &MemFile {
+        &self.active
+    }
+
+    /// Returns the directory of the segments
+    #[inline]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Global ID of the first entry in the active segment
+    #[inline]
+    fn active_start(&self) -> usize {
+        match self.segments.last() {
+            Some(last) => self.starts[self.starts.len() - 1] + last.len(),
+            None => 0,
+        }
+    }
+
+    #[inline]
+    fn segment_path(&self, number: u64) -> PathBuf {
+        self.dir.join(format!("{number:08}.{SEGMENT_EXT}"))
+    }
+}
+
+impl IndexedAccess for SegmentedFile {
+    #[inline]
+    fn get(&self, pos: usize) -> Option<&[u8]> {
+        let (segment, local) = self.locate(pos)?;
+        match self.segments.get(segment) {
+            Some(segment) => segment.get(local),
+            None => self.active.get(local),
+        }
+    }
+
+    #[inline]
+    fn get_unchecked(&self, pos: usize) -> &[u8] {
+        self.get(pos).unwrap()
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.active_start() + self.active.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::read_to_string;
+
+    #[test]
+    fn test_segmented() {
+        let dir = "test_segmented_file";
+        let _ = fs::remove_dir_all(dir);
+        let content = read_to_string("./testfiles/LICENSE").unwrap();
+        let words: Vec<_> = content.split_whitespace().collect();
+
+        let mut file = SegmentedFile::open(dir, 1000).unwrap();
+        for (id, word) in words.iter().enumerate() {
+            assert_eq!(file.insert(word.as_bytes()).unwrap(), id);
+        }
+        assert!(file.segment_count() > 1);
+        assert!(!file.active().is_empty());
+        assert_eq!(file.len(), words.len());
+        assert!(file.iter().eq(words.iter().map(|i| i.as_bytes())));
+        assert_eq!(file.get(words.len()), None);
+
+        let (segment, local) = file.locate(words.len() - 1).unwrap();
+        assert_eq!(segment, file.segment_count());
+        assert_eq!(
+            file.active().get(local),
+            Some(words[words.len() - 1].as_bytes())
+        );
+
+        file.seal().unwrap();
+        let count = file.segment_count();
+        drop(file);
+
+        let file = SegmentedFile::open(dir, 1000).unwrap();
+        assert_eq!(file.segment_count(), count);
+        assert!(file.active().is_empty());
+        assert!(file.iter().eq(words.iter().map(|i| i.as_bytes())));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_segment_numbering() {
+        let dir = Path::new("test_segment_numbering");
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir_all(dir).unwrap();
+        for (name, entry) in [("00000000.seg", "a"), ("00000002.seg", "b")] {
+            MemFile::from([entry].iter())
+                .save(dir.join(name), IndexEncoding::Plain)
+                .unwrap();
+        }
+        fs::write(dir.join("00000003.seg.tmp"), b"partial").unwrap();
+
+        let mut file = SegmentedFile::open(dir, 1000).unwrap();
+        assert!(!dir.join("00000003.seg.tmp").exists());
+        file.insert(b"c").unwrap();
+        file.seal().unwrap();
+        assert!(dir.join("00000003.seg").exists());
+        drop(file);
+
+        let file = SegmentedFile::open(dir, 1000).unwrap();
+        assert!(file.iter().eq([b"a", b"b", b"c"]));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
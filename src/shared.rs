@@ -0,0 +1,178 @@
+use crate::map::MappedFile;
+use std::{
+    fs,
+    io::Error,
+    path::{Path, PathBuf},
+    sync::{Arc, PoisonError, RwLock},
+    time::SystemTime,
+};
+
+/// A [`MappedFile`] that can be shared between threads and swapped for a newer version of
+/// the file while it is being read.
+///
+/// Readers take a snapshot, which keeps its mapping alive until the snapshot is dropped, even
+/// if the shared file has been swapped in the meantime. Swapping only takes a short write lock
+/// and never waits for readers to finish with their snapshots.
+///
+/// The current file is kept behind a `RwLock` instead of a lock free pointer. Both sides only
+/// hold the lock to clone or replace an `Arc`, new files are opened before taking it, so
+/// readers never wait for I/O and don't block each other.
+///
+/// Files should be replaced on disk by writing a new file and renaming it over the old one.
+/// Writing into a mapped file in place changes the data seen by existing snapshots.
+pub struct SharedMappedFile {
+    path: PathBuf,
+    current: RwLock<Current>,
+}
+
+/// The file handed out to new readers
+struct Current {
+    file: Arc<MappedFile>,
+    /// Incremented on each swap
+    generation: u64,
+    /// Size and modification time of the file when it was opened
+    stamp: Option<(u64, SystemTime)>,
+}
+
+// Shared files are handed to other threads, which requires the mapping to be sendable
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<MappedFile>();
+    assert_send_sync::<SharedMappedFile>();
+};
+
+impl SharedMappedFile {
+    /// Opens and maps the file at `path`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        let stamp = stamp(&path);
+        let file = Arc::new(MappedFile::open(&path)?);
+        Ok(Self {
+            path,
+            current: RwLock::new(Current {
+                file,
+                generation: 0,
+                stamp,
+            }),
+        })
+    }
+
+    /// Returns the current file. The snapshot stays valid after the shared file has been
+    /// swapped or reloaded.
+    #[inline]
+    pub fn snapshot(&self) -> Arc<MappedFile> {
+        self.read().file.clone()
+    }
+
+    /// Returns the amount of times the file has been swapped
+    #[inline]
+    pub fn generation(&self) -> u64 {
+        self.read().generation
+    }
+
+    /// Returns the path the file is reloaded from
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Opens the file at the path again and hands it out to new readers
+    pub fn reload(&self) -> Result<(), Error> {
+        let stamp = stamp(&self.path);
+        let file = MappedFile::open(&self.path)?;
+        self.replace(file, stamp);
+        Ok(())
+    }
+
+    /// Reloads the file if its size or modification time changed since it has been opened.
+    /// Returns `true` if the file has been reloaded.
+    pub fn reload_if_changed(&self) -> Result<bool, Error> {
+        let stamp = stamp(&self.path);
+        if stamp.is_some() && stamp == self.read().stamp {
+            return Ok(false);
+        }
+        let file = MappedFile::open(&self.path)?;
+        self.replace(file, stamp);
+        Ok(true)
+    }
+
+    /// Hands out `file` to new readers and returns the previous file. `file` doesn't have to
+    /// be mapped from the path of this shared file, but later reloads use the original path.
+    /// The next `reload_if_changed` always reloads, as the swapped in file might not match the
+    /// file at the path.
+    #[inline]
+    pub fn swap(&self, file: MappedFile) -> Arc<MappedFile> {
+        self.replace(file, None)
+    }
+
+    fn replace(&self, file: MappedFile, stamp: Option<(u64, SystemTime)>) -> Arc<MappedFile> {
+        // The state is always consistent, so a panicking reader doesn't prevent swapping
+        let mut current = self.current.write().unwrap_or_else(PoisonError::into_inner);
+        current.generation += 1;
+        current.stamp = stamp;
+        std::mem::replace(&mut current.file, Arc::new(file))
+    }
+
+    #[inline]
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, Current> {
+        self.current.read().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Returns size and modification time of the file at `path`
+#[inline]
+fn stamp(path: &Path) -> Option<(u64, SystemTime)> {
+    let meta = fs::metadata(path).ok()?;
+    Some((meta.len(), meta.modified().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{encoded_index::IndexEncoding, memory::MemFile, traits::IndexedAccess};
+
+    fn write(path: &str, entries: &[&str]) {
+        let tmp = format!("{path}.tmp");
+        MemFile::from(entries.iter())
+            .save(&tmp, IndexEncoding::Plain)
+            .unwrap();
+        fs::rename(tmp, path).unwrap();
+    }
+
+    #[test]
+    fn test_shared_swap() {
+        let path = "test_shared_mapped_file";
+        write(path, &["a", "b"]);
+        let shared = Arc::new(SharedMappedFile::open(path).unwrap());
+        assert!(!shared.reload_if_changed().unwrap());
+
+        let old = shared.snapshot();
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                let shared = shared.clone();
+                s.spawn(move || {
+                    for _ in 0..100 {
+                        let file = shared.snapshot();
+                        assert!(file.get(0) == Some(b"a") || file.get(0) == Some(b"c"));
+                    }
+                });
+            }
+            write(path, &["c", "d", "e"]);
+            assert!(shared.reload_if_changed().unwrap());
+        });
+
+        assert_eq!(shared.generation(), 1);
+        assert_eq!(shared.snapshot().len(), 3);
+        assert!(old.iter().eq([b"a", b"b"]));
+
+        let previous = shared.swap(MappedFile::open(path).unwrap());
+        assert_eq!(previous.get(2), Some(&b"e"[..]));
+        assert_eq!(shared.generation(), 2);
+        assert!(shared.reload_if_changed().unwrap());
+        assert!(!shared.reload_if_changed().unwrap());
+        assert_eq!(shared.generation(), 3);
+        fs::remove_file(path).unwrap();
+        assert!(shared.reload().is_err());
+        assert_eq!(shared.snapshot().len(), 3);
+    }
+}
@@ -0,0 +1,633 @@
+use crate::{
+    encoded_index::IndexEncoding,
+    format::{invalid_data, section, Layout},
+    memory::MemFile,
+    traits::{IndexedAccess, IndexedAccessMut},
+};
+use std::{
+    cmp::Ordering,
+    fs::File,
+    io::{BufWriter, Error, ErrorKind, Write},
+    ops::{Bound, Range, RangeBounds},
+    path::Path,
+};
+
+#[cfg(feature = "mapped")]
+use crate::{ext_sort::ExternalSorter, map::MappedFile, writer::FileWriter};
+#[cfg(feature = "typed")]
+use {serde::de::DeserializeOwned, std::marker::PhantomData};
+
+/// Defines the order of entries in a [`SortedFile`]
+pub trait Comparator {
+    /// Compares two entries
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering;
+
+    /// Identifies the order in saved files, which can only be loaded using a comparator with
+    /// the same name. Comparators defining different orders should have different names.
+    /// Files sorted by unnamed comparators, like closures, can't be saved or loaded.
+    #[inline]
+    fn name(&self) -> &str {
+        ""
+    }
+}
+
+/// Orders entries by their raw bytes
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Bytewise;
+
+impl Comparator for Bytewise {
+    #[inline]
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        a.cmp(b)
+    }
+
+    #[inline]
+    fn name(&self) -> &str {
+        "bytewise"
+    }
+}
+
+impl<F> Comparator for F
+where
+    F: Fn(&[u8], &[u8]) -> Ordering,
+{
+    #[inline]
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        (self)(a, b)
+    }
+}
+
+/// Orders typed entries by a key extracted from the decoded entry. Entries that can't be
+/// decoded are ordered before all others.
+#[cfg(feature = "typed")]
+pub struct ByKey<T, K, F> {
+    key: F,
+    name: &'static str,
+    _marker: PhantomData<fn(&T) -> K>,
+}
+
+#[cfg(feature = "typed")]
+impl<T, K, F> ByKey<T, K, F>
+where
+    T: DeserializeOwned,
+    K: Ord,
+    F: Fn(&T) -> K,
+{
+    /// Creates a new comparator that orders entries by the key returned by `key`
+    #[inline]
+    pub fn new(key: F) -> Self {
+        Self {
+            key,
+            name: "",
+            _marker: PhantomData,
+        }
+    }
+
+    /// Sets the name identifying the order in saved files, see [`Comparator::name`]
+    #[inline]
+    pub fn with_name(mut self, name: &'static str) -> Self {
+        self.name = name;
+        self
+    }
+
+    #[inline]
+    fn key(&self, entry: &[u8]) -> Option<K> {
+        let item: T = bincode::deserialize(entry).ok()?;
+        Some((self.key)(&item))
+    }
+}
+
+#[cfg(feature = "typed")]
+impl<T, K, F> Comparator for ByKey<T, K, F>
+where
+    T: DeserializeOwned,
+    K: Ord,
+    F: Fn(&T) -> K,
+{
+    #[inline]
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        self.key(a).cmp(&self.key(b))
+    }
+
+    #[inline]
+    fn name(&self) -> &str {
+        self.name
+    }
+}
+
+/// A file whose entries are sorted by a [`Comparator`], allowing to search entries using
+/// binary search.
+pub struct SortedFile<F, C = Bytewise> {
+    file: F,
+    cmp: C,
+}
+
+impl<F, C> SortedFile<F, C>
+where
+    F: IndexedAccess,
+    C: Comparator,
+{
+    /// Wraps a file that is already sorted. Returns `None` if the entries are not sorted
+    pub fn new(file: F, cmp: C) -> Option<Self> {
+        if !is_sorted(&file, &cmp) {
+            return None;
+        }
+        Some(Self { file, cmp })
+    }
+
+    /// Wraps a file without checking whether it is sorted. Searching an unsorted file
+    /// returns unspecified results.
+    #[inline]
+    pub fn new_unchecked(file: F, cmp: C) -> Self {
+        Self { file, cmp }
+    }
+
+    /// Returns the ID of an entry that equals `key`
+    #[inline]
+    pub fn find(&self, key: &[u8]) -> Option<usize> {
+        let pos = self.lower_bound(key);
+        let entry = self.file.get(pos)?;
+        (self.cmp.compare(entry, key) == Ordering::Equal).then_some(pos)
+    }
+
+    /// Returns the ID of the first entry that is not less than `key` or `len()` if there is none
+    #[inline]
+    pub fn lower_bound(&self, key: &[u8]) -> usize {
+        self.partition_point(|entry| self.cmp.compare(entry, key) == Ordering::Less)
+    }
+
+    /// Returns the ID of the first entry that is greater than `key` or `len()` if there is none
+    #[inline]
+    pub fn upper_bound(&self, key: &[u8]) -> usize {
+        self.partition_point(|entry| self.cmp.compare(entry, key) != Ordering::Greater)
+    }
+
+    /// Returns the range of IDs of all entries within the given key range
+    #[inline]
+    pub fn range<K, R>(&self, range: R) -> Range<usize>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        self.id_range(
+            range,
+            |key| self.lower_bound(key.as_ref()),
+            |key| self.upper_bound(key.as_ref()),
+        )
+    }
+
+    /// Returns the underlying file
+    #[inline]
+    pub fn file(&self) -> &F {
+        &self.file
+    }
+
+    /// Returns the underlying file
+    #[inline]
+    pub fn into_inner(self) -> F {
+        self.file
+    }
+
+    /// Returns the range of IDs within `range`, using `lower` and `upper` to find the bounds
+    /// of a single key
+    fn id_range<K, R, L, U>(&self, range: R, lower: L, upper: U) -> Range<usize>
+    where
+        R: RangeBounds<K>,
+        L: Fn(&K) -> usize,
+        U: Fn(&K) -> usize,
+    {
+        let start = match range.start_bound() {
+            Bound::Included(key) => lower(key),
+            Bound::Excluded(key) => upper(key),
+            Bound::Unbounded => 0,
+        };
+
+        let end = match range.end_bound() {
+            Bound::Included(key) => upper(key),
+            Bound::Excluded(key) => lower(key),
+            Bound::Unbounded => self.len(),
+        };
+
+        start..end.max(start)
+    }
+
+    /// Returns the first ID for which `pred` returns false. `pred` has to return true
+    /// for all entries before and false for all entries after that ID.
+    fn partition_point<P>(&self, pred: P) -> usize
+    where
+        P: Fn(&[u8]) -> bool,
+    {
+        let mut low = 0;
+        let mut high = self.file.len();
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if pred(self.file.get_unchecked(mid)) {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        low
+    }
+}
+
+#[cfg(feature = "typed")]
+impl<F, T, K, KF> SortedFile<F, ByKey<T, K, KF>>
+where
+    F: IndexedAccess,
+    T: DeserializeOwned,
+    K: Ord,
+    KF: Fn(&T) -> K,
+{
+    /// Returns the ID of an entry whose key equals `key`
+    #[inline]
+    pub fn find_key(&self, key: &K) -> Option<usize> {
+        let pos = self.lower_bound_key(key);
+        let entry_key = self.cmp.key(self.file.get(pos)?)?;
+        (entry_key == *key).then_some(pos)
+    }
+
+    /// Returns the ID of the first entry whose key is not less than `key` or `len()` if there
+    /// is none
+    #[inline]
+    pub fn lower_bound_key(&self, key: &K) -> usize {
+        self.partition_point(|entry| self.cmp.key(entry).is_none_or(|i| i < *key))
+    }
+
+    /// Returns the ID of the first entry whose key is greater than `key` or `len()` if there
+    /// is none
+    #[inline]
+    pub fn upper_bound_key(&self, key: &K) -> usize {
+        self.partition_point(|entry| self.cmp.key(entry).is_none_or(|i| i <= *key))
+    }
+
+    /// Returns the range of IDs of all entries whose key is within `range`
+    #[inline]
+    pub fn range_key<R: RangeBounds<K>>(&self, range: R) -> Range<usize> {
+        self.id_range(
+            range,
+            |key| self.lower_bound_key(key),
+            |key| self.upper_bound_key(key),
+        )
+    }
+}
+
+impl<C: Comparator> SortedFile<MemFile, C> {
+    /// Builds a new sorted file from the entries of `iter`. If the entries are not already
+    /// sorted they get sorted in memory. Use [`SortedFile::build_to`] for inputs that don't
+    /// fit into memory.
+    pub fn build<I, T>(iter: I, cmp: C) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<[u8]>,
+    {
+        let mut file = MemFile::new();
+        file.extend(iter);
+        let file = sort_in_memory(file, &cmp);
+        Self { file, cmp }
+    }
+
+    /// Saves the file and marks it as sorted by its comparator
+    pub fn save<P: AsRef<Path>>(&self, path: P, encoding: IndexEncoding) -> Result<(), Error> {
+        comparator_name(&self.cmp)?;
+        let out = BufWriter::new(File::create(path)?);
+        self.write_to(out, encoding)
+    }
+
+    /// Writes the file and marks it as sorted by its comparator
+    #[inline]
+    pub fn write_to<W: Write>(&self, w: W, encoding: IndexEncoding) -> Result<(), Error> {
+        let name = comparator_name(&self.cmp)?;
+        self.file
+            .write_with_sections(w, encoding, &[(section::SORTED, name)])
+    }
+
+    /// Loads a file that has been saved as sorted. Fails if the name of `cmp` isn't the name
+    /// of the comparator the file has been sorted with.
+    pub fn load<P: AsRef<Path>>(path: P, cmp: C) -> Result<Self, Error> {
+        let bytes = std::fs::read(path)?;
+        let layout = Layout::parse(&bytes)?;
+        let sorted = layout.required_section(section::SORTED)?;
+        check_comparator(&bytes[sorted.range()], &cmp)?;
+        let file = MemFile::from_layout(&bytes, &layout)?;
+        Ok(Self { file, cmp })
+    }
+}
+
+#[cfg(feature = "mapped")]
+impl<C: Comparator> SortedFile<MappedFile, C> {
+    /// Opens a file that has been saved as sorted. Fails if the name of `cmp` isn't the name
+    /// of the comparator the file has been sorted with.
+    pub fn open<P: AsRef<Path>>(path: P, cmp: C) -> Result<Self, Error> {
+        let file = MappedFile::open(path)?;
+        let sorted = file
+            .section(section::SORTED)
+            .ok_or_else(|| invalid_data("File is not marked as sorted"))?;
+        check_comparator(sorted, &cmp)?;
+        Ok(Self { file, cmp })
+    }
+
+    /// Builds a sorted file at `path` from the entries of `iter` and opens it. Entries are
+    /// sorted in memory as long as they don't exceed `memory_limit` bytes. Larger inputs are
+    /// written to a temporary file next to `path` and sorted using an [`ExternalSorter`] with
+    /// runs of `memory_limit` bytes.
+    pub fn build_to<I, T, P>(
+        iter: I,
+        cmp: C,
+        path: P,
+        encoding: IndexEncoding,
+        memory_limit: usize,
+    ) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<[u8]>,
+        P: AsRef<Path>,
+    {
+        comparator_name(&cmp)?;
+        let path = path.as_ref();
+        let mut iter = iter.into_iter();
+        let mut file = MemFile::new();
+        for entry in iter.by_ref() {
+            file.insert(entry.as_ref());
+            if file.raw_len() > memory_limit {
+                break;
+            }
+        }
+
+        if file.raw_len() <= memory_limit {
+            let sorted = SortedFile::new_unchecked(sort_in_memory(file, &cmp), cmp);
+            sorted.save(path, encoding)?;
+            return Self::open(path, sorted.cmp);
+        }
+
+        let mut unsorted = path.as_os_str().to_owned();
+        unsorted.push(".unsorted");
+        let result = Self::sort_external(
+            file,
+            iter,
+            cmp,
+            path,
+            unsorted.as_ref(),
+            encoding,
+            memory_limit,
+        );
+        let removed = std::fs::remove_file(unsorted);
+        let cmp = result?;
+        removed?;
+        Self::open(path, cmp)
+    }
+
+    /// Writes `file` followed by the remaining entries of `iter` to `unsorted` and sorts them
+    /// into `path`. Returns the comparator.
+    fn sort_external<I, T>(
+        file: MemFile,
+        iter: I,
+        cmp: C,
+        path: &Path,
+        unsorted: &Path,
+        encoding: IndexEncoding,
+        run_size: usize,
+    ) -> Result<C, Error>
+    where
+        I: Iterator<Item = T>,
+        T: AsRef<[u8]>,
+    {
+        let mut writer = FileWriter::create(unsorted, IndexEncoding::Plain)?;
+        for entry in file.iter() {
+            writer.insert(entry)?;
+        }
+        drop(file);
+        for entry in iter {
+            writer.insert(entry.as_ref())?;
+        }
+        writer.finish()?.flush()?;
+
+        let temp_dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let sorter = ExternalSorter::with_comparator(cmp)
+            .run_size(run_size)
+            .temp_dir(temp_dir);
+
+        let input = MappedFile::open(unsorted)?;
+        let mut out = FileWriter::create(path, encoding)?;
+        sorter.sort(&input, &mut out)?;
+        let cmp = sorter.into_comparator();
+        out.finish_with_sections(&[(section::SORTED, comparator_name(&cmp)?)])?
+            .flush()?;
+        Ok(cmp)
+    }
+}
+
+impl<F: IndexedAccess, C> IndexedAccess for SortedFile<F, C> {
+    #[inline]
+    fn get(&self, pos: usize) -> Option<&[u8]> {
+        self.file.get(pos)
+    }
+
+    #[inline]
+    fn get_unchecked(&self, pos: usize) -> &[u8] {
+        self.file.get_unchecked(pos)
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.file.len()
+    }
+}
+
+/// Returns the entries of `file` in sorted order
+fn sort_in_memory<C: Comparator>(file: MemFile, cmp: &C) -> MemFile {
+    if is_sorted(&file, cmp) {
+        return file;
+    }
+
+    let mut ids: Vec<_> = (0..file.len()).collect();
+    ids.sort_by(|a, b| cmp.compare(file.get_unchecked(*a), file.get_unchecked(*b)));
+
+    let mut sorted = MemFile::with_capacity(file.raw_len());
+    for id in ids {
+        sorted.insert(file.get_unchecked(id));
+    }
+    sorted
+}
+
+/// Returns the name of `cmp` stored in sorted files. Fails if the comparator has no name.
+#[inline]
+fn comparator_name<C: Comparator>(cmp: &C) -> Result<&[u8], Error> {
+    match cmp.name() {
+        "" => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Comparators of saved files need a name",
+        )),
+        name => Ok(name.as_bytes()),
+    }
+}
+
+/// Checks that a file has been sorted by `cmp`, given the content of its sorted section
+fn check_comparator<C: Comparator>(sorted: &[u8], cmp: &C) -> Result<(), Error> {
+    if sorted != comparator_name(cmp)? {
+        return Err(invalid_data(format!(
+            "File has been sorted by comparator '{}'",
+            String::from_utf8_lossy(sorted)
+        )));
+    }
+    Ok(())
+}
+
+/// Returns `true` if all entries of `file` are in order
+fn is_sorted<F: IndexedAccess, C: Comparator>(file: &F, cmp: &C) -> bool {
+    (1..file.len())
+        .all(|i| cmp.compare(file.get_unchecked(i - 1), file.get_unchecked(i)) != Ordering::Greater)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::read_to_string;
+
+    fn words() -> Vec<String> {
+        let content = read_to_string("./testfiles/LICENSE").unwrap();
+        content.split_whitespace().map(|i| i.to_string()).collect()
+    }
+
+    #[test]
+    fn test_search() {
+        let mut words = words();
+        let file = SortedFile::build(words.iter(), Bytewise);
+        words.sort();
+
+        assert!(file.iter().eq(words.iter().map(|i| i.as_bytes())));
+
+        for word in words.iter() {
+            let id = file.find(word.as_bytes()).unwrap();
+            assert_eq!(file.get(id), Some(word.as_bytes()));
+
+            let lower = file.lower_bound(word.as_bytes());
+            let upper = file.upper_bound(word.as_bytes());
+            assert_eq!(lower, words.partition_point(|i| i < word));
+            assert_eq!(upper, words.partition_point(|i| i <= word));
+            assert_eq!(file.range(word.as_bytes()..=word.as_bytes()), lower..upper);
+        }
+
+        assert_eq!(file.find(b"not in the license"), None);
+
+        let range = file.range(b"a".as_slice()..b"c".as_slice());
+        let exp = words
+            .iter()
+            .filter(|i| i.as_str() >= "a" && i.as_str() < "c");
+        assert!(range
+            .map(|i| file.get_unchecked(i))
+            .eq(exp.map(|i| i.as_bytes())));
+
+        assert_eq!(file.range::<&[u8], _>(..), 0..words.len());
+        assert_eq!(file.range(b"z".as_slice()..b"a".as_slice()).len(), 0);
+    }
+
+    #[test]
+    fn test_comparator() {
+        let by_len = |a: &[u8], b: &[u8]| a.len().cmp(&b.len()).then(a.cmp(b));
+        let file = SortedFile::build(["ccc", "a", "bb", "dddd", "b"], by_len);
+
+        let entries: Vec<_> = file.iter().collect();
+        assert_eq!(entries, [&b"a"[..], b"b", b"bb", b"ccc", b"dddd"]);
+        assert_eq!(file.find(b"ccc"), Some(3));
+        assert_eq!(file.range(b"b".as_slice()..b"zzz".as_slice()), 1..4);
+
+        let unsorted = MemFile::from(["b", "a"].iter());
+        assert!(SortedFile::new(unsorted, Bytewise).is_none());
+    }
+
+    #[test]
+    fn test_save_load() {
+        let file = SortedFile::build(words().iter(), Bytewise);
+        file.save("test_sorted_save_load", IndexEncoding::BlockPacked)
+            .unwrap();
+
+        let loaded = SortedFile::load("test_sorted_save_load", Bytewise).unwrap();
+        assert!(loaded.iter().eq(file.iter()));
+
+        file.file()
+            .save("test_sorted_save_load", IndexEncoding::Plain)
+            .unwrap();
+        assert!(SortedFile::load("test_sorted_save_load", Bytewise).is_err());
+
+        // Files can only be loaded with a comparator of the same name
+        file.save("test_sorted_save_load", IndexEncoding::Plain)
+            .unwrap();
+        let by_len = |a: &[u8], b: &[u8]| a.len().cmp(&b.len());
+        assert!(SortedFile::load("test_sorted_save_load", by_len).is_err());
+
+        // Files sorted by unnamed comparators can't be saved
+        let unnamed = SortedFile::build(["b", "a"], by_len);
+        let err = unnamed.write_to(vec![], IndexEncoding::Plain).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+        std::fs::remove_file("test_sorted_save_load").unwrap();
+    }
+
+    #[cfg(feature = "mapped")]
+    #[test]
+    fn test_mapped() {
+        let words = words();
+        let file = SortedFile::build(words.iter(), Bytewise);
+        file.save("test_sorted_mapped", IndexEncoding::EliasFano)
+            .unwrap();
+
+        let mapped = SortedFile::open("test_sorted_mapped", Bytewise).unwrap();
+        for word in words.iter() {
+            assert_eq!(mapped.find(word.as_bytes()), file.find(word.as_bytes()));
+        }
+
+        // Sorted in memory and externally
+        for limit in [usize::MAX, 1024] {
+            let built = SortedFile::build_to(
+                words.iter(),
+                Bytewise,
+                "test_sorted_mapped",
+                IndexEncoding::Plain,
+                limit,
+            )
+            .unwrap();
+            assert!(built.iter().eq(file.iter()));
+            let opened = SortedFile::open("test_sorted_mapped", Bytewise).unwrap();
+            assert_eq!(opened.len(), words.len());
+        }
+        assert!(!Path::new("test_sorted_mapped.unsorted").exists());
+
+        std::fs::remove_file("test_sorted_mapped").unwrap();
+    }
+
+    #[cfg(feature = "typed")]
+    #[test]
+    fn test_by_key() {
+        let lengths: Vec<u32> = words().iter().map(|i| i.len() as u32).collect();
+        let entries = lengths
+            .iter()
+            .enumerate()
+            .map(|(pos, len)| bincode::serialize(&(*len, pos as u32)).unwrap());
+        let by_len = || ByKey::new(|i: &(u32, u32)| i.0).with_name("length");
+        let file = SortedFile::build(entries, by_len());
+
+        let count = |pred: &dyn Fn(u32) -> bool| lengths.iter().filter(|i| pred(**i)).count();
+        assert_eq!(file.lower_bound_key(&3), count(&|i| i < 3));
+        assert_eq!(file.upper_bound_key(&3), count(&|i| i <= 3));
+        assert_eq!(file.range_key(2..4).len(), count(&|i| (2..4).contains(&i)));
+        assert_eq!(file.find_key(&1000), None);
+        let id = file.find_key(&5).unwrap();
+        let entry: (u32, u32) = bincode::deserialize(file.get_unchecked(id)).unwrap();
+        assert_eq!(entry.0, 5);
+
+        file.save("test_sorted_by_key", IndexEncoding::Plain)
+            .unwrap();
+        let by_pos = ByKey::new(|i: &(u32, u32)| i.1).with_name("position");
+        assert!(SortedFile::load("test_sorted_by_key", by_pos).is_err());
+        let loaded = SortedFile::load("test_sorted_by_key", by_len()).unwrap();
+        assert_eq!(
+            loaded.find_key(&5).map(|i| loaded.get_unchecked(i)),
+            Some(file.get_unchecked(id))
+        );
+        std::fs::remove_file("test_sorted_by_key").unwrap();
+    }
+}
@@ -0,0 +1,439 @@
+use crate::{
+    encoded_index::IndexEncoding, format::invalid_data, traits::IndexedAccess, writer::FileWriter,
+};
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Error, Write},
+    path::{Path, PathBuf},
+};
+
+/// Name of the manifest file written next to the shards
+pub const MANIFEST_NAME: &str = "manifest";
+
+/// Describes which entries of a split file landed in which shard
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Manifest {
+    shards: Vec<Shard>,
+    /// All ID ranges of all shards ordered by their first ID, used to locate entries
+    table: Vec<Segment>,
+}
+
+/// A single shard of a split file
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Shard {
+    /// File name of the shard, relative to the directory of the manifest
+    pub name: String,
+    /// Ascending ranges of the original IDs stored in the shard. The entries keep their
+    /// order, so the first ID of the first range has the local ID 0.
+    pub ids: Vec<IdRange>,
+}
+
+/// The original IDs `start`, `start + step`, `start + 2 * step`, ... smaller than `end`.
+/// Entries that are distributed over shards in an interleaved way, eg. round robin, are
+/// described by a single range per shard this way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IdRange {
+    pub start: usize,
+    pub end: usize,
+    pub step: usize,
+}
+
+impl IdRange {
+    /// Returns the amount of IDs in the range
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.end.saturating_sub(self.start).div_ceil(self.step)
+    }
+
+    /// Returns `true` if the range contains no IDs
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.end <= self.start
+    }
+
+    /// Returns the position of `id` within the range
+    #[inline]
+    pub fn position(&self, id: usize) -> Option<usize> {
+        let offset = id.checked_sub(self.start).filter(|_| id < self.end)?;
+        offset
+            .is_multiple_of(self.step)
+            .then_some(offset / self.step)
+    }
+
+    /// Returns an iterator over all IDs in the range
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = usize> {
+        (self.start..self.end).step_by(self.step)
+    }
+}
+
+/// An ID range of a shard in the lookup table of a [`Manifest`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Segment {
+    ids: IdRange,
+    shard: usize,
+    /// Local ID of the first entry of the range
+    local: usize,
+    /// Largest end of this and all previous ranges in the table
+    max_end: usize,
+}
+
+impl Shard {
+    /// Returns the amount of entries in the shard
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.ids.iter().map(|i| i.len()).sum()
+    }
+
+    /// Returns `true` if the shard has no entries
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ids.iter().all(|i| i.is_empty())
+    }
+
+    /// Returns the local ID of the original entry `id` if it is in this shard
+    pub fn local_id(&self, id: usize) -> Option<usize> {
+        let mut local = 0;
+        for range in self.ids.iter() {
+            if let Some(pos) = range.position(id) {
+                return Some(local + pos);
+            }
+            local += range.len();
+        }
+        None
+    }
+
+    /// Adds the next ID to the shard. IDs have to be ascending.
+    #[inline]
+    fn push(&mut self, id: usize) {
+        match self.ids.last_mut() {
+            Some(last) if last.len() == 1 => {
+                last.step = id - last.start;
+                last.end = id + 1;
+            }
+            Some(last) if last.end - 1 + last.step == id => last.end = id + 1,
+            _ => self.ids.push(IdRange {
+                start: id,
+                end: id + 1,
+                step: 1,
+            }),
+        }
+    }
+}
+
+impl Manifest {
+    /// Creates a new manifest from its shards
+    pub fn new(shards: Vec<Shard>) -> Self {
+        let mut table = vec![];
+        for (pos, shard) in shards.iter().enumerate() {
+            let mut local = 0;
+            for ids in shard.ids.iter().filter(|i| !i.is_empty()) {
+                table.push(Segment {
+                    ids: *ids,
+                    shard: pos,
+                    local,
+                    max_end: 0,
+                });
+                local += ids.len();
+            }
+        }
+
+        table.sort_by_key(|i| i.ids.start);
+        let mut max_end = 0;
+        for segment in table.iter_mut() {
+            max_end = max_end.max(segment.ids.end);
+            segment.max_end = max_end;
+        }
+
+        Self { shards, table }
+    }
+
+    /// Returns all shards
+    #[inline]
+    pub fn shards(&self) -> &[Shard] {
+        &self.shards
+    }
+
+    /// Returns the shard and local ID of the original entry `id`
+    pub fn locate(&self, id: usize) -> Option<(usize, usize)> {
+        // Ranges starting after `id` can't contain it. Of the others, only those ending
+        // after `id` need to be checked, which are found using the largest end so far.
+        let end = self.table.partition_point(|i| i.ids.start <= id);
+        self.table[..end]
+            .iter()
+            .rev()
+            .take_while(|i| i.max_end > id)
+            .find_map(|i| Some((i.shard, i.local + i.ids.position(id)?)))
+    }
+
+    /// Saves the manifest to `path`. Each line contains the name of a shard followed by its
+    /// ID ranges, separated by tabs. Ranges are written as `start..end` or `start..end:step`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let mut out = BufWriter::new(File::create(path)?);
+        for shard in self.shards.iter() {
+            out.write_all(shard.name.as_bytes())?;
+            for range in shard.ids.iter() {
+                write!(out, "\t{}..{}", range.start, range.end)?;
+                if range.step != 1 {
+                    write!(out, ":{}", range.step)?;
+                }
+            }
+            out.write_all(b"\n")?;
+        }
+        out.flush()
+    }
+
+    /// Loads a manifest that has been saved with `Manifest::save`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let content = fs::read_to_string(path)?;
+        let mut shards = vec![];
+        for line in content.lines() {
+            let mut parts = line.split('\t');
+            let name = parts.next().unwrap_or_default().to_string();
+            let ids = parts.map(parse_range).collect::<Result<_, Error>>()?;
+            shards.push(Shard { name, ids });
+        }
+        Ok(Self::new(shards))
+    }
+}
+
+/// Parses a range written by `Manifest::save`
+fn parse_range(s: &str) -> Result<IdRange, Error> {
+    let parse = |s: &str| s.parse().map_err(|_| invalid_data("Invalid ID"));
+    let (start, end) = s
+        .split_once("..")
+        .ok_or_else(|| invalid_data("Invalid ID range"))?;
+    let (end, step) = match end.split_once(':') {
+        Some((end, step)) => (end, parse(step)?),
+        None => (end, 1),
+    };
+    if step == 0 {
+        return Err(invalid_data("Invalid ID range step"));
+    }
+    Ok(IdRange {
+        start: parse(start)?,
+        end: parse(end)?,
+        step,
+    })
+}
+
+/// Splits `file` into `count` shards of about the same amount of entries, written to `dir`.
+/// Returns the manifest, which is saved to `dir` as well.
+pub fn split_by_count<F, P>(
+    file: &F,
+    dir: P,
+    count: usize,
+    encoding: IndexEncoding,
+) -> Result<Manifest, Error>
+where
+    F: IndexedAccess,
+    P: AsRef<Path>,
+{
+    let count = count.max(1);
+    let len = file.len();
+    split_by(file, dir, count, encoding, |id, _| id * count / len.max(1))
+}
+
+/// Splits `file` into shards of at most `max_size` bytes of entry data, written to `dir`. Each
+/// shard contains at least one entry, so entries larger than `max_size` get a shard of their
+/// own. Returns the manifest, which is saved to `dir` as well.
+pub fn split_by_size<F, P>(
+    file: &F,
+    dir: P,
+    max_size: usize,
+    encoding: IndexEncoding,
+) -> Result<Manifest, Error>
+where
+    F: IndexedAccess,
+    P: AsRef<Path>,
+{
+    let mut splitter = Splitter::new(dir.as_ref(), encoding);
+    let mut shard_size = 0;
+    for (id, entry) in file.iter().enumerate() {
+        if splitter.writers.is_empty() || (shard_size > 0 && shard_size + entry.len() > max_size) {
+            splitter.add_shard()?;
+            shard_size = 0;
+        }
+        shard_size += entry.len();
+        let shard = splitter.writers.len() - 1;
+        splitter.insert(shard, id, entry)?;
+    }
+    splitter.finish()
+}
+
+/// Splits `file` into `count` shards, written to `dir`. `shard` returns the shard of each
+/// entry given its ID and data. Returns the manifest, which is saved to `dir` as well.
+pub fn split_by<F, P, S>(
+    file: &F,
+    dir: P,
+    count: usize,
+    encoding: IndexEncoding,
+    mut shard: S,
+) -> Result<Manifest, Error>
+where
+    F: IndexedAccess,
+    P: AsRef<Path>,
+    S: FnMut(usize, &[u8]) -> usize,
+{
+    let mut splitter = Splitter::new(dir.as_ref(), encoding);
+    for _ in 0..count {
+        splitter.add_shard()?;
+    }
+    for (id, entry) in file.iter().enumerate() {
+        let shard = shard(id, entry);
+        if shard >= count {
+            return Err(Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Shard {shard} of entry {id} out of range"),
+            ));
+        }
+        splitter.insert(shard, id, entry)?;
+    }
+    splitter.finish()
+}
+
+/// Writes entries into multiple shard files. All written files are removed again if the
+/// splitter gets dropped without being finished, eg. because of an error.
+struct Splitter<'a> {
+    dir: &'a Path,
+    encoding: IndexEncoding,
+    writers: Vec<FileWriter<BufWriter<File>>>,
+    shards: Vec<Shard>,
+    finished: bool,
+}
+
+impl<'a> Splitter<'a> {
+    #[inline]
+    fn new(dir: &'a Path, encoding: IndexEncoding) -> Self {
+        Self {
+            dir,
+            encoding,
+            writers: vec![],
+            shards: vec![],
+            finished: false,
+        }
+    }
+
+    fn add_shard(&mut self) -> Result<(), Error> {
+        if self.writers.is_empty() {
+            fs::create_dir_all(self.dir)?;
+        }
+        let name = format!("{:08}.shard", self.writers.len());
+        let path = self.shard_path(&name);
+        self.shards.push(Shard { name, ids: vec![] });
+        self.writers.push(FileWriter::create(path, self.encoding)?);
+        Ok(())
+    }
+
+    #[inline]
+    fn insert(&mut self, shard: usize, id: usize, data: &[u8]) -> Result<(), Error> {
+        self.writers[shard].insert(data)?;
+        self.shards[shard].push(id);
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Manifest, Error> {
+        fs::create_dir_all(self.dir)?;
+        for writer in std::mem::take(&mut self.writers) {
+            writer.finish()?.flush()?;
+        }
+        let manifest = Manifest::new(std::mem::take(&mut self.shards));
+        manifest.save(self.dir.join(MANIFEST_NAME))?;
+        self.finished = true;
+        Ok(manifest)
+    }
+
+    #[inline]
+    fn shard_path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+}
+
+impl Drop for Splitter<'_> {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        // Close the files before removing them
+        self.writers.clear();
+        for shard in self.shards.iter() {
+            let _ = fs::remove_file(self.shard_path(&shard.name));
+        }
+        let _ = fs::remove_file(self.dir.join(MANIFEST_NAME));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemFile;
+    use std::fs::read_to_string;
+
+    fn check(file: &MemFile, dir: &str, manifest: &Manifest) {
+        assert_eq!(
+            &Manifest::load(Path::new(dir).join(MANIFEST_NAME)).unwrap(),
+            manifest
+        );
+        let shards: Vec<_> = manifest
+            .shards()
+            .iter()
+            .map(|i| MemFile::load(Path::new(dir).join(&i.name)).unwrap())
+            .collect();
+        assert_eq!(shards.iter().map(|i| i.len()).sum::<usize>(), file.len());
+        for (id, entry) in file.iter().enumerate() {
+            let (shard, local) = manifest.locate(id).unwrap();
+            assert_eq!(manifest.shards()[shard].local_id(id), Some(local));
+            assert_eq!(shards[shard].get(local), Some(entry));
+        }
+        assert_eq!(manifest.locate(file.len()), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_split() {
+        let content = read_to_string("./testfiles/LICENSE").unwrap();
+        let file = MemFile::from(content.split_whitespace());
+
+        let dir = "test_split_count";
+        let manifest = split_by_count(&file, dir, 4, IndexEncoding::Plain).unwrap();
+        assert_eq!(manifest.shards().len(), 4);
+        assert!(manifest.shards().iter().all(|i| i.ids.len() == 1));
+        assert!(manifest.shards[0].len().abs_diff(file.len() / 4) <= 1);
+        check(&file, dir, &manifest);
+
+        let dir = "test_split_size";
+        let manifest = split_by_size(&file, dir, 500, IndexEncoding::EliasFano).unwrap();
+        assert!(manifest.shards().len() > 1);
+        for shard in manifest.shards().iter() {
+            let size: usize = shard
+                .ids
+                .iter()
+                .flat_map(|i| i.iter().map(|id| file.get_unchecked(id)))
+                .map(|i| i.len())
+                .sum();
+            assert!(size <= 500);
+        }
+        check(&file, dir, &manifest);
+
+        let dir = "test_split_by";
+        let manifest = split_by(&file, dir, 3, IndexEncoding::Plain, |_, data| {
+            data.len() % 3
+        })
+        .unwrap();
+        assert_eq!(manifest.shards().len(), 3);
+        check(&file, dir, &manifest);
+
+        // Interleaved entries are described by a single range per shard
+        let dir = "test_split_interleaved";
+        let manifest = split_by(&file, dir, 3, IndexEncoding::Plain, |id, _| id % 3).unwrap();
+        assert!(manifest.shards().iter().all(|i| i.ids.len() == 1));
+        assert_eq!(manifest.shards()[1].ids[0].step, 3);
+        check(&file, dir, &manifest);
+
+        // Shard files are removed on errors
+        let dir = "test_split_err";
+        assert!(split_by(&file, dir, 2, IndexEncoding::Plain, |id, _| id / 100).is_err());
+        assert_eq!(fs::read_dir(dir).unwrap().count(), 0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,4 +1,8 @@
-use crate::iter::IndexedAccessIter;
+use crate::{encoded_index::IndexEncoding, iter::IndexedAccessIter};
+use std::{
+    io::{Error, Write},
+    ops::{Bound, Range, RangeBounds},
+};
 
 #[cfg(feature = "typed")]
 use serde::{de::DeserializeOwned, Serialize};
@@ -23,13 +27,33 @@ pub trait IndexedAccess {
 
     /// Returns an iterator over all entries in the file
     #[inline]
-    fn iter(&self) -> IndexedAccessIter<Self>
+    fn iter(&self) -> IndexedAccessIter<'_, Self>
     where
         Self: Sized,
     {
         IndexedAccessIter::new(self)
     }
 
+    /// Returns an iterator over all entries with an ID within `range`
+    #[inline]
+    fn iter_range<R>(&self, range: R) -> IndexedAccessIter<'_, Self>
+    where
+        Self: Sized,
+        R: RangeBounds<usize>,
+    {
+        let start = match range.start_bound() {
+            Bound::Included(start) => *start,
+            Bound::Excluded(start) => start.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => end.saturating_add(1),
+            Bound::Excluded(end) => *end,
+            Bound::Unbounded => self.len(),
+        };
+        IndexedAccessIter::with_range(self, start..end)
+    }
+
     /// Returns the amount of items in the file
     fn len(&self) -> usize;
 
@@ -38,6 +62,62 @@ pub trait IndexedAccess {
     fn is_empty(&self) -> bool {
         self.len() == 0
     }
+
+    /// Returns the file as [`RawAccess`] if it stores all entries contiguously, which allows
+    /// copying them in bulk, eg. when merging files
+    #[inline]
+    fn as_raw(&self) -> Option<&dyn RawAccess> {
+        None
+    }
+}
+
+/// Trait for files storing all entries contiguously, which allows copying them in bulk
+pub trait RawAccess: IndexedAccess {
+    /// Returns the data of all entries
+    fn raw_data(&self) -> &[u8];
+
+    /// Returns the offset of the entry with the given ID within `raw_data`
+    fn raw_offset(&self, id: usize) -> Option<u32>;
+}
+
+/// Trait for files saved in the container format, which can carry additional sections like a
+/// [`BloomFilter`](crate::BloomFilter) saved using `BloomFilter::save_with`
+pub trait ContainerFile {
+    /// Writes the file in the container format followed by `sections`, each given by its
+    /// kind and content
+    fn write_with_sections<W: Write>(
+        &self,
+        w: W,
+        encoding: IndexEncoding,
+        sections: &[(u32, &[u8])],
+    ) -> Result<(), Error>;
+}
+
+/// Trait for indexes that map IDs of entries to the offset of their data
+pub trait OffsetIndex {
+    /// Returns the data offset of the entry with the given ID
+    fn offset(&self, id: usize) -> Option<u32>;
+
+    /// Returns the amount of entries in the index
+    fn len(&self) -> usize;
+
+    /// Returns true if the index is empty
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the range of the data between `id` and id+1 or `end` if `id`
+    /// is the last item
+    #[inline]
+    fn data_range(&self, id: usize, end: usize) -> Option<Range<usize>> {
+        let start = self.offset(id)? as usize;
+        let end = self.offset(id + 1).map(|i| i as usize).unwrap_or(end);
+        Some(start..end)
+    }
+
+    /// Returns the amount of heap memory used by the index in bytes
+    fn heap_size(&self) -> usize;
 }
 
 #[cfg(feature = "typed")]
@@ -77,7 +157,7 @@ pub trait TypedIndexedAccess: IndexedAccess {
 
     /// Returns an iterator over all entries in the file
     #[inline]
-    fn iter_typed<T>(&self) -> crate::typed_iter::TypedIndexedAccessIter<Self, T>
+    fn iter_typed<T>(&self) -> crate::typed_iter::TypedIndexedAccessIter<'_, Self, T>
     where
         Self: Sized,
         T: DeserializeOwned,
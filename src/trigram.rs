@@ -0,0 +1,443 @@
+use crate::{
+    encoded_index::IndexEncoding,
+    format::{invalid_data, section, Layout},
+    memory::MemFile,
+    traits::IndexedAccess,
+};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Error, Write},
+    ops::Range,
+    path::Path,
+};
+
+#[cfg(feature = "mapped")]
+use crate::map::MappedFile;
+
+/// Inverted index mapping each trigram (three consecutive bytes) to the IDs of all entries
+/// containing it. Answers substring queries with a list of candidate IDs, which can be
+/// verified against the indexed file using `search`.
+///
+/// The index is stored in its little endian encoding: the trigram count, the sorted
+/// trigrams, count + 1 offsets into the posting lists and the posting lists themselves.
+/// Thus it can be used directly from a mapped file.
+#[derive(Clone, Debug)]
+pub struct TrigramIndex<D = Vec<u8>> {
+    bytes: D,
+    count: usize,
+}
+
+impl TrigramIndex<Vec<u8>> {
+    /// Builds an index over all entries of `file`
+    pub fn build<F: IndexedAccess>(file: &F) -> Self {
+        let mut postings: HashMap<u32, Vec<u32>> = HashMap::new();
+        let mut trigrams = vec![];
+        for (id, entry) in file.iter().enumerate() {
+            trigrams.clear();
+            trigrams.extend(entry.windows(3).map(trigram));
+            trigrams.sort_unstable();
+            trigrams.dedup();
+            for t in trigrams.iter() {
+                postings.entry(*t).or_default().push(id as u32);
+            }
+        }
+
+        let mut keys: Vec<_> = postings.keys().copied().collect();
+        keys.sort_unstable();
+
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&(keys.len() as u32).to_le_bytes());
+        for t in keys.iter() {
+            bytes.extend_from_slice(&t.to_le_bytes());
+        }
+        let mut offset = 0u32;
+        for t in keys.iter() {
+            bytes.extend_from_slice(&offset.to_le_bytes());
+            offset += postings[t].len() as u32;
+        }
+        bytes.extend_from_slice(&offset.to_le_bytes());
+        for t in keys.iter() {
+            for id in postings[t].iter() {
+                bytes.extend_from_slice(&id.to_le_bytes());
+            }
+        }
+
+        Self {
+            bytes,
+            count: keys.len(),
+        }
+    }
+}
+
+impl<D: AsRef<[u8]>> TrigramIndex<D> {
+    /// Creates an index from its encoding. `entries` is the amount of entries in the indexed
+    /// file and used to validate the posting lists.
+    pub fn from_bytes(bytes: D, entries: usize) -> Result<Self, Error> {
+        let index = Self::view(bytes)?;
+        let buf = index.bytes.as_ref();
+
+        let postings = index.postings_start();
+        let posting_count = index.offset(index.count) as usize;
+        if buf.len() != postings + posting_count * 4 {
+            return Err(invalid_data("Invalid trigram index length"));
+        }
+
+        let sorted = (1..index.count).all(|i| index.trigram(i - 1) < index.trigram(i));
+        let offsets = (1..=index.count).all(|i| index.offset(i - 1) <= index.offset(i));
+        if !sorted || !offsets || (index.count == 0 && posting_count != 0) {
+            return Err(invalid_data("Invalid trigram index"));
+        }
+        if (0..posting_count).any(|i| read_u32(buf, postings + i * 4) as usize >= entries) {
+            return Err(invalid_data("Invalid ID in trigram index"));
+        }
+
+        Ok(index)
+    }
+
+    /// Returns the encoded index
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        self.bytes.as_ref()
+    }
+
+    /// Returns the IDs of all entries that contain every trigram of `query`, in ascending
+    /// order. Returns `None` if the query is shorter than three bytes, as such queries
+    /// can't be answered by the index.
+    pub fn candidates(&self, query: &[u8]) -> Option<Vec<usize>> {
+        if query.len() < 3 {
+            return None;
+        }
+
+        let mut trigrams: Vec<_> = query.windows(3).map(trigram).collect();
+        trigrams.sort_unstable();
+        trigrams.dedup();
+
+        let mut lists = vec![];
+        for t in trigrams {
+            let Some(list) = self.find(t) else {
+                return Some(vec![]);
+            };
+            lists.push(list);
+        }
+
+        // Intersect starting with the shortest list
+        lists.sort_unstable_by_key(|i| i.len());
+        let mut out: Vec<u32> = self.posting_list(lists[0].clone()).collect();
+        for list in &lists[1..] {
+            let mut other = self.posting_list(list.clone()).peekable();
+            out.retain(|id| {
+                while other.next_if(|i| i < id).is_some() {}
+                other.peek() == Some(id)
+            });
+        }
+
+        Some(out.into_iter().map(|i| i as usize).collect())
+    }
+
+    /// Returns the IDs of all entries of `file` that contain `query`, in ascending order.
+    /// `file` has to be the file the index has been built from. Queries shorter than three
+    /// bytes fall back to scanning all entries.
+    pub fn search<F: IndexedAccess>(&self, file: &F, query: &[u8]) -> Vec<usize> {
+        let contains = |id: &usize| file.get(*id).is_some_and(|i| contains(i, query));
+        match self.candidates(query) {
+            Some(candidates) => candidates.into_iter().filter(contains).collect(),
+            None => (0..file.len()).filter(contains).collect(),
+        }
+    }
+
+    /// Returns the amount of distinct trigrams
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Returns `true` if the index doesn't contain any trigram
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Creates an index from its encoding, only validating its header
+    fn view(bytes: D) -> Result<Self, Error> {
+        let buf = bytes.as_ref();
+        if buf.len() < 4 {
+            return Err(invalid_data("Invalid trigram index length"));
+        }
+        let count = read_u32(buf, 0) as usize;
+        if buf.len() < 4 + count * 8 + 4 {
+            return Err(invalid_data("Invalid trigram index length"));
+        }
+        Ok(Self { bytes, count })
+    }
+
+    /// Returns the range of the posting list of trigram `t` within the postings
+    #[inline]
+    fn find(&self, t: u32) -> Option<Range<usize>> {
+        let (mut low, mut high) = (0, self.count);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.trigram(mid).cmp(&t) {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => {
+                    return Some(self.offset(mid) as usize..self.offset(mid + 1) as usize)
+                }
+            }
+        }
+        None
+    }
+
+    #[inline]
+    fn posting_list(&self, range: Range<usize>) -> impl Iterator<Item = u32> + '_ {
+        let start = self.postings_start();
+        range.map(move |i| read_u32(self.bytes.as_ref(), start + i * 4))
+    }
+
+    #[inline]
+    fn trigram(&self, i: usize) -> u32 {
+        read_u32(self.bytes.as_ref(), 4 + i * 4)
+    }
+
+    #[inline]
+    fn offset(&self, i: usize) -> u32 {
+        read_u32(self.bytes.as_ref(), 4 + self.count * 4 + i * 4)
+    }
+
+    #[inline]
+    fn postings_start(&self) -> usize {
+        4 + self.count * 8 + 4
+    }
+}
+
+/// A [`MemFile`] with a [`TrigramIndex`] over its entries that gets saved together with the
+/// file, allowing substring search over a [`MappedTextFile`] without rebuilding the index.
+#[derive(Clone)]
+pub struct TextFile {
+    file: MemFile,
+    index: TrigramIndex,
+}
+
+impl TextFile {
+    /// Builds the trigram index over all entries of `file`
+    #[inline]
+    pub fn new(file: MemFile) -> Self {
+        let index = TrigramIndex::build(&file);
+        Self { file, index }
+    }
+
+    /// Returns the IDs of all entries that contain `query`, in ascending order
+    #[inline]
+    pub fn search(&self, query: &[u8]) -> Vec<usize> {
+        self.index.search(&self.file, query)
+    }
+
+    /// Returns the underlying file
+    #[inline]
+    pub fn file(&self) -> &MemFile {
+        &self.file
+    }
+
+    /// Returns the trigram index
+    #[inline]
+    pub fn index(&self) -> &TrigramIndex {
+        &self.index
+    }
+
+    /// Returns the underlying file
+    #[inline]
+    pub fn into_inner(self) -> MemFile {
+        self.file
+    }
+
+    /// Saves the file including its trigram index
+    pub fn save<P: AsRef<Path>>(&self, path: P, encoding: IndexEncoding) -> Result<(), Error> {
+        let out = BufWriter::new(File::create(path)?);
+        self.write_to(out, encoding)
+    }
+
+    /// Writes the file including its trigram index
+    #[inline]
+    pub fn write_to<W: Write>(&self, w: W, encoding: IndexEncoding) -> Result<(), Error> {
+        let sections = [(section::TRIGRAM, self.index.as_bytes())];
+        self.file.write_with_sections(w, encoding, &sections)
+    }
+
+    /// Loads a file saved with `TextFile::save`. If the file doesn't contain a trigram
+    /// index, eg. because it has been saved as regular `MemFile`, the index gets built.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let bytes = std::fs::read(path)?;
+        let layout = Layout::parse(&bytes)?;
+        let file = MemFile::from_layout(&bytes, &layout)?;
+
+        let Some(index) = layout.section(section::TRIGRAM) else {
+            return Ok(Self::new(file));
+        };
+        let index = TrigramIndex::from_bytes(bytes[index.range()].to_vec(), file.len())?;
+        Ok(Self { file, index })
+    }
+}
+
+impl IndexedAccess for TextFile {
+    #[inline]
+    fn get(&self, pos: usize) -> Option<&[u8]> {
+        self.file.get(pos)
+    }
+
+    #[inline]
+    fn get_unchecked(&self, pos: usize) -> &[u8] {
+        self.file.get_unchecked(pos)
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.file.len()
+    }
+}
+
+/// Read only [`TextFile`] backed by a [`MappedFile`]. The trigram index is used directly from
+/// the mapping.
+#[cfg(feature = "mapped")]
+pub struct MappedTextFile {
+    file: MappedFile,
+}
+
+#[cfg(feature = "mapped")]
+impl MappedTextFile {
+    /// Opens a file saved with `TextFile::save`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = MappedFile::open(path)?;
+        let index = file
+            .section(section::TRIGRAM)
+            .ok_or_else(|| invalid_data("Missing trigram index"))?;
+        TrigramIndex::from_bytes(index, file.len())?;
+        Ok(Self { file })
+    }
+
+    /// Returns the IDs of all entries that contain `query`, in ascending order
+    #[inline]
+    pub fn search(&self, query: &[u8]) -> Vec<usize> {
+        self.index().search(&self.file, query)
+    }
+
+    /// Returns the trigram index
+    #[inline]
+    pub fn index(&self) -> TrigramIndex<&[u8]> {
+        // Has been validated on open
+        TrigramIndex::view(self.file.section(section::TRIGRAM).unwrap()).unwrap()
+    }
+
+    /// Returns the underlying file
+    #[inline]
+    pub fn file(&self) -> &MappedFile {
+        &self.file
+    }
+}
+
+#[cfg(feature = "mapped")]
+impl IndexedAccess for MappedTextFile {
+    #[inline]
+    fn get(&self, pos: usize) -> Option<&[u8]> {
+        self.file.get(pos)
+    }
+
+    #[inline]
+    fn get_unchecked(&self, pos: usize) -> &[u8] {
+        self.file.get_unchecked(pos)
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.file.len()
+    }
+}
+
+#[inline]
+fn trigram(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]])
+}
+
+#[inline]
+fn read_u32(bytes: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap())
+}
+
+/// Returns `true` if `haystack` contains `needle`
+#[inline]
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    needle.is_empty() || haystack.windows(needle.len()).any(|i| i == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::read_to_string;
+
+    fn lines() -> MemFile {
+        let content = read_to_string("./testfiles/LICENSE").unwrap();
+        MemFile::from(content.lines())
+    }
+
+    fn scan(file: &MemFile, query: &[u8]) -> Vec<usize> {
+        (0..file.len())
+            .filter(|i| contains(file.get_unchecked(*i), query))
+            .collect()
+    }
+
+    #[test]
+    fn test_search() {
+        let file = TextFile::new(lines());
+        for query in [
+            "License",
+            "the Program",
+            "GNU",
+            "copyright holder",
+            "zzz",
+            "a",
+            "",
+        ] {
+            let query = query.as_bytes();
+            assert_eq!(file.search(query), scan(file.file(), query));
+        }
+
+        let candidates = file.index().candidates(b"Licens").unwrap();
+        assert!(candidates.len() >= file.search(b"Licens").len());
+        assert_eq!(file.index().candidates(b"Li"), None);
+    }
+
+    #[test]
+    fn test_save_load() {
+        let file = TextFile::new(lines());
+        file.save("test_trigram_save_load", IndexEncoding::BlockPacked)
+            .unwrap();
+
+        let loaded = TextFile::load("test_trigram_save_load").unwrap();
+        assert_eq!(loaded.index().as_bytes(), file.index().as_bytes());
+        assert_eq!(loaded.search(b"Program"), file.search(b"Program"));
+
+        #[cfg(feature = "mapped")]
+        {
+            let mapped = MappedTextFile::open("test_trigram_save_load").unwrap();
+            assert_eq!(mapped.search(b"Program"), file.search(b"Program"));
+            assert_eq!(mapped.search(b"not in there"), Vec::<usize>::new());
+        }
+
+        // Without trigram index
+        file.file()
+            .save("test_trigram_save_load", IndexEncoding::Plain)
+            .unwrap();
+        let loaded = TextFile::load("test_trigram_save_load").unwrap();
+        assert_eq!(loaded.index().as_bytes(), file.index().as_bytes());
+
+        #[cfg(feature = "mapped")]
+        assert!(MappedTextFile::open("test_trigram_save_load").is_err());
+
+        std::fs::remove_file("test_trigram_save_load").unwrap();
+
+        let mut bytes = file.index().as_bytes().to_vec();
+        assert!(TrigramIndex::from_bytes(&bytes[..], 10).is_err());
+        bytes.pop();
+        assert!(TrigramIndex::from_bytes(&bytes[..], file.file().len()).is_err());
+    }
+}
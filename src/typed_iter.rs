@@ -35,7 +35,7 @@ where
 {
     #[inline]
     fn len(&self) -> usize {
-        I::len(self.file)
+        self.end - self.start
     }
 }
 
@@ -48,6 +48,10 @@ where
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
+        if self.start >= self.end {
+            return None;
+        }
+
         let content = self
             .file
             .get_typed(self.start)
@@ -64,11 +68,10 @@ where
 {
     #[inline]
     fn next_back(&mut self) -> Option<Self::Item> {
-        if self.end == 0 {
+        if self.end <= self.start {
             return None;
         }
 
-        // we use 0 to indicate that the iterator is done
         let item = self
             .file
             .get_typed(self.end - 1)
@@ -108,7 +111,7 @@ mod tests {
             assert_eq!(inp[pos], data);
         }
 
-        for (pos, data) in idx.iter_typed().rev().enumerate() {
+        for (pos, data) in idx.iter_typed::<u32>().rev().enumerate() {
             let real_pos = inp.len() - pos - 1;
             assert_eq!(inp[real_pos], data);
         }
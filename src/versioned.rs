@@ -0,0 +1,346 @@
+use crate::{
+    encoded_index::IndexEncoding,
+    format::{invalid_data, section, write_u32s, write_u64s, ByteReader, Layout},
+    memory::MemFile,
+    traits::{IndexedAccess, IndexedAccessMut},
+};
+use std::{
+    fs::File,
+    io::{BufWriter, Error, Write},
+    ops::Range,
+    path::Path,
+};
+
+/// A file that keeps the previous values of replaced entries. Every insert and replace is a
+/// new commit and appends a new version of the entry instead of overwriting it, so older
+/// values can be read by version or by commit. `get` always returns the latest version.
+///
+/// All versions are stored as entries of a single [`MemFile`]. The amount of versions kept per
+/// entry can be limited; pruned versions can't be read anymore and their data gets removed by
+/// [`VersionedFile::compact`].
+#[derive(Clone, Default)]
+pub struct VersionedFile {
+    file: MemFile,
+    entries: Vec<History>,
+    commit: u64,
+    max_versions: Option<usize>,
+}
+
+/// All retained versions of an entry
+#[derive(Clone, Default)]
+pub(crate) struct History {
+    /// Number of the first retained version
+    first: u64,
+    /// Commit and ID in the underlying file of each retained version
+    versions: Vec<(u64, u32)>,
+}
+
+impl History {
+    /// Returns the ID of the version in the underlying file
+    #[inline]
+    fn slot(&self, version: u64) -> Option<usize> {
+        let pos = version.checked_sub(self.first)?;
+        Some(self.versions.get(pos as usize)?.1 as usize)
+    }
+
+    /// Returns the ID of the latest version that existed at `commit`
+    #[inline]
+    fn slot_as_of(&self, commit: u64) -> Option<usize> {
+        let pos = self.versions.partition_point(|i| i.0 <= commit);
+        Some(self.versions.get(pos.checked_sub(1)?)?.1 as usize)
+    }
+
+    #[inline]
+    fn prune(&mut self, max_versions: Option<usize>) {
+        if let Some(max) = max_versions {
+            let remove = self.versions.len().saturating_sub(max);
+            self.versions.drain(..remove);
+            self.first += remove as u64;
+        }
+    }
+}
+
+impl VersionedFile {
+    /// Creates a new file keeping all versions of each entry
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new file keeping at most `max_versions` versions of each entry
+    #[inline]
+    pub fn with_max_versions(max_versions: usize) -> Self {
+        Self {
+            max_versions: Some(max_versions.max(1)),
+            ..Self::default()
+        }
+    }
+
+    /// Sets the amount of versions kept per entry. `None` keeps all versions. Versions
+    /// exceeding the new limit get pruned.
+    pub fn set_max_versions(&mut self, max_versions: Option<usize>) {
+        self.max_versions = max_versions.map(|i| i.max(1));
+        for history in self.entries.iter_mut() {
+            history.prune(self.max_versions);
+        }
+    }
+
+    /// Returns the latest commit. Each insert and replace creates a new commit, starting at 1
+    #[inline]
+    pub fn commit(&self) -> u64 {
+        self.commit
+    }
+
+    /// Returns the given version of an entry. Versions are counted from 0, the version
+    /// inserted first.
+    #[inline]
+    pub fn get_at(&self, id: usize, version: u64) -> Option<&[u8]> {
+        let slot = self.entries.get(id)?.slot(version)?;
+        Some(self.file.get_unchecked(slot))
+    }
+
+    /// Returns the value an entry had at the given commit. Returns `None` if the entry
+    /// didn't exist yet or the version has been pruned.
+    #[inline]
+    pub fn get_as_of(&self, id: usize, commit: u64) -> Option<&[u8]> {
+        let slot = self.entries.get(id)?.slot_as_of(commit)?;
+        Some(self.file.get_unchecked(slot))
+    }
+
+    /// Returns the numbers of all retained versions of an entry
+    #[inline]
+    pub fn versions(&self, id: usize) -> Option<Range<u64>> {
+        let history = self.entries.get(id)?;
+        Some(history.first..history.first + history.versions.len() as u64)
+    }
+
+    /// Returns all retained versions of an entry as (version, commit, value), oldest first
+    pub fn history(&self, id: usize) -> Option<impl Iterator<Item = (u64, u64, &[u8])>> {
+        let history = self.entries.get(id)?;
+        let iter = history
+            .versions
+            .iter()
+            .enumerate()
+            .map(move |(pos, (commit, slot))| {
+                let value = self.file.get_unchecked(*slot as usize);
+                (history.first + pos as u64, *commit, value)
+            });
+        Some(iter)
+    }
+
+    /// Removes the data of pruned versions from the underlying file
+    pub fn compact(&mut self) {
+        let mut file = MemFile::new();
+        for history in self.entries.iter_mut() {
+            for (_, slot) in history.versions.iter_mut() {
+                *slot = file.insert(self.file.get_unchecked(*slot as usize)) as u32;
+            }
+        }
+        self.file = file;
+    }
+
+    /// Returns the underlying file containing all versions
+    #[inline]
+    pub fn file(&self) -> &MemFile {
+        &self.file
+    }
+
+    /// Saves the file including the history of all entries
+    pub fn save<P: AsRef<Path>>(&self, path: P, encoding: IndexEncoding) -> Result<(), Error> {
+        let out = BufWriter::new(File::create(path)?);
+        self.write_to(out, encoding)
+    }
+
+    /// Writes the file including the history of all entries
+    pub fn write_to<W: Write>(&self, w: W, encoding: IndexEncoding) -> Result<(), Error> {
+        let mut versions = vec![];
+        self.encode_versions(&mut versions)?;
+        self.file
+            .write_with_sections(w, encoding, &[(section::VERSIONS, &versions)])
+    }
+
+    /// Loads a file saved with `VersionedFile::save`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let bytes = std::fs::read(path)?;
+        let layout = Layout::parse(&bytes)?;
+        let versions = layout.required_section(section::VERSIONS)?;
+        let file = MemFile::from_layout(&bytes, &layout)?;
+
+        let mut new = Self {
+            file,
+            ..Self::default()
+        };
+        new.decode_versions(&bytes[versions.range()])?;
+        Ok(new)
+    }
+
+    /// Encodes the histories as u64 commit, u64 max versions (0 for unlimited), u64 entry
+    /// count, u64 version count, the first retained version and u32 version count of each
+    /// entry, followed by the u64 commit and u32 ID of each version.
+    fn encode_versions<W: Write>(&self, mut w: W) -> Result<(), Error> {
+        let total: usize = self.entries.iter().map(|i| i.versions.len()).sum();
+        let header = [
+            self.commit,
+            self.max_versions.unwrap_or(0) as u64,
+            self.entries.len() as u64,
+            total as u64,
+        ];
+        write_u64s(&mut w, &header)?;
+
+        let first: Vec<_> = self.entries.iter().map(|i| i.first).collect();
+        let lens: Vec<_> = self
+            .entries
+            .iter()
+            .map(|i| i.versions.len() as u32)
+            .collect();
+        write_u64s(&mut w, &first)?;
+        write_u32s(&mut w, &lens)?;
+
+        let versions = self.entries.iter().flat_map(|i| i.versions.iter());
+        let commits: Vec<_> = versions.clone().map(|i| i.0).collect();
+        let slots: Vec<_> = versions.map(|i| i.1).collect();
+        write_u64s(&mut w, &commits)?;
+        write_u32s(&mut w, &slots)
+    }
+
+    fn decode_versions(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.entries = decode_versions(bytes, self.file.len())?;
+        let mut r = ByteReader::new(bytes);
+        self.commit = r.u64()?;
+        self.max_versions = Some(r.u64()? as usize).filter(|i| *i > 0);
+        Ok(())
+    }
+}
+
+/// Decodes and validates the histories of a `VersionedFile` with `slots` stored versions
+pub(crate) fn decode_versions(bytes: &[u8], slots: usize) -> Result<Vec<History>, Error> {
+    let mut r = ByteReader::new(bytes);
+    let commit = r.u64()?;
+    r.u64()?;
+    let entries = r.u64()? as usize;
+    let total = r.u64()? as usize;
+
+    let first = r.u64_vec(entries)?;
+    let lens = r.u32_vec(entries)?;
+    let commits = r.u64_vec(total)?;
+    let ids = r.u32_vec(total)?;
+    if lens.iter().map(|i| *i as usize).sum::<usize>() != total
+        || lens.contains(&0)
+        || ids.iter().any(|i| *i as usize >= slots)
+        || commits.iter().any(|i| *i > commit)
+    {
+        return Err(invalid_data("Invalid version history"));
+    }
+
+    let mut versions = commits.into_iter().zip(ids);
+    let histories = first
+        .into_iter()
+        .zip(lens)
+        .map(|(first, len)| History {
+            first,
+            versions: versions.by_ref().take(len as usize).collect(),
+        })
+        .collect::<Vec<_>>();
+    if histories
+        .iter()
+        .any(|i| i.versions.windows(2).any(|i| i[0].0 >= i[1].0))
+    {
+        return Err(invalid_data("Unordered version history"));
+    }
+    Ok(histories)
+}
+
+impl IndexedAccessMut for VersionedFile {
+    /// Inserts a new entry as version 0 in a new commit and returns its ID
+    #[inline]
+    fn insert(&mut self, data: &[u8]) -> usize {
+        self.commit += 1;
+        let slot = self.file.insert(data) as u32;
+        self.entries.push(History {
+            first: 0,
+            versions: vec![(self.commit, slot)],
+        });
+        self.entries.len() - 1
+    }
+
+    /// Adds a new version of an entry in a new commit
+    fn replace(&mut self, pos: usize, data: &[u8]) -> Option<()> {
+        let history = self.entries.get_mut(pos)?;
+        self.commit += 1;
+        let slot = self.file.insert(data) as u32;
+        history.versions.push((self.commit, slot));
+        history.prune(self.max_versions);
+        Some(())
+    }
+}
+
+impl IndexedAccess for VersionedFile {
+    /// Returns the latest version of an entry
+    #[inline]
+    fn get(&self, pos: usize) -> Option<&[u8]> {
+        let slot = self.entries.get(pos)?.versions.last()?.1;
+        Some(self.file.get_unchecked(slot as usize))
+    }
+
+    #[inline]
+    fn get_unchecked(&self, pos: usize) -> &[u8] {
+        self.get(pos).unwrap()
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_versions() {
+        let mut file = VersionedFile::new();
+        let a = file.insert(b"a0");
+        let b = file.insert(b"b0");
+        file.replace(a, b"a1").unwrap();
+        let commit = file.commit();
+        file.replace(a, b"a2").unwrap();
+        assert_eq!(file.replace(5, b"x"), None);
+
+        assert_eq!(file.get(a), Some(&b"a2"[..]));
+        assert_eq!(file.get(b), Some(&b"b0"[..]));
+        assert_eq!(file.get_at(a, 0), Some(&b"a0"[..]));
+        assert_eq!(file.get_at(a, 3), None);
+        assert_eq!(file.get_as_of(a, commit), Some(&b"a1"[..]));
+        assert_eq!(file.get_as_of(b, 1), None);
+        assert_eq!(file.get_as_of(b, commit), Some(&b"b0"[..]));
+        let history: Vec<_> = file.history(a).unwrap().map(|i| (i.0, i.1)).collect();
+        assert_eq!(history, [(0, 1), (1, 3), (2, 4)]);
+
+        let mut buf = vec![];
+        file.write_to(&mut buf, IndexEncoding::Plain).unwrap();
+        let info = crate::info::verify(&buf).unwrap();
+        assert_eq!(info.sections[2].name, "versions");
+
+        file.set_max_versions(Some(2));
+        assert_eq!(file.versions(a), Some(1..3));
+        assert_eq!(file.get_at(a, 0), None);
+        assert_eq!(file.get_as_of(a, 1), None);
+        file.replace(a, b"a3").unwrap();
+        assert_eq!(file.versions(a), Some(2..4));
+
+        file.compact();
+        assert_eq!(file.file().len(), 3);
+        assert_eq!(file.get_at(a, 2), Some(&b"a2"[..]));
+        assert!(file.iter().eq([&b"a3"[..], b"b0"]));
+
+        file.save("test_versioned_file", IndexEncoding::EliasFano)
+            .unwrap();
+        let loaded = VersionedFile::load("test_versioned_file").unwrap();
+        assert_eq!(loaded.commit(), file.commit());
+        assert_eq!(loaded.versions(a), Some(2..4));
+        assert_eq!(loaded.get_as_of(a, commit + 1), Some(&b"a2"[..]));
+        assert!(loaded.iter().eq(file.iter()));
+        std::fs::remove_file("test_versioned_file").unwrap();
+    }
+}
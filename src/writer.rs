@@ -0,0 +1,151 @@
+use crate::{
+    encoded_index::{EncodedIndex, IndexEncoding},
+    format::{section, ContainerWriter},
+    memory::extend_offsets,
+    traits::IndexedAccessMut,
+};
+use std::{
+    fs::File,
+    io::{BufWriter, Error, ErrorKind, Write},
+    path::Path,
+};
+
+/// Writes a file in the container format without keeping its data in memory. Entries are
+/// written directly to the underlying writer, only their offsets are kept until the index
+/// gets written in `finish`. The result can be loaded as `MemFile` or opened as `MappedFile`.
+pub struct FileWriter<W: Write> {
+    writer: ContainerWriter<W>,
+    offsets: Vec<u32>,
+    data_len: u64,
+    encoding: IndexEncoding,
+}
+
+impl FileWriter<BufWriter<File>> {
+    /// Creates a new file at `path`
+    #[inline]
+    pub fn create<P: AsRef<Path>>(path: P, encoding: IndexEncoding) -> Result<Self, Error> {
+        Self::new(BufWriter::new(File::create(path)?), encoding)
+    }
+}
+
+impl<W: Write> FileWriter<W> {
+    /// Creates a new FileWriter that writes into `w` using the given encoding for the index
+    pub fn new(w: W, encoding: IndexEncoding) -> Result<Self, Error> {
+        let mut writer = ContainerWriter::new(w)?;
+        writer.begin_section(section::DATA, 0)?;
+        Ok(Self {
+            writer,
+            offsets: vec![],
+            data_len: 0,
+            encoding,
+        })
+    }
+
+    /// Writes a new entry and returns its ID
+    pub fn insert(&mut self, data: &[u8]) -> Result<usize, Error> {
+        let offset = u32::try_from(self.data_len)
+            .map_err(|_| Error::new(ErrorKind::InvalidInput, "File too large"))?;
+        self.writer.write_all(data)?;
+        self.data_len += data.len() as u64;
+        self.offsets.push(offset);
+        Ok(self.offsets.len() - 1)
+    }
+
+    /// Writes `data` containing multiple entries at once. `offsets` are the start offsets of
+    /// the entries within `data` and have to be monotonic.
+    pub(crate) fn insert_raw<I>(&mut self, data: &[u8], offsets: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = u32>,
+    {
+        let base = self.data_len as u32;
+        if self.data_len + data.len() as u64 > u32::MAX as u64 {
+            return Err(Error::new(ErrorKind::InvalidInput, "File too large"));
+        }
+        extend_offsets(&mut self.offsets, base, data.len(), offsets)?;
+        self.writer.write_all(data)?;
+        self.data_len += data.len() as u64;
+        Ok(())
+    }
+
+    /// Returns the amount of entries written so far
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Returns `true` if no entry has been written yet
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Writes the index and returns the underlying writer
+    #[inline]
+    pub fn finish(self) -> Result<W, Error> {
+        self.finish_with_sections(&[])
+    }
+
+    /// Writes the index followed by additional sections and returns the underlying writer
+    pub(crate) fn finish_with_sections(mut self, sections: &[(u32, &[u8])]) -> Result<W, Error> {
+        self.writer.end_section();
+
+        EncodedIndex::write_section(&mut self.writer, &self.offsets, self.encoding)?;
+        for (kind, data) in sections {
+            self.writer.write_section(*kind, 0, data)?;
+        }
+
+        self.writer.finish(self.offsets.len())
+    }
+}
+
+/// Destination entries can be written into, eg. a [`MemFile`](crate::MemFile) or a
+/// [`FileWriter`]
+pub trait Sink {
+    /// Adds a new entry
+    fn push(&mut self, data: &[u8]) -> Result<(), Error>;
+}
+
+impl<T: IndexedAccessMut> Sink for T {
+    #[inline]
+    fn push(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.insert(data);
+        Ok(())
+    }
+}
+
+impl<W: Write> Sink for FileWriter<W> {
+    #[inline]
+    fn push(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.insert(data)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{traits::IndexedAccess, MemFile};
+    use std::fs::read_to_string;
+
+    #[test]
+    fn test_writer() {
+        let content = read_to_string("./testfiles/LICENSE").unwrap();
+        let exp = MemFile::from(content.split_whitespace());
+
+        for encoding in [IndexEncoding::Plain, IndexEncoding::EliasFano] {
+            let mut writer = FileWriter::new(vec![], encoding).unwrap();
+            for (id, word) in content.split_whitespace().enumerate() {
+                assert_eq!(writer.insert(word.as_bytes()).unwrap(), id);
+            }
+            assert_eq!(writer.len(), exp.len());
+
+            let mut buf = vec![];
+            exp.write_to(&mut buf, encoding).unwrap();
+            assert_eq!(writer.finish().unwrap(), buf);
+        }
+
+        let empty = FileWriter::new(vec![], IndexEncoding::Plain).unwrap();
+        let file = MemFile::from_bytes(&empty.finish().unwrap()).unwrap();
+        assert!(file.is_empty());
+    }
+}